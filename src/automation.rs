@@ -0,0 +1,288 @@
+//! Parameters that change over time, following breakpoints or an LFO.
+//!
+//! On the command line an automation is written as:
+//! - a number, for a constant,
+//! - `time:value` breakpoints separated by commas, like `0:0.1,2.5s:0.9`, with times in seconds,
+//! - `@file.csv`, a file of `time,value` lines, possibly starting with a header,
+//! - `lfo:shape:frequency:low:high`, with `sine`, `triangle`, `saw` or `square` as shape.
+
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::processor::Processor;
+use crate::types::AudioMetadata;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Automation {
+    Constant(f64),
+    /// Times in seconds and the value at that time, sorted by time. Values are interpolated
+    /// linearly in between and held before the first and after the last.
+    Breakpoints(Vec<(f64, f64)>),
+    Lfo(Lfo),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lfo {
+    pub shape: Shape,
+    /// In Hz.
+    pub frequency: f64,
+    /// Value at the bottom of the wave, where it starts.
+    pub low: f64,
+    /// Value at the top of the wave.
+    pub high: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Sine,
+    Triangle,
+    Saw,
+    Square,
+}
+
+impl Shape {
+    fn name(self) -> &'static str {
+        match self {
+            Shape::Sine => "sine",
+            Shape::Triangle => "triangle",
+            Shape::Saw => "saw",
+            Shape::Square => "square",
+        }
+    }
+}
+
+impl FromStr for Shape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sine" => Ok(Shape::Sine),
+            "triangle" => Ok(Shape::Triangle),
+            "saw" => Ok(Shape::Saw),
+            "square" => Ok(Shape::Square),
+            _ => Err(format!(
+                "unknown LFO shape {}, it is one of sine, triangle, saw, square",
+                s
+            )),
+        }
+    }
+}
+
+impl Lfo {
+    /// Position in the wave at `time`, from 0 at `low` to 1 at `high`.
+    fn wave(&self, time: f64) -> f64 {
+        let phase = (time * self.frequency).rem_euclid(1.);
+        match self.shape {
+            Shape::Sine => 0.5 - 0.5 * (2. * PI * phase).cos(),
+            Shape::Triangle => 1. - (2. * phase - 1.).abs(),
+            Shape::Saw => phase,
+            Shape::Square => {
+                if phase < 0.5 {
+                    0.
+                } else {
+                    1.
+                }
+            }
+        }
+    }
+}
+
+impl Automation {
+    /// Value at `time`, in seconds.
+    pub fn at(&self, time: f64) -> f64 {
+        match self {
+            Automation::Constant(x) => *x,
+            Automation::Breakpoints(points) => {
+                let i = points.partition_point(|&(t, _)| t <= time);
+                match (i.checked_sub(1).map(|i| points[i]), points.get(i)) {
+                    (Some((t0, v0)), Some(&(t1, v1))) => v0 + (v1 - v0) * (time - t0) / (t1 - t0),
+                    (Some((_, v)), None) | (None, Some(&(_, v))) => v,
+                    (None, None) => 0.,
+                }
+            }
+            Automation::Lfo(lfo) => lfo.low + (lfo.high - lfo.low) * lfo.wave(time),
+        }
+    }
+
+    /// Lowest and highest values it takes.
+    pub fn bounds(&self) -> (f64, f64) {
+        match self {
+            Automation::Constant(x) => (*x, *x),
+            Automation::Breakpoints(points) => points
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &(_, v)| {
+                    (min.min(v), max.max(v))
+                }),
+            Automation::Lfo(lfo) => (lfo.low.min(lfo.high), lfo.low.max(lfo.high)),
+        }
+    }
+}
+
+fn parse_number(s: &str, what: &str) -> Result<f64, String> {
+    s.trim()
+        .parse()
+        .map_err(|e| format!("{} {}: {}", what, s.trim(), e))
+}
+
+/// Parses `time,value` lines, whose first line may be a header.
+fn parse_csv(text: &str) -> Result<Vec<(f64, f64)>, String> {
+    let mut points = Vec::new();
+    let mut first = true;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let point = match line.split_once(',') {
+            Some((time, value)) => parse_point(time, value),
+            None => Err(String::from("expected time,value")),
+        };
+        match point {
+            Ok(point) => points.push(point),
+            Err(_) if first => {}
+            Err(e) => return Err(format!("line {}: {}", i + 1, e)),
+        }
+        first = false;
+    }
+    Ok(points)
+}
+
+/// Parses a breakpoint, whose time may end with an `s`.
+fn parse_point(time: &str, value: &str) -> Result<(f64, f64), String> {
+    Ok((
+        parse_number(time.trim().trim_end_matches('s'), "time")?,
+        parse_number(value, "value")?,
+    ))
+}
+
+impl FromStr for Automation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(x) = s.parse() {
+            return Ok(Automation::Constant(x));
+        }
+        if let Some(lfo) = s.strip_prefix("lfo:") {
+            return match lfo.split(':').collect::<Vec<_>>()[..] {
+                [shape, frequency, low, high] => {
+                    let frequency = parse_number(frequency, "LFO frequency")?;
+                    if frequency < 0. {
+                        return Err(format!("LFO frequency {} is negative", frequency));
+                    }
+                    Ok(Automation::Lfo(Lfo {
+                        shape: shape.parse()?,
+                        frequency,
+                        low: parse_number(low, "LFO low")?,
+                        high: parse_number(high, "LFO high")?,
+                    }))
+                }
+                _ => Err(format!("expected lfo:shape:frequency:low:high, got {}", s)),
+            };
+        }
+
+        let points = match s.strip_prefix('@') {
+            Some(path) => {
+                parse_csv(&std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?)
+                    .map_err(|e| format!("{}: {}", path, e))?
+            }
+            None => s
+                .split(',')
+                .map(|point| match point.split_once(':') {
+                    Some((time, value)) => parse_point(time, value),
+                    None => Err(format!("expected time:value, got {}", point)),
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        if points.is_empty() {
+            return Err(format!("{} has no breakpoints", s));
+        }
+        if points.windows(2).any(|pair| pair[1].0 < pair[0].0) {
+            return Err(format!("the breakpoints of {} aren't in time order", s));
+        }
+        Ok(Automation::Breakpoints(points))
+    }
+}
+
+/// Written so that parsing gives it back, breakpoints from a file included.
+impl fmt::Display for Automation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Automation::Constant(x) => write!(f, "{}", x),
+            Automation::Breakpoints(points) => {
+                let points: Vec<String> = points
+                    .iter()
+                    .map(|(time, value)| format!("{}:{}", time, value))
+                    .collect();
+                write!(f, "{}", points.join(","))
+            }
+            Automation::Lfo(lfo) => write!(
+                f,
+                "lfo:{}:{}:{}:{}",
+                lfo.shape.name(),
+                lfo.frequency,
+                lfo.low,
+                lfo.high
+            ),
+        }
+    }
+}
+
+/// Gives a processor the current value of one of its parameters.
+pub type Setter<P> = fn(&mut P, f32);
+
+/// Processor whose parameters follow automations, set before every frame.
+pub struct Automated<P> {
+    processor: P,
+    controls: Vec<(Automation, Setter<P>)>,
+    channels: usize,
+    sample_rate: f64,
+    /// Frames processed so far.
+    position: usize,
+}
+
+impl<P: Processor> Automated<P> {
+    pub fn new(processor: P, metadata: &AudioMetadata) -> Self {
+        Self {
+            processor,
+            controls: Vec::new(),
+            channels: usize::from(metadata.channels),
+            sample_rate: f64::from(metadata.sample_rate),
+            position: 0,
+        }
+    }
+
+    /// Has `set` give the processor the value of `automation`, once and for all if it is a
+    /// constant.
+    pub fn control(mut self, automation: Automation, set: Setter<P>) -> Self {
+        match automation {
+            Automation::Constant(x) => set(&mut self.processor, x as f32),
+            automation => self.controls.push((automation, set)),
+        }
+        self
+    }
+}
+
+impl<P: Processor> Processor for Automated<P> {
+    fn process(&mut self, block: &mut [f32]) {
+        if self.controls.is_empty() {
+            return self.processor.process(block);
+        }
+        for frame in block.chunks_exact_mut(self.channels) {
+            let time = self.position as f64 / self.sample_rate;
+            for (automation, set) in &self.controls {
+                set(&mut self.processor, automation.at(time) as f32);
+            }
+            self.processor.process(frame);
+            self.position += 1;
+        }
+    }
+
+    fn lookahead(&self) -> usize {
+        self.processor.lookahead()
+    }
+
+    fn latency(&self) -> usize {
+        self.processor.latency()
+    }
+}
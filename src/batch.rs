@@ -0,0 +1,243 @@
+//! Running one chain over many files: finding them, naming the results and sharing the work
+//! between threads.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::automation::Automation;
+use crate::combine::Combine;
+use crate::effect::{Invocation, Value};
+use crate::graph::Node;
+
+/// Paths matching `pattern`, where `*`, `?` and `[...]` can be used in any component, sorted.
+///
+/// A pattern without any of them is returned as is, whether the file exists or not.
+pub fn glob(pattern: &str) -> io::Result<Vec<PathBuf>> {
+    if !pattern.contains(['*', '?', '[']) {
+        return Ok(vec![PathBuf::from(pattern)]);
+    }
+
+    let mut paths = vec![PathBuf::new()];
+    for component in Path::new(pattern).components() {
+        let component = component.as_os_str();
+        let text = component.to_string_lossy();
+        if !text.contains(['*', '?', '[']) {
+            for path in &mut paths {
+                path.push(component);
+            }
+            continue;
+        }
+        let pattern: Vec<char> = text.chars().collect();
+        let mut matched = Vec::new();
+        for path in &paths {
+            let dir = if path.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                path.as_path()
+            };
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                // a component matched a file, or a directory can't be listed
+                Err(_) => continue,
+            };
+            for entry in entries {
+                let name = entry?.file_name();
+                let chars: Vec<char> = name.to_string_lossy().chars().collect();
+                // like shells, hidden files have to be asked for explicitly
+                let hidden = chars.first() == Some(&'.') && pattern.first() != Some(&'.');
+                if !hidden && matches(&pattern, &chars) {
+                    matched.push(path.join(name));
+                }
+            }
+        }
+        paths = matched;
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Whether `name` matches the glob `pattern`.
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|i| matches(&pattern[1..], &name[i..])),
+        Some('?') => !name.is_empty() && matches(&pattern[1..], &name[1..]),
+        Some('[') => match (pattern.iter().position(|&c| c == ']'), name.first()) {
+            (Some(end), Some(&c)) if end > 1 => {
+                let set = &pattern[1..end];
+                let (negated, set) = match set.first() {
+                    Some('!') | Some('^') => (true, &set[1..]),
+                    _ => (false, set),
+                };
+                let mut found = false;
+                let mut i = 0;
+                while i < set.len() {
+                    if i + 2 < set.len() && set[i + 1] == '-' {
+                        found |= set[i] <= c && c <= set[i + 2];
+                        i += 3;
+                    } else {
+                        found |= set[i] == c;
+                        i += 1;
+                    }
+                }
+                found != negated && matches(&pattern[end + 1..], &name[1..])
+            }
+            // an unclosed bracket is taken literally
+            (None, Some('[')) => matches(&pattern[1..], &name[1..]),
+            _ => false,
+        },
+        Some(&c) => name.first() == Some(&c) && matches(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Names a chain the way fzf_screech did, like `softclipped_0.4_3xfolded`.
+pub fn chain_name(chain: &[Invocation]) -> String {
+    if chain.is_empty() {
+        return String::from("copy");
+    }
+    let names: Vec<String> = chain
+        .iter()
+        .map(|invocation| {
+            let name = invocation.effect.name();
+            let mut part = if invocation.iterations != 1 {
+                format!("{}x", invocation.iterations)
+            } else {
+                String::new()
+            };
+            part += name;
+            part += if name.ends_with('e') { "d" } else { "ed" };
+            for value in invocation.args.values() {
+                part += "_";
+                part += &value_name(value);
+            }
+            if let Some(mix) = &invocation.mix {
+                part += &format!("_mixed_{}", value_name(mix));
+            }
+            part
+        })
+        .collect();
+    names.join("_")
+}
+
+/// `value` as it appears in a name, breakpoints, which can come from a CSV file of any length,
+/// being left out for the name to fit in a file name.
+fn value_name(value: &Value) -> String {
+    match value {
+        Value::Automation(Automation::Breakpoints(_)) => String::from("auto"),
+        value => value.to_string(),
+    }
+}
+
+/// Like `chain_name`, with the branches spelled out the way they are on the command line, like
+/// `split_branch_0.5_folded_branch_1_copy_sum`.
+pub fn graph_name(nodes: &[Node<Invocation>]) -> String {
+    if nodes.is_empty() {
+        return chain_name(&[]);
+    }
+    let parts: Vec<String> = nodes
+        .iter()
+        .map(|node| match node {
+            Node::Effect(invocation) => chain_name(std::slice::from_ref(invocation)),
+            Node::Split(branches) => {
+                let branches: Vec<String> = branches
+                    .iter()
+                    .map(|(gain, chain)| format!("branch_{}_{}", gain, graph_name(chain)))
+                    .collect();
+                format!("split_{}_sum", branches.join("_"))
+            }
+            Node::Channels(branches) => {
+                let branches: Vec<String> = branches
+                    .iter()
+                    .map(|(channel, chain)| format!("channel_{}_{}", channel, graph_name(chain)))
+                    .collect();
+                format!("channels_{}_merge", branches.join("_"))
+            }
+            Node::Input(name, combine) => match combine {
+                Combine::Mix(gain) => format!("mixed_{}_{}", name, gain),
+                Combine::Concat => format!("concatenated_{}", name),
+                Combine::Crossfade(fade) => format!("crossfaded_{}_{}", name, fade),
+                Combine::Ring => format!("ringmodulated_{}", name),
+                Combine::Duck { depth, release } => {
+                    format!("ducked_{}_{}_{}", name, depth, release)
+                }
+            },
+        })
+        .collect();
+    parts.join("_")
+}
+
+/// Fills in `{dir}`, `{stem}` and `{chain}` in `template` for `input`, `chain` being the name of
+/// the chain.
+pub fn output_path(template: &str, input: &Path, chain: &str) -> Result<String, String> {
+    let dir = match input.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy(),
+        _ => ".".into(),
+    };
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+
+    let mut output = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        output += &rest[..start];
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed {{ in {}", template))?;
+        output += &match &rest[start + 1..start + end] {
+            "dir" => dir.to_string(),
+            "stem" => stem.to_string(),
+            "chain" => chain.to_string(),
+            other => return Err(format!("unknown placeholder {{{}}} in {}", other, template)),
+        };
+        rest = &rest[start + end + 1..];
+    }
+    Ok(output + rest)
+}
+
+/// Adds the `label` of a sweep's variant to `path`, before its extension.
+pub fn variant_path(path: &str, label: &str) -> String {
+    if label.is_empty() {
+        return path.to_string();
+    }
+    let path = Path::new(path);
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push("_");
+    name.push(label);
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Calls `f` on every item from `jobs` threads, returning the results in the items' order.
+pub fn run_parallel<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new((0..items.len()).map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= items.len() {
+                    break;
+                }
+                let result = f(&items[i]);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every item is processed"))
+        .collect()
+}
@@ -0,0 +1,170 @@
+//! Bringing extra inputs into a chain.
+
+use std::sync::Arc;
+
+use crate::effect::Stage;
+use crate::processor::Processor;
+use crate::region::Time;
+use crate::resample::resample;
+use crate::types::{AudioBuffer, AudioMetadata};
+
+/// Extra input, which the chain refers to by name.
+#[derive(Clone)]
+pub struct Input {
+    pub name: String,
+    pub audio: Arc<AudioBuffer>,
+}
+
+/// Way of combining the audio going through a chain with an extra input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Combine {
+    /// Adds the input, scaled by a gain.
+    Mix(f32),
+    /// Appends the input.
+    Concat,
+    /// Appends the input, overlapping the end of the audio by a fade of that length.
+    Crossfade(Time),
+    /// Multiplies the audio by the input.
+    Ring,
+    /// Turns the audio down as the input gets louder, by up to `depth`, and back up over
+    /// `release` when it gets quieter.
+    Duck { depth: f32, release: Time },
+}
+
+impl Combine {
+    /// Stage combining audio described by `metadata` with `input`, which must have the same
+    /// channels unless it only drives `Duck`. It is resampled to the audio's sample rate.
+    pub fn stage(
+        &self,
+        input: Arc<AudioBuffer>,
+        metadata: &AudioMetadata,
+    ) -> Result<Stage, String> {
+        let input = if input.metadata.sample_rate == metadata.sample_rate {
+            input
+        } else {
+            Arc::new(resample((*input).clone(), metadata.sample_rate))
+        };
+        let duck = matches!(self, Combine::Duck { .. });
+        if !duck && input.metadata.channels != metadata.channels {
+            return Err(format!(
+                "it has {} channels instead of {}",
+                input.metadata.channels, metadata.channels
+            ));
+        }
+
+        Ok(match *self {
+            Combine::Mix(gain) => Stage::stream(Add {
+                input,
+                gain,
+                position: 0,
+            }),
+            Combine::Ring => Stage::stream(Ring { input, position: 0 }),
+            Combine::Duck { depth, release } => {
+                let release = release.frames(metadata.sample_rate) as f32;
+                Stage::stream(Duck {
+                    depth,
+                    decay: if release > 0. {
+                        (-1. / release).exp()
+                    } else {
+                        0.
+                    },
+                    envelope: 0.,
+                    input,
+                    channels: usize::from(metadata.channels),
+                    position: 0,
+                })
+            }
+            Combine::Concat => Stage::whole(move |mut audio: AudioBuffer| {
+                audio.data.extend_from_slice(&input.data);
+                audio
+            }),
+            Combine::Crossfade(fade) => Stage::whole(move |audio| crossfade(audio, &input, fade)),
+        })
+    }
+}
+
+/// Streaming version of `Combine::Mix`, the input being cut or padded with silence to the
+/// length of the audio.
+struct Add {
+    input: Arc<AudioBuffer>,
+    gain: f32,
+    /// Samples processed so far.
+    position: usize,
+}
+
+impl Processor for Add {
+    fn process(&mut self, block: &mut [f32]) {
+        for s in block {
+            *s += self.gain * self.input.data.get(self.position).copied().unwrap_or(0.);
+            self.position += 1;
+        }
+    }
+}
+
+/// Streaming version of `Combine::Ring`, silent past the end of the input.
+struct Ring {
+    input: Arc<AudioBuffer>,
+    /// Samples processed so far.
+    position: usize,
+}
+
+impl Processor for Ring {
+    fn process(&mut self, block: &mut [f32]) {
+        for s in block {
+            *s *= self.input.data.get(self.position).copied().unwrap_or(0.);
+            self.position += 1;
+        }
+    }
+}
+
+/// Streaming version of `Combine::Duck`, following the peak of the input across its channels.
+struct Duck {
+    depth: f32,
+    /// How much of the envelope is left after a frame.
+    decay: f32,
+    envelope: f32,
+    input: Arc<AudioBuffer>,
+    /// Channels of the audio, the input can have others.
+    channels: usize,
+    /// Frames processed so far.
+    position: usize,
+}
+
+impl Processor for Duck {
+    fn process(&mut self, block: &mut [f32]) {
+        let input_channels = usize::from(self.input.metadata.channels);
+        for frame in block.chunks_exact_mut(self.channels) {
+            let start = self.position * input_channels;
+            let peak = self
+                .input
+                .data
+                .get(start..start + input_channels)
+                .map_or(0., |frame| {
+                    frame.iter().fold(0., |peak: f32, s| peak.max(s.abs()))
+                });
+            self.envelope = peak.max(self.envelope * self.decay);
+            let gain = 1. - self.depth * self.envelope.min(1.);
+            for s in frame {
+                *s *= gain;
+            }
+            self.position += 1;
+        }
+    }
+}
+
+/// `audio` followed by `input`, overlapping by `fade`, shortened to fit both of them.
+fn crossfade(mut audio: AudioBuffer, input: &AudioBuffer, fade: Time) -> AudioBuffer {
+    let channels = usize::from(audio.metadata.channels);
+    let frames = audio.data.len() / channels;
+    let fade = fade
+        .frames(audio.metadata.sample_rate)
+        .min(frames)
+        .min(input.data.len() / channels);
+    let start = (frames - fade) * channels;
+    for (i, (s, next)) in audio.data[start..].iter_mut().zip(&input.data).enumerate() {
+        let gain = ((i / channels) as f32 + 0.5) / fade as f32;
+        *s = *s * (1. - gain) + next * gain;
+    }
+    audio.data.extend_from_slice(&input.data[fade * channels..]);
+    audio
+}
@@ -1,40 +1,207 @@
-use crate::types::AudioBuffer;
+use crate::automation::Automated;
+use crate::effect::{Args, Effect, Param, Stage};
+use crate::processor::Processor;
+use crate::types::{AudioBuffer, AudioMetadata};
 
-pub fn decimate(mut audio: AudioBuffer, depth: f32) -> AudioBuffer {
-    for s in &mut audio.data {
-        *s = (*s * depth).round() / depth;
+pub struct Decimate(pub f32);
+
+impl Processor for Decimate {
+    fn process(&mut self, block: &mut [f32]) {
+        let depth = self.0;
+        for s in block {
+            *s = (*s * depth).round() / depth;
+        }
+    }
+}
+
+pub struct Fold;
+
+impl Processor for Fold {
+    fn process(&mut self, block: &mut [f32]) {
+        for s in block {
+            *s = s.sin();
+        }
+    }
+}
+
+pub struct HardClip(pub f32);
+
+impl Processor for HardClip {
+    fn process(&mut self, block: &mut [f32]) {
+        let thresh = self.0;
+        for s in block {
+            *s = if s.abs() > thresh {
+                thresh * s.signum()
+            } else {
+                *s
+            }
+        }
+    }
+}
+
+pub struct SoftClip(pub f32);
+
+impl Processor for SoftClip {
+    fn process(&mut self, block: &mut [f32]) {
+        let amount = self.0;
+        for s in block {
+            *s -= amount * s.powi(3) / 3.;
+        }
+    }
+}
+
+pub struct Tense(pub f32);
+
+impl Processor for Tense {
+    fn process(&mut self, block: &mut [f32]) {
+        let tension = self.0;
+        for s in block {
+            *s = s.signum() * (1. - (1. - s.abs()).powf(tension));
+        }
     }
+}
+
+pub fn decimate(mut audio: AudioBuffer, depth: f32) -> AudioBuffer {
+    Decimate(depth).process(&mut audio.data);
     audio
 }
 
 pub fn fold(mut audio: AudioBuffer) -> AudioBuffer {
-    for s in &mut audio.data {
-        *s = s.sin();
-    }
+    Fold.process(&mut audio.data);
     audio
 }
 
 pub fn hard_clip(mut audio: AudioBuffer, thresh: f32) -> AudioBuffer {
-    for s in &mut audio.data {
-        *s = if s.abs() > thresh {
-            thresh * s.signum()
-        } else {
-            *s
-        }
-    }
+    HardClip(thresh).process(&mut audio.data);
     audio
 }
 
 pub fn soft_clip(mut audio: AudioBuffer, amount: f32) -> AudioBuffer {
-    for s in &mut audio.data {
-        *s -= amount * s.powi(3) / 3.;
-    }
+    SoftClip(amount).process(&mut audio.data);
     audio
 }
 
 pub fn tense(mut audio: AudioBuffer, tension: f32) -> AudioBuffer {
-    for s in &mut audio.data {
-        *s = s.signum() * (1. - (1. - s.abs()).powf(tension));
-    }
+    Tense(tension).process(&mut audio.data);
     audio
 }
+
+pub struct DecimateEffect;
+
+impl Effect for DecimateEffect {
+    fn name(&self) -> &'static str {
+        "decimate"
+    }
+
+    fn description(&self) -> &'static str {
+        "Quantizes samples to depth steps per unit"
+    }
+
+    fn params(&self) -> &'static [Param] {
+        static PARAMS: [Param; 1] = [Param::float("depth", 16.).at_least(1.).automatable()];
+        &PARAMS
+    }
+
+    fn build(&self, args: &Args, metadata: &AudioMetadata) -> Stage {
+        Stage::stream(
+            Automated::new(Decimate(16.), metadata)
+                .control(args.automation("depth"), |d, x| d.0 = x),
+        )
+    }
+
+    fn repeats(&self) -> bool {
+        false
+    }
+}
+
+pub struct FoldEffect;
+
+impl Effect for FoldEffect {
+    fn name(&self) -> &'static str {
+        "fold"
+    }
+
+    fn description(&self) -> &'static str {
+        "Folds samples back with a sine"
+    }
+
+    fn build(&self, _: &Args, _: &AudioMetadata) -> Stage {
+        Stage::stream(Fold)
+    }
+}
+
+pub struct HardClipEffect;
+
+impl Effect for HardClipEffect {
+    fn name(&self) -> &'static str {
+        "hardclip"
+    }
+
+    fn description(&self) -> &'static str {
+        "Clips samples above threshold"
+    }
+
+    fn params(&self) -> &'static [Param] {
+        static PARAMS: [Param; 1] = [Param::float("threshold", 0.5).at_least(0.).automatable()];
+        &PARAMS
+    }
+
+    fn build(&self, args: &Args, metadata: &AudioMetadata) -> Stage {
+        Stage::stream(
+            Automated::new(HardClip(0.5), metadata)
+                .control(args.automation("threshold"), |c, x| c.0 = x),
+        )
+    }
+
+    fn repeats(&self) -> bool {
+        false
+    }
+}
+
+pub struct SoftClipEffect;
+
+impl Effect for SoftClipEffect {
+    fn name(&self) -> &'static str {
+        "softclip"
+    }
+
+    fn description(&self) -> &'static str {
+        "Cubic soft clipper"
+    }
+
+    fn params(&self) -> &'static [Param] {
+        static PARAMS: [Param; 1] = [Param::float("amount", 1.).automatable()];
+        &PARAMS
+    }
+
+    fn build(&self, args: &Args, metadata: &AudioMetadata) -> Stage {
+        Stage::stream(
+            Automated::new(SoftClip(1.), metadata)
+                .control(args.automation("amount"), |c, x| c.0 = x),
+        )
+    }
+}
+
+pub struct TenseEffect;
+
+impl Effect for TenseEffect {
+    fn name(&self) -> &'static str {
+        "tense"
+    }
+
+    fn description(&self) -> &'static str {
+        "Bends the waveform, below 1 towards a square and above 1 towards spikes"
+    }
+
+    fn params(&self) -> &'static [Param] {
+        static PARAMS: [Param; 1] = [Param::float("tension", 0.5).at_least(0.).automatable()];
+        &PARAMS
+    }
+
+    fn build(&self, args: &Args, metadata: &AudioMetadata) -> Stage {
+        Stage::stream(
+            Automated::new(Tense(0.5), metadata)
+                .control(args.automation("tension"), |t, x| t.0 = x),
+        )
+    }
+}
@@ -0,0 +1,144 @@
+use crate::types::{AudioBuffer, SampleFormat};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dither {
+    None,
+    /// White noise spanning one least significant bit.
+    Rectangular,
+    /// Triangular noise spanning two least significant bits, which decorrelates the quantisation
+    /// error from the signal.
+    Triangular,
+    /// Triangular noise with the quantisation error pushed towards high frequencies, where it is
+    /// less audible.
+    NoiseShaped,
+}
+
+impl Dither {
+    /// Dithering used when nothing is specified: triangular noise when `target` is an integer
+    /// format that can't represent all the precision of `source`, or of the audio `processed`
+    /// out of it, nothing otherwise. Samples being processed as 32 bit floats, formats of 24 bits
+    /// or more already hold all of it.
+    pub fn default_for(source: SampleFormat, target: SampleFormat, processed: bool) -> Self {
+        let reduced = source.is_float() || source.bit_depth() > target.bit_depth();
+        if !target.is_float() && target.bit_depth() < 24 && (processed || reduced) {
+            Self::Triangular
+        } else {
+            Self::None
+        }
+    }
+}
+
+impl std::str::FromStr for Dither {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "rect" => Ok(Self::Rectangular),
+            "tpdf" => Ok(Self::Triangular),
+            "shaped" => Ok(Self::NoiseShaped),
+            _ => Err(format!("unknown dither {}", s)),
+        }
+    }
+}
+
+/// Error feedback filter from Lipshitz, Vanderkooy and Wannamaker's "Minimally audible noise
+/// shaping", designed for 44.1kHz.
+const SHAPING_FILTER: [f32; 5] = [2.033, -2.165, 1.959, -1.590, 0.6149];
+
+/// xorshift64*, seeded through splitmix64 so that any seed, including 0, works.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Self((z ^ (z >> 31)) | 1)
+    }
+
+    /// Uniform value in [-0.5, 0.5).
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let x = self.0.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (x >> 40) as f32 / (1_u64 << 24) as f32 - 0.5
+    }
+}
+
+/// Quantises samples to the resolution of an integer format, adding dither noise beforehand.
+///
+/// The samples stay as `f32` but land exactly on values that `write_wav` can encode without any
+/// further rounding. State is kept between calls so that a file can be processed in pieces.
+pub struct Ditherer {
+    kind: Dither,
+    scale: f32,
+    min: f32,
+    max: f32,
+    channels: usize,
+    channel: usize,
+    rng: Rng,
+    /// Last quantisation errors of each channel, most recent first.
+    errors: Vec<[f32; SHAPING_FILTER.len()]>,
+}
+
+impl Ditherer {
+    pub fn new(kind: Dither, format: SampleFormat, channels: u16, seed: u64) -> Self {
+        let kind = if format.is_float() {
+            Dither::None
+        } else {
+            kind
+        };
+        let scale = (1_u64 << (format.bit_depth() - 1)) as f32;
+        Self {
+            kind,
+            scale,
+            min: -scale,
+            max: scale - 1.,
+            channels: usize::from(channels),
+            channel: 0,
+            rng: Rng::new(seed),
+            errors: vec![[0.; SHAPING_FILTER.len()]; usize::from(channels)],
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if self.kind == Dither::None {
+            return;
+        }
+
+        for s in samples {
+            let errors = &mut self.errors[self.channel];
+            let mut wanted = *s * self.scale;
+            if self.kind == Dither::NoiseShaped {
+                wanted -= SHAPING_FILTER
+                    .iter()
+                    .zip(errors.iter())
+                    .map(|(h, e)| h * e)
+                    .sum::<f32>();
+            }
+            let noise = match self.kind {
+                Dither::Rectangular => self.rng.next(),
+                _ => self.rng.next() + self.rng.next(),
+            };
+            let quantized = (wanted + noise).round();
+            if self.kind == Dither::NoiseShaped {
+                errors.rotate_right(1);
+                errors[0] = quantized - wanted;
+            }
+            *s = quantized.clamp(self.min, self.max) / self.scale;
+            self.channel = (self.channel + 1) % self.channels;
+        }
+    }
+}
+
+pub fn dither(
+    mut audio: AudioBuffer,
+    format: SampleFormat,
+    kind: Dither,
+    seed: u64,
+) -> AudioBuffer {
+    Ditherer::new(kind, format, audio.metadata.channels, seed).process(&mut audio.data);
+    audio
+}
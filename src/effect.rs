@@ -0,0 +1,742 @@
+//! Effects described by name and typed parameters, so that command lines, usage text and
+//! front-ends can all be generated from one list.
+
+use std::fmt;
+use std::num::{ParseFloatError, ParseIntError};
+
+use crate::automation::Automation;
+use crate::distort::*;
+use crate::gain::*;
+use crate::mix::mix;
+use crate::phase::*;
+use crate::pitch::*;
+use crate::processor::Processor;
+use crate::pseudo_cycle::*;
+use crate::resample::*;
+use crate::types::{AudioBuffer, AudioMetadata};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamKind {
+    Int,
+    Float,
+    /// One of a fixed set of names.
+    Enum(&'static [&'static str]),
+}
+
+impl ParamKind {
+    fn name(self) -> &'static str {
+        match self {
+            ParamKind::Int => "int",
+            ParamKind::Float => "float",
+            ParamKind::Enum(_) => "enum",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Enum(&'static str),
+    /// Float that changes over time, for parameters that can be automated.
+    Automation(Automation),
+}
+
+impl Value {
+    /// Value of a float parameter over time, constant unless it is automated.
+    pub fn automation(&self) -> Automation {
+        match self {
+            Value::Int(i) => Automation::Constant(*i as f64),
+            Value::Float(x) => Automation::Constant(*x),
+            Value::Enum(choice) => panic!("{} isn't a number", choice),
+            Value::Automation(automation) => automation.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Enum(name) => write!(f, "{}", name),
+            Value::Automation(automation) => write!(f, "{}", automation),
+        }
+    }
+}
+
+/// Parameter of an effect, with inclusive bounds where values outside of them make no sense.
+#[derive(Clone, Debug)]
+pub struct Param {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    pub default: Value,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub unit: Option<&'static str>,
+    /// Whether a float can be given as an `Automation` instead of a constant.
+    pub automatable: bool,
+    /// Whether it can be left out, taking its default, which only trailing parameters can.
+    pub optional: bool,
+}
+
+#[derive(Debug)]
+pub enum ParamError {
+    ParseInt(ParseIntError),
+    ParseFloat(ParseFloatError),
+    OutOfRange(&'static Param, f64),
+    Missing(&'static Param),
+    UnknownChoice(&'static Param, String),
+    BadSweep(String),
+    BadAutomation(&'static Param, String),
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamError::ParseInt(e) => write!(f, "{}", e),
+            ParamError::ParseFloat(e) => write!(f, "{}", e),
+            ParamError::OutOfRange(param, value) => {
+                write!(f, "{} {} is out of range", param.name, value)?;
+                match (param.min, param.max) {
+                    (Some(min), Some(max)) => write!(f, ", it goes from {} to {}", min, max),
+                    (Some(min), None) => write!(f, ", it is at least {}", min),
+                    (None, Some(max)) => write!(f, ", it is at most {}", max),
+                    (None, None) => Ok(()),
+                }
+            }
+            ParamError::Missing(param) => write!(f, "missing {}", param.describe()),
+            ParamError::UnknownChoice(param, value) => match param.kind {
+                ParamKind::Enum(choices) => write!(
+                    f,
+                    "unknown {} {}, it is one of {}",
+                    param.name,
+                    value,
+                    choices.join(", ")
+                ),
+                _ => write!(f, "unknown {} {}", param.name, value),
+            },
+            ParamError::BadSweep(s) => write!(f, "{}", s),
+            ParamError::BadAutomation(param, e) => write!(f, "{}: {}", param.name, e),
+        }
+    }
+}
+
+impl Param {
+    pub const fn int(name: &'static str, default: i64) -> Self {
+        Self {
+            name,
+            kind: ParamKind::Int,
+            default: Value::Int(default),
+            min: None,
+            max: None,
+            unit: None,
+            automatable: false,
+            optional: false,
+        }
+    }
+
+    pub const fn float(name: &'static str, default: f64) -> Self {
+        Self {
+            name,
+            kind: ParamKind::Float,
+            default: Value::Float(default),
+            min: None,
+            max: None,
+            unit: None,
+            automatable: false,
+            optional: false,
+        }
+    }
+
+    pub const fn choice(
+        name: &'static str,
+        choices: &'static [&'static str],
+        default: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            kind: ParamKind::Enum(choices),
+            default: Value::Enum(default),
+            min: None,
+            max: None,
+            unit: None,
+            automatable: false,
+            optional: false,
+        }
+    }
+
+    pub const fn at_least(mut self, min: f64) -> Self {
+        self.min = Some(min);
+        self
+    }
+
+    pub const fn at_most(mut self, max: f64) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    pub const fn unit(mut self, unit: &'static str) -> Self {
+        self.unit = Some(unit);
+        self
+    }
+
+    pub const fn automatable(mut self) -> Self {
+        self.automatable = true;
+        self
+    }
+
+    pub const fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    /// Parses and validates a value given on the command line.
+    pub fn parse(&'static self, s: &str) -> Result<Value, ParamError> {
+        let value = match self.kind {
+            ParamKind::Int => Value::Int(s.parse().map_err(ParamError::ParseInt)?),
+            // anything else is a number, and its errors are those of a number
+            ParamKind::Float if self.automatable && (s.contains(':') || s.starts_with('@')) => {
+                match s.parse() {
+                    Ok(Automation::Constant(x)) => Value::Float(x),
+                    Ok(automation) => Value::Automation(automation),
+                    Err(e) => return Err(ParamError::BadAutomation(self, e)),
+                }
+            }
+            ParamKind::Float => Value::Float(s.parse().map_err(ParamError::ParseFloat)?),
+            ParamKind::Enum(choices) => match choices.iter().find(|&&choice| choice == s) {
+                Some(choice) => Value::Enum(choice),
+                None => return Err(ParamError::UnknownChoice(self, s.to_string())),
+            },
+        };
+        self.check(value)
+    }
+
+    /// Parses either one value or a sweep over several: `[a,b,c]` lists them and `a..b:n`
+    /// spreads `n` of them evenly from `a` to `b`. Automations can't be listed, their
+    /// breakpoints being separated by commas too.
+    pub fn parse_sweep(&'static self, s: &str) -> Result<Vec<Value>, ParamError> {
+        if let Some(list) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            if list.contains(':') {
+                return Err(ParamError::BadSweep(format!(
+                    "{} can't be swept over automations",
+                    self.name
+                )));
+            }
+            return list
+                .split(',')
+                .map(|value| self.parse(value.trim()))
+                .collect();
+        }
+        let (range, count) = match s.split_once(':') {
+            Some((range, count)) if range.contains("..") => (range, count),
+            _ => return Ok(vec![self.parse(s)?]),
+        };
+        let (start, end) = range.split_once("..").unwrap_or_default();
+        let count = count.parse::<usize>().map_err(ParamError::ParseInt)?;
+        if count == 0 {
+            return Err(ParamError::BadSweep(format!("{} sweeps over no values", s)));
+        }
+        let (start, end) = match (self.parse(start)?, self.parse(end)?) {
+            (Value::Int(start), Value::Int(end)) => (start as f64, end as f64),
+            (Value::Float(start), Value::Float(end)) => (start, end),
+            _ => {
+                return Err(ParamError::BadSweep(format!(
+                    "{} can only be swept with a list",
+                    self.name
+                )))
+            }
+        };
+        (0..count)
+            .map(|i| {
+                let t = if count == 1 {
+                    0.
+                } else {
+                    i as f64 / (count - 1) as f64
+                };
+                let x = start + (end - start) * t;
+                self.check(match self.kind {
+                    ParamKind::Int => Value::Int(x.round() as i64),
+                    // without the rounding noise, which would end up in file names
+                    _ => Value::Float(format!("{:.12}", x).parse().unwrap_or(x)),
+                })
+            })
+            .collect()
+    }
+
+    pub fn check(&'static self, value: Value) -> Result<Value, ParamError> {
+        let (low, high) = match &value {
+            Value::Int(i) => (*i as f64, *i as f64),
+            Value::Float(x) => (*x, *x),
+            Value::Enum(_) => return Ok(value),
+            Value::Automation(automation) => automation.bounds(),
+        };
+        for x in [low, high] {
+            // NaN compares false with any bound
+            if !x.is_finite()
+                || self.min.is_some_and(|min| x < min)
+                || self.max.is_some_and(|max| x > max)
+            {
+                return Err(ParamError::OutOfRange(self, x));
+            }
+        }
+        Ok(value)
+    }
+
+    /// Like "a decimal amount", for error messages.
+    fn describe(&self) -> String {
+        match self.kind {
+            ParamKind::Int => format!("an integer {}", self.name),
+            ParamKind::Float => format!("a decimal {}", self.name),
+            ParamKind::Enum(choices) => format!("a {} among {}", self.name, choices.join("/")),
+        }
+    }
+}
+
+/// Values of an effect's parameters, in the order they are declared in.
+#[derive(Clone, Debug)]
+pub struct Args {
+    params: &'static [Param],
+    values: Vec<Value>,
+}
+
+impl Args {
+    /// Parses one argument per parameter, optional ones taking their default when left out.
+    pub fn parse(params: &'static [Param], args: &[String]) -> Result<Self, ParamError> {
+        let values = params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| match args.get(i) {
+                Some(arg) => param.parse(arg),
+                None if param.optional => Ok(param.default.clone()),
+                None => Err(ParamError::Missing(param)),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { params, values })
+    }
+
+    /// Default value of every parameter.
+    pub fn defaults(params: &'static [Param]) -> Self {
+        Self {
+            params,
+            values: params.iter().map(|param| param.default.clone()).collect(),
+        }
+    }
+
+    pub fn params(&self) -> &'static [Param] {
+        self.params
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// Sets the value of the `i`th parameter, which has to be valid.
+    pub fn set(&mut self, i: usize, value: Value) -> Result<(), ParamError> {
+        self.values[i] = self.params[i].check(value)?;
+        Ok(())
+    }
+
+    fn get(&self, name: &str) -> &Value {
+        let i = self
+            .params
+            .iter()
+            .position(|param| param.name == name)
+            .unwrap_or_else(|| panic!("no parameter {}", name));
+        &self.values[i]
+    }
+
+    pub fn int(&self, name: &str) -> i64 {
+        match self.get(name) {
+            Value::Int(i) => *i,
+            Value::Float(x) => *x as i64,
+            Value::Enum(_) => panic!("{} is an enum", name),
+            Value::Automation(_) => panic!("{} is automated", name),
+        }
+    }
+
+    pub fn float(&self, name: &str) -> f64 {
+        match self.get(name) {
+            Value::Int(i) => *i as f64,
+            Value::Float(x) => *x,
+            Value::Enum(_) => panic!("{} is an enum", name),
+            Value::Automation(_) => panic!("{} is automated", name),
+        }
+    }
+
+    /// Value of an automatable parameter, a constant one included.
+    pub fn automation(&self, name: &str) -> Automation {
+        self.get(name).automation()
+    }
+
+    pub fn choice(&self, name: &str) -> &'static str {
+        match self.get(name) {
+            Value::Enum(choice) => choice,
+            _ => panic!("{} isn't an enum", name),
+        }
+    }
+}
+
+/// An effect ready to be applied.
+pub enum Stage {
+    /// Effect that can be applied block by block.
+    Stream(Box<dyn Processor>),
+    /// Effect that needs the whole file at once.
+    Whole(Box<dyn FnMut(AudioBuffer) -> AudioBuffer>),
+}
+
+impl Stage {
+    pub fn stream<P: Processor + 'static>(processor: P) -> Self {
+        Stage::Stream(Box::new(processor))
+    }
+
+    pub fn whole<F: FnMut(AudioBuffer) -> AudioBuffer + 'static>(f: F) -> Self {
+        Stage::Whole(Box::new(f))
+    }
+
+    /// Stages applied one after the other, which only stream if all of them do.
+    pub fn chain(stages: Vec<Stage>) -> Self {
+        if stages.iter().all(|stage| matches!(stage, Stage::Stream(_))) {
+            let processors: Vec<_> = stages
+                .into_iter()
+                .filter_map(|stage| match stage {
+                    Stage::Stream(processor) => Some(processor),
+                    Stage::Whole(_) => None,
+                })
+                .collect();
+            return Stage::stream(processors);
+        }
+        let mut stages = stages;
+        Stage::whole(move |audio| {
+            stages
+                .iter_mut()
+                .fold(audio, |audio, stage| stage.apply(audio))
+        })
+    }
+
+    pub fn apply(&mut self, mut audio: AudioBuffer) -> AudioBuffer {
+        match self {
+            Stage::Stream(processor) => {
+                processor.process(&mut audio.data);
+                audio
+            }
+            Stage::Whole(f) => f(audio),
+        }
+    }
+}
+
+/// Effect that can be looked up by name in `EFFECTS`.
+pub trait Effect: Sync {
+    fn name(&self) -> &'static str;
+
+    /// One line for help texts and front-ends.
+    fn description(&self) -> &'static str;
+
+    fn params(&self) -> &'static [Param] {
+        &[]
+    }
+
+    fn build(&self, args: &Args, metadata: &AudioMetadata) -> Stage;
+
+    /// Sample rate of the audio coming out of the effect.
+    fn sample_rate(&self, _args: &Args, sample_rate: u32) -> u32 {
+        sample_rate
+    }
+
+    /// Whether the audio coming out of the effect can be longer or shorter than what went in.
+    fn changes_length(&self, _args: &Args) -> bool {
+        false
+    }
+
+    /// Whether iterations apply the effect again, which is pointless for effects whose result
+    /// doesn't change when they are applied to it, so those are applied once.
+    fn repeats(&self) -> bool {
+        true
+    }
+
+    /// Like "fractalize <depth>".
+    fn synopsis(&self) -> String {
+        let mut synopsis = String::from(self.name());
+        for param in self.params() {
+            if param.optional {
+                synopsis += &format!(" [<{}>]", param.name);
+            } else {
+                synopsis += &format!(" <{}>", param.name);
+            }
+        }
+        synopsis
+    }
+
+    /// Like "fractalize takes an integer depth".
+    fn expected_args(&self) -> String {
+        let described: Vec<_> = self
+            .params()
+            .iter()
+            .map(|param| {
+                if param.optional {
+                    format!("optionally {}", param.describe())
+                } else {
+                    param.describe()
+                }
+            })
+            .collect();
+        match described.split_last() {
+            None => format!("{} takes no arguments", self.name()),
+            Some((last, [])) => format!("{} takes {}", self.name(), last),
+            Some((last, rest)) => format!("{} takes {} and {}", self.name(), rest.join(", "), last),
+        }
+    }
+}
+
+/// Every effect, in the order in which abbreviated names are tried.
+pub static EFFECTS: &[&dyn Effect] = &[
+    &InterpolateEffect,
+    &FractalizeEffect,
+    &ExpandEffect,
+    &ReversePseudoCyclesEffect,
+    &FoldEffect,
+    &HardClipEffect,
+    &SoftClipEffect,
+    &TenseEffect,
+    &TensePseudoCyclesEffect,
+    &DecimateEffect,
+    &DelayPitchEffect,
+    &DelayRotateEffect,
+    &SpeedEffect,
+    &GainEffect,
+    &DcEffect,
+    &RemoveDcEffect,
+    &NormalizeEffect,
+    &ResampleEffect,
+];
+
+/// Finds the first effect whose name starts with `name`.
+pub fn find(name: &str) -> Option<&'static dyn Effect> {
+    EFFECTS
+        .iter()
+        .find(|effect| effect.name().starts_with(name))
+        .copied()
+}
+
+/// Dry/wet ratio of `Invocation::mix`, from only the input at 0 to only the effect at 1.
+pub static MIX: Param = Param::float("mix", 1.)
+    .at_least(0.)
+    .at_most(1.)
+    .automatable();
+
+/// An effect of a chain, with its arguments and how many times in a row it is applied.
+#[derive(Clone)]
+pub struct Invocation {
+    pub effect: &'static dyn Effect,
+    pub iterations: u32,
+    pub args: Args,
+    /// Ratio at which the result of all the iterations is mixed with their input, a `MIX` value.
+    pub mix: Option<Value>,
+}
+
+impl fmt::Debug for Invocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Invocation")
+            .field("effect", &self.effect.name())
+            .field("iterations", &self.iterations)
+            .field("args", &self.args.values)
+            .field("mix", &self.mix)
+            .finish()
+    }
+}
+
+impl Invocation {
+    pub fn build(&self, metadata: &AudioMetadata) -> Stage {
+        let stage = Stage::chain(
+            (0..self.applications())
+                .map(|_| self.effect.build(&self.args, metadata))
+                .collect(),
+        );
+        match &self.mix {
+            Some(ratio) => mix(stage, ratio.automation(), metadata),
+            None => stage,
+        }
+    }
+
+    /// Sample rate of the audio coming out of every iteration.
+    pub fn sample_rate(&self, sample_rate: u32) -> u32 {
+        (0..self.applications()).fold(sample_rate, |rate, _| {
+            self.effect.sample_rate(&self.args, rate)
+        })
+    }
+
+    /// How many times the effect is actually applied.
+    fn applications(&self) -> u32 {
+        if self.effect.repeats() {
+            self.iterations
+        } else {
+            1
+        }
+    }
+}
+
+/// Describes every effect and its parameters as a JSON array, for front-ends.
+pub fn catalogue_json() -> String {
+    let effects: Vec<String> = EFFECTS
+        .iter()
+        .map(|effect| {
+            let params: Vec<String> = effect.params().iter().map(param_json).collect();
+            format!(
+                "  {{\"name\": {}, \"description\": {}, \"params\": [{}]}}",
+                json_string(effect.name()),
+                json_string(effect.description()),
+                params.join(", ")
+            )
+        })
+        .collect();
+    format!("[\n{}\n]", effects.join(",\n"))
+}
+
+fn param_json(param: &Param) -> String {
+    let number = |x: Option<f64>| x.map_or(String::from("null"), |x| x.to_string());
+    let mut json = format!(
+        "{{\"name\": {}, \"type\": \"{}\", \"default\": {}, \"min\": {}, \"max\": {}, \"unit\": {}",
+        json_string(param.name),
+        param.kind.name(),
+        match &param.default {
+            Value::Enum(choice) => json_string(choice),
+            value => value.to_string(),
+        },
+        number(param.min),
+        number(param.max),
+        param.unit.map_or(String::from("null"), json_string),
+    );
+    if let ParamKind::Enum(choices) = param.kind {
+        let choices: Vec<String> = choices.iter().map(|choice| json_string(choice)).collect();
+        json += &format!(", \"choices\": [{}]", choices.join(", "));
+    }
+    if param.automatable {
+        json += ", \"automatable\": true";
+    }
+    if param.optional {
+        json += ", \"optional\": true";
+    }
+    json + "}"
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json += "\\\"",
+            '\\' => json += "\\\\",
+            c if u32::from(c) < 0x20 => json += &format!("\\u{:04x}", u32::from(c)),
+            c => json.push(c),
+        }
+    }
+    json + "\""
+}
+
+/// Like `Invocation`, with a list of values for each parameter and for the mix.
+#[derive(Clone)]
+pub struct Sweep {
+    pub effect: &'static dyn Effect,
+    pub iterations: u32,
+    pub values: Vec<Vec<Value>>,
+    pub mix: Option<Vec<Value>>,
+}
+
+impl fmt::Debug for Sweep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sweep")
+            .field("effect", &self.effect.name())
+            .field("iterations", &self.iterations)
+            .field("values", &self.values)
+            .field("mix", &self.mix)
+            .finish()
+    }
+}
+
+impl Sweep {
+    /// Whether more than one value is given for any parameter.
+    pub fn is_swept(&self) -> bool {
+        self.values
+            .iter()
+            .chain(&self.mix)
+            .any(|values| values.len() > 1)
+    }
+}
+
+impl From<Invocation> for Sweep {
+    fn from(invocation: Invocation) -> Self {
+        Self {
+            effect: invocation.effect,
+            iterations: invocation.iterations,
+            values: invocation
+                .args
+                .values
+                .iter()
+                .map(|v| vec![v.clone()])
+                .collect(),
+            mix: invocation.mix.map(|mix| vec![mix]),
+        }
+    }
+}
+
+/// Every combination of the swept values, with a name telling the swept values apart, like
+/// `softclip-amount-0.3_delaypitch-factor-2`, which is empty when nothing is swept.
+pub fn expand_sweeps(sweeps: &[Sweep]) -> Vec<(String, Vec<Invocation>)> {
+    let mut variants = vec![(Vec::new(), Vec::new())];
+    for (position, sweep) in sweeps.iter().enumerate() {
+        let params = sweep.effect.params();
+        let mut args = vec![(Vec::new(), Vec::new())];
+        // the mix is swept like one more parameter, which comes last
+        let swept = params
+            .iter()
+            .zip(&sweep.values)
+            .chain(sweep.mix.iter().map(|mix| (&MIX, mix)));
+        for (param, values) in swept {
+            let label = if sweeps[..position]
+                .iter()
+                .any(|s| s.effect.name() == sweep.effect.name())
+            {
+                format!("{}{}-{}", sweep.effect.name(), position + 1, param.name)
+            } else {
+                format!("{}-{}", sweep.effect.name(), param.name)
+            };
+            args = args
+                .into_iter()
+                .flat_map(|(names, prefix): (Vec<String>, Vec<Value>)| {
+                    let label = &label;
+                    values.iter().map(move |value| {
+                        let mut names = names.clone();
+                        if values.len() > 1 {
+                            names.push(format!("{}-{}", label, value));
+                        }
+                        let mut prefix = prefix.clone();
+                        prefix.push(value.clone());
+                        (names, prefix)
+                    })
+                })
+                .collect();
+        }
+        variants = variants
+            .into_iter()
+            .flat_map(|(names, chain): (Vec<String>, Vec<Invocation>)| {
+                args.iter().map(move |(arg_names, values)| {
+                    let mut names = names.clone();
+                    names.extend(arg_names.iter().cloned());
+                    let mut values = values.clone();
+                    let mix = sweep.mix.as_ref().and_then(|_| values.pop());
+                    let mut chain = chain.clone();
+                    chain.push(Invocation {
+                        effect: sweep.effect,
+                        iterations: sweep.iterations,
+                        args: Args { params, values },
+                        mix,
+                    });
+                    (names, chain)
+                })
+            })
+            .collect();
+    }
+    variants
+        .into_iter()
+        .map(|(names, chain)| (names.join("_"), chain))
+        .collect()
+}
@@ -1,16 +1,35 @@
-use crate::types::AudioBuffer;
+use crate::automation::Automated;
+use crate::effect::{Args, Effect, Param, Stage};
+use crate::processor::Processor;
+use crate::types::{AudioBuffer, AudioMetadata};
 
-pub fn gain(mut audio: AudioBuffer, gain: f32) -> AudioBuffer {
-    for s in &mut audio.data {
-        *s *= gain;
+pub struct Gain(pub f32);
+
+impl Processor for Gain {
+    fn process(&mut self, block: &mut [f32]) {
+        for s in block {
+            *s *= self.0;
+        }
+    }
+}
+
+pub struct AddDc(pub f32);
+
+impl Processor for AddDc {
+    fn process(&mut self, block: &mut [f32]) {
+        for s in block {
+            *s += self.0;
+        }
     }
+}
+
+pub fn gain(mut audio: AudioBuffer, gain: f32) -> AudioBuffer {
+    Gain(gain).process(&mut audio.data);
     audio
 }
 
 pub fn add_dc(mut audio: AudioBuffer, dc: f32) -> AudioBuffer {
-    for s in &mut audio.data {
-        *s += dc;
-    }
+    AddDc(dc).process(&mut audio.data);
     audio
 }
 
@@ -37,3 +56,89 @@ pub fn normalize(mut audio: AudioBuffer) -> AudioBuffer {
 
     audio
 }
+
+pub struct GainEffect;
+
+impl Effect for GainEffect {
+    fn name(&self) -> &'static str {
+        "gain"
+    }
+
+    fn description(&self) -> &'static str {
+        "Multiplies samples by gain"
+    }
+
+    fn params(&self) -> &'static [Param] {
+        static PARAMS: [Param; 1] = [Param::float("gain", 1.).automatable()];
+        &PARAMS
+    }
+
+    fn build(&self, args: &Args, metadata: &AudioMetadata) -> Stage {
+        Stage::stream(
+            Automated::new(Gain(1.), metadata).control(args.automation("gain"), |g, x| g.0 = x),
+        )
+    }
+}
+
+pub struct DcEffect;
+
+impl Effect for DcEffect {
+    fn name(&self) -> &'static str {
+        "dc"
+    }
+
+    fn description(&self) -> &'static str {
+        "Adds a constant offset"
+    }
+
+    fn params(&self) -> &'static [Param] {
+        static PARAMS: [Param; 1] = [Param::float("dc", 0.).automatable()];
+        &PARAMS
+    }
+
+    fn build(&self, args: &Args, metadata: &AudioMetadata) -> Stage {
+        Stage::stream(
+            Automated::new(AddDc(0.), metadata).control(args.automation("dc"), |d, x| d.0 = x),
+        )
+    }
+}
+
+pub struct RemoveDcEffect;
+
+impl Effect for RemoveDcEffect {
+    fn name(&self) -> &'static str {
+        "removedc"
+    }
+
+    fn description(&self) -> &'static str {
+        "Removes the average offset"
+    }
+
+    fn build(&self, _: &Args, _: &AudioMetadata) -> Stage {
+        Stage::whole(remove_dc)
+    }
+
+    fn repeats(&self) -> bool {
+        false
+    }
+}
+
+pub struct NormalizeEffect;
+
+impl Effect for NormalizeEffect {
+    fn name(&self) -> &'static str {
+        "normalize"
+    }
+
+    fn description(&self) -> &'static str {
+        "Scales the file so that its peak is at full scale"
+    }
+
+    fn build(&self, _: &Args, _: &AudioMetadata) -> Stage {
+        Stage::whole(normalize)
+    }
+
+    fn repeats(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,413 @@
+//! Chains that aren't linear: the signal is split into branches, each processed by its own chain,
+//! which are then brought back together.
+
+use std::collections::VecDeque;
+
+use crate::combine::{Combine, Input};
+use crate::effect::{expand_sweeps, Invocation, Stage, Sweep};
+use crate::processor::Processor;
+use crate::types::{AudioBuffer, AudioMetadata};
+
+/// Element of a chain, where `T` is an `Invocation`, or a `Sweep` before it is expanded.
+#[derive(Clone, Debug)]
+pub enum Node<T> {
+    Effect(T),
+    /// Branches fed with the same input, whose outputs are summed, each with a gain.
+    Split(Vec<(f32, Vec<Node<T>>)>),
+    /// Branches fed with a single channel each, the other channels are left as they are.
+    Channels(Vec<(u16, Vec<Node<T>>)>),
+    /// Combines the audio with the extra input of that name.
+    Input(String, Combine),
+}
+
+/// Effects of `nodes`, in the order they are written in.
+pub fn effects<T>(nodes: &[Node<T>]) -> Vec<&T> {
+    let mut effects = Vec::new();
+    for node in nodes {
+        match node {
+            Node::Effect(effect) => effects.push(effect),
+            Node::Split(branches) => {
+                for (_, chain) in branches {
+                    effects.extend(self::effects(chain));
+                }
+            }
+            Node::Channels(branches) => {
+                for (_, chain) in branches {
+                    effects.extend(self::effects(chain));
+                }
+            }
+            Node::Input(..) => {}
+        }
+    }
+    effects
+}
+
+/// The same graph with every effect replaced by `f(effect)`, called in the order of `effects`.
+pub fn map<T, U, F: FnMut(&T) -> U>(nodes: &[Node<T>], f: &mut F) -> Vec<Node<U>> {
+    nodes
+        .iter()
+        .map(|node| match node {
+            Node::Effect(effect) => Node::Effect(f(effect)),
+            Node::Split(branches) => Node::Split(
+                branches
+                    .iter()
+                    .map(|(gain, chain)| (*gain, map(chain, f)))
+                    .collect(),
+            ),
+            Node::Channels(branches) => Node::Channels(
+                branches
+                    .iter()
+                    .map(|(channel, chain)| (*channel, map(chain, f)))
+                    .collect(),
+            ),
+            Node::Input(name, combine) => Node::Input(name.clone(), *combine),
+        })
+        .collect()
+}
+
+/// The effects of `nodes` if it is a plain chain, without branches.
+pub fn linear<T: Clone>(nodes: &[Node<T>]) -> Option<Vec<T>> {
+    nodes
+        .iter()
+        .map(|node| match node {
+            Node::Effect(effect) => Some(effect.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Like `expand_sweeps`, sweeping through the effects of every branch.
+pub fn expand_graph(nodes: &[Node<Sweep>]) -> Vec<(String, Vec<Node<Invocation>>)> {
+    let sweeps: Vec<Sweep> = effects(nodes).into_iter().cloned().collect();
+    expand_sweeps(&sweeps)
+        .into_iter()
+        .map(|(label, chain)| {
+            let mut invocations = chain.into_iter();
+            let graph = map(nodes, &mut |_| invocations.next().expect("one per sweep"));
+            (label, graph)
+        })
+        .collect()
+}
+
+/// Sample rate of the audio coming out of `nodes` when `sample_rate` goes in.
+pub fn sample_rate(nodes: &[Node<Invocation>], sample_rate: u32) -> u32 {
+    nodes.iter().fold(sample_rate, |rate, node| match node {
+        Node::Effect(invocation) => invocation.sample_rate(rate),
+        // `build` makes sure that branches don't change it
+        _ => rate,
+    })
+}
+
+/// Builds the stages of `nodes`, which only stream if all of them do, taking the inputs they
+/// refer to from `inputs`. Each stage is built for the sample rate the ones before it leave.
+pub fn build(
+    nodes: &[Node<Invocation>],
+    metadata: &AudioMetadata,
+    inputs: &[Input],
+) -> Result<Stage, String> {
+    let mut metadata = metadata.clone();
+    let mut stages = Vec::new();
+    for node in nodes {
+        let keeps_rate = |chain: &[Node<Invocation>]| {
+            if sample_rate(chain, metadata.sample_rate) == metadata.sample_rate {
+                Ok(())
+            } else {
+                Err(String::from("branches can't change the sample rate"))
+            }
+        };
+        stages.push(match node {
+            Node::Effect(invocation) => {
+                let rate = invocation.sample_rate(metadata.sample_rate);
+                if invocation.mix.is_some() {
+                    // the input and the output are mixed sample by sample
+                    let change = if rate != metadata.sample_rate {
+                        Some("sample rate")
+                    } else if invocation.effect.changes_length(&invocation.args) {
+                        Some("length")
+                    } else {
+                        None
+                    };
+                    if let Some(change) = change {
+                        return Err(format!(
+                            "{} changes the {}, it can't be mixed with its input",
+                            invocation.effect.name(),
+                            change
+                        ));
+                    }
+                }
+                let stage = invocation.build(&metadata);
+                metadata.sample_rate = rate;
+                stage
+            }
+            Node::Split(branches) => {
+                let mut stages = Vec::new();
+                for (gain, chain) in branches {
+                    keeps_rate(chain)?;
+                    stages.push((*gain, build(chain, &metadata, inputs)?));
+                }
+                split(stages, usize::from(metadata.channels))
+            }
+            Node::Channels(branches) => {
+                let mono = AudioMetadata {
+                    channels: 1,
+                    channel_mask: None,
+                    ..metadata.clone()
+                };
+                let mut stages = Vec::new();
+                for (i, (channel, chain)) in branches.iter().enumerate() {
+                    if *channel >= metadata.channels {
+                        return Err(format!(
+                            "there is no channel {}, channels go from 0 to {}",
+                            channel,
+                            metadata.channels - 1
+                        ));
+                    }
+                    if branches[..i].iter().any(|(c, _)| c == channel) {
+                        return Err(format!("channel {} has more than one branch", channel));
+                    }
+                    keeps_rate(chain)?;
+                    stages.push((usize::from(*channel), build(chain, &mono, inputs)?));
+                }
+                per_channel(stages, usize::from(metadata.channels))
+            }
+            Node::Input(name, combine) => {
+                let input = inputs
+                    .iter()
+                    .find(|input| input.name == *name)
+                    .ok_or_else(|| format!("there is no input named {}", name))?;
+                combine
+                    .stage(input.audio.clone(), &metadata)
+                    .map_err(|e| format!("input {} can't be combined, {}", name, e))?
+            }
+        });
+    }
+    Ok(Stage::chain(stages))
+}
+
+/// Delays samples by a fixed amount, to line up branches of different latencies.
+struct Delay(VecDeque<f32>);
+
+impl Delay {
+    fn new(samples: usize) -> Self {
+        Delay(VecDeque::from(vec![0.; samples]))
+    }
+
+    fn push(&mut self, s: f32) -> f32 {
+        self.0.push_back(s);
+        self.0.pop_front().expect("just pushed")
+    }
+}
+
+/// Streaming version of `split`.
+struct Split {
+    branches: Vec<(f32, Box<dyn Processor>, Delay)>,
+    /// Copy of the input that a branch processes.
+    scratch: Vec<f32>,
+}
+
+impl Processor for Split {
+    fn process(&mut self, block: &mut [f32]) {
+        let input = block.to_vec();
+        block.fill(0.);
+        for (gain, processor, delay) in &mut self.branches {
+            self.scratch.clear();
+            self.scratch.extend_from_slice(&input);
+            processor.process(&mut self.scratch);
+            for (out, &s) in block.iter_mut().zip(&self.scratch) {
+                *out += *gain * delay.push(s);
+            }
+        }
+    }
+
+    fn lookahead(&self) -> usize {
+        self.branches
+            .iter()
+            .map(|(_, processor, _)| processor.lookahead())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn latency(&self) -> usize {
+        self.branches
+            .iter()
+            .map(|(_, processor, _)| processor.latency())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Sums the outputs of `branches`, each fed with the input and scaled by its gain. Outputs of
+/// different lengths are padded with silence.
+fn split(branches: Vec<(f32, Stage)>, channels: usize) -> Stage {
+    if branches
+        .iter()
+        .all(|(_, stage)| matches!(stage, Stage::Stream(_)))
+    {
+        let processors: Vec<(f32, Box<dyn Processor>)> = branches
+            .into_iter()
+            .filter_map(|(gain, stage)| match stage {
+                Stage::Stream(processor) => Some((gain, processor)),
+                Stage::Whole(_) => None,
+            })
+            .collect();
+        let latency = processors
+            .iter()
+            .map(|(_, processor)| processor.latency())
+            .max()
+            .unwrap_or(0);
+        let branches = processors
+            .into_iter()
+            .map(|(gain, processor)| {
+                let delay = Delay::new((latency - processor.latency()) * channels);
+                (gain, processor, delay)
+            })
+            .collect();
+        return Stage::stream(Split {
+            branches,
+            scratch: Vec::new(),
+        });
+    }
+
+    let mut branches = branches;
+    Stage::whole(move |audio: AudioBuffer| {
+        let mut sum: Option<AudioBuffer> = None;
+        for (gain, stage) in &mut branches {
+            let mut out = stage.apply(audio.clone());
+            for s in &mut out.data {
+                *s *= *gain;
+            }
+            sum = Some(match sum {
+                None => out,
+                Some(mut sum) => {
+                    if sum.data.len() < out.data.len() {
+                        sum.data.resize(out.data.len(), 0.);
+                    }
+                    for (s, o) in sum.data.iter_mut().zip(&out.data) {
+                        *s += o;
+                    }
+                    sum
+                }
+            });
+        }
+        sum.unwrap_or(audio)
+    })
+}
+
+/// Streaming version of `per_channel`.
+struct PerChannel {
+    /// Processor of each channel, if it has a branch, and the delay lining it up with the others.
+    channels: Vec<(Option<Box<dyn Processor>>, Delay)>,
+    /// Samples of one channel.
+    scratch: Vec<f32>,
+}
+
+impl Processor for PerChannel {
+    fn process(&mut self, block: &mut [f32]) {
+        let count = self.channels.len();
+        for (channel, (processor, delay)) in self.channels.iter_mut().enumerate() {
+            self.scratch.clear();
+            self.scratch
+                .extend(block.iter().skip(channel).step_by(count).copied());
+            if let Some(processor) = processor {
+                processor.process(&mut self.scratch);
+            }
+            for (out, &s) in block
+                .iter_mut()
+                .skip(channel)
+                .step_by(count)
+                .zip(&self.scratch)
+            {
+                *out = delay.push(s);
+            }
+        }
+    }
+
+    fn lookahead(&self) -> usize {
+        self.channels
+            .iter()
+            .filter_map(|(processor, _)| processor.as_ref().map(|p| p.lookahead()))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn latency(&self) -> usize {
+        self.channels
+            .iter()
+            .filter_map(|(processor, _)| processor.as_ref().map(|p| p.latency()))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Processes each channel that has a branch with it, as mono audio. Channels of different
+/// lengths are padded with silence.
+fn per_channel(branches: Vec<(usize, Stage)>, channels: usize) -> Stage {
+    if branches
+        .iter()
+        .all(|(_, stage)| matches!(stage, Stage::Stream(_)))
+    {
+        let mut processors: Vec<Option<Box<dyn Processor>>> = (0..channels).map(|_| None).collect();
+        for (channel, stage) in branches {
+            if let Stage::Stream(processor) = stage {
+                processors[channel] = Some(processor);
+            }
+        }
+        let latency = processors
+            .iter()
+            .filter_map(|p| p.as_ref().map(|p| p.latency()))
+            .max()
+            .unwrap_or(0);
+        let channels = processors
+            .into_iter()
+            .map(|processor| {
+                let own = processor.as_ref().map_or(0, |p| p.latency());
+                (processor, Delay::new(latency - own))
+            })
+            .collect();
+        return Stage::stream(PerChannel {
+            channels,
+            scratch: Vec::new(),
+        });
+    }
+
+    let mut branches = branches;
+    Stage::whole(move |mut audio: AudioBuffer| {
+        let mut outputs: Vec<Option<Vec<f32>>> = (0..channels).map(|_| None).collect();
+        for (channel, stage) in &mut branches {
+            let mono = AudioBuffer {
+                metadata: AudioMetadata {
+                    channels: 1,
+                    channel_mask: None,
+                    chunks: Default::default(),
+                    ..audio.metadata.clone()
+                },
+                data: audio
+                    .data
+                    .iter()
+                    .skip(*channel)
+                    .step_by(channels)
+                    .copied()
+                    .collect(),
+            };
+            outputs[*channel] = Some(stage.apply(mono).data);
+        }
+        let input_frames = audio.data.len() / channels;
+        let frames = outputs
+            .iter()
+            .map(|output| output.as_ref().map_or(input_frames, Vec::len))
+            .max()
+            .unwrap_or(0);
+        let mut data = vec![0.; frames * channels];
+        for (channel, output) in outputs.iter().enumerate() {
+            let samples: Box<dyn Iterator<Item = &f32>> = match output {
+                Some(output) => Box::new(output.iter()),
+                None => Box::new(audio.data.iter().skip(channel).step_by(channels)),
+            };
+            for (out, &s) in data.iter_mut().skip(channel).step_by(channels).zip(samples) {
+                *out = s;
+            }
+        }
+        audio.data = data;
+        audio
+    })
+}
@@ -1,198 +1,702 @@
+use crate::metadata::ChunkMetadata;
 use crate::types::*;
 
+use std::fmt;
 use std::io;
-use std::io::{Read, Write};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
 
-pub fn write_wav<W: Write>(writer: &mut W, audio_buffer: &AudioBuffer) -> Result<(), io::Error> {
-    writer.write_all(b"RIFF")?;
-    writer.write_all(&audio_buffer.file_size().to_le_bytes())?;
-    writer.write_all(b"WAVE")?;
+mod aiff;
+mod flac;
+mod raw;
+pub mod riff;
+
+pub use self::aiff::{read_aiff, write_aiff};
+pub use self::flac::{read_flac, write_flac};
+pub use self::raw::{read_raw, write_raw, write_raw_samples, RawFormat, RawReader, RawSpec};
+
+#[derive(Debug)]
+pub enum WavError {
+    Io(io::Error),
+    BadMagic([u8; 4]),
+    UnsupportedCodec(u16),
+    /// AIFF-C compression type that isn't uncompressed PCM or float.
+    UnsupportedCompression([u8; 4]),
+    UnsupportedBitDepth(u16),
+    Truncated,
+    MissingFmt,
+    MissingData,
+    /// Encoded stream that doesn't follow its specification, e.g. a FLAC frame with a bad CRC.
+    Corrupt(&'static str),
+    /// Header or raw spec whose values can't describe any audio, e.g. zero channels.
+    InvalidHeader(&'static str),
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WavError::Io(e) => write!(f, "{}", e),
+            WavError::BadMagic(tag) => write!(
+                f,
+                "bad magic: expected RIFF/WAVE, FORM/AIFF or fLaC, found {}",
+                String::from_utf8_lossy(tag)
+            ),
+            WavError::UnsupportedCodec(x) => write!(f, "unsupported format {:#06x}", x),
+            WavError::UnsupportedCompression(x) => {
+                write!(f, "unsupported compression {}", String::from_utf8_lossy(x))
+            }
+            WavError::UnsupportedBitDepth(x) => write!(f, "{} bit not supported", x),
+            WavError::Truncated => write!(f, "file is truncated"),
+            WavError::MissingFmt => write!(f, "no fmt, COMM or STREAMINFO found"),
+            WavError::MissingData => write!(f, "no data chunk found"),
+            WavError::Corrupt(x) => write!(f, "corrupt stream: {}", x),
+            WavError::InvalidHeader(x) => write!(f, "invalid header: {}", x),
+        }
+    }
+}
+
+impl std::error::Error for WavError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WavError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for WavError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            WavError::Truncated
+        } else {
+            WavError::Io(err)
+        }
+    }
+}
+
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Every KSDATAFORMAT_SUBTYPE GUID used by WAVE_FORMAT_EXTENSIBLE ends with these bytes, the first
+/// two bytes being the actual format code.
+const SUBFORMAT_GUID_TAIL: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+/// Writes `audio_buffer` as a WAV file whose samples are encoded as `format`.
+///
+/// Integer formats round each sample to the nearest value and clip it to the representable range.
+/// Files that don't fit the 32 bit sizes of RIFF are written as RF64.
+pub fn write_wav<W: Write>(
+    writer: &mut W,
+    audio_buffer: &AudioBuffer,
+    format: SampleFormat,
+) -> Result<(), io::Error> {
+    let samples = audio_buffer.data.len() as u64;
+    write_wav_header(writer, &audio_buffer.metadata, format, samples)?;
+    write_samples(writer, &audio_buffer.data, format, Endianness::Little)?;
+    if audio_buffer.data_size(format) % 2 == 1 {
+        writer.write_all(&[0])?;
+    }
+    Ok(())
+}
+
+/// Writes everything that comes before the samples in a WAV file holding `samples` samples, i.e.
+/// up to and including the data chunk's header.
+pub fn write_wav_header<W: Write>(
+    writer: &mut W,
+    metadata: &AudioMetadata,
+    format: SampleFormat,
+    samples: u64,
+) -> Result<(), io::Error> {
+    write_header(writer, metadata, format, Some(samples))
+}
+
+/// Like `write_wav_header`, for when the number of samples isn't known yet, e.g. when writing to
+/// a pipe. The sizes are set to `0xFFFFFFFF`, which readers take as "until the end of the file",
+/// and a JUNK chunk reserves room for a ds64 chunk in case the file turns out to need RF64.
+pub fn write_streaming_wav_header<W: Write>(
+    writer: &mut W,
+    metadata: &AudioMetadata,
+    format: SampleFormat,
+) -> Result<(), io::Error> {
+    write_header(writer, metadata, format, None)
+}
+
+fn write_header<W: Write>(
+    writer: &mut W,
+    metadata: &AudioMetadata,
+    format: SampleFormat,
+    samples: Option<u64>,
+) -> Result<(), io::Error> {
+    let md = metadata;
+    let too_wide = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the frames are too wide for the fmt chunk to hold their size and rate",
+        )
+    };
+    let block_align = md
+        .channels
+        .checked_mul(format.bytes_per_sample())
+        .ok_or_else(too_wide)?;
+    let byte_rate = md
+        .sample_rate
+        .checked_mul(u32::from(block_align))
+        .ok_or_else(too_wide)?;
+
+    let known_samples = samples.unwrap_or(0);
+    let data_size = u64::from(format.bytes_per_sample()) * known_samples;
+    let file_size = metadata.file_size(format, known_samples);
+    let rf64 = samples.is_some() && file_size > u64::from(u32::MAX);
+    if rf64 {
+        let file_size = file_size + 8 + u64::from(AudioBuffer::DS64_CHUNK_SIZE);
+        writer.write_all(b"RF64")?;
+        writer.write_all(&u32::MAX.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"ds64")?;
+        writer.write_all(&AudioBuffer::DS64_CHUNK_SIZE.to_le_bytes())?;
+        writer.write_all(&file_size.to_le_bytes())?;
+        writer.write_all(&data_size.to_le_bytes())?;
+        writer.write_all(&(known_samples / u64::from(metadata.channels)).to_le_bytes())?;
+        // no table of other chunk sizes
+        writer.write_all(&0_u32.to_le_bytes())?;
+    } else {
+        let file_size = if samples.is_some() {
+            file_size as u32
+        } else {
+            u32::MAX
+        };
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&file_size.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+        if samples.is_none() {
+            writer.write_all(b"JUNK")?;
+            writer.write_all(&AudioBuffer::DS64_CHUNK_SIZE.to_le_bytes())?;
+            writer.write_all(&[0; AudioBuffer::DS64_CHUNK_SIZE as usize])?;
+        }
+    }
 
     writer.write_all(b"fmt ")?;
-    writer.write_all(&AudioBuffer::FMT_CHUNK_SIZE.to_le_bytes())?;
-    let md = &audio_buffer.metadata;
-    writer.write_all(&(WavFormat::FLOAT as u16).to_le_bytes())?;
+    writer.write_all(&metadata.fmt_chunk_size().to_le_bytes())?;
+    let bit_depth = format.bit_depth();
+    let format_tag = match md.channel_mask {
+        Some(_) => WAVE_FORMAT_EXTENSIBLE,
+        None => format.wav_format() as u16,
+    };
+    writer.write_all(&format_tag.to_le_bytes())?;
     writer.write_all(&md.channels.to_le_bytes())?;
     writer.write_all(&md.sample_rate.to_le_bytes())?;
-    writer.write_all(&(md.sample_rate * 32 / 8).to_le_bytes())?;
-    writer.write_all(&(4 * md.channels).to_le_bytes())?;
-    writer.write_all(&(32_u16).to_le_bytes())?;
+    writer.write_all(&byte_rate.to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&bit_depth.to_le_bytes())?;
+    if let Some(channel_mask) = md.channel_mask {
+        // the input's valid bits only still hold if the samples are stored the same way
+        let valid_bits = md
+            .valid_bits_per_sample
+            .filter(|&bits| bits <= bit_depth)
+            .filter(|_| format == md.sample_format && !format.is_float())
+            .unwrap_or(bit_depth);
+        writer.write_all(&22_u16.to_le_bytes())?;
+        writer.write_all(&valid_bits.to_le_bytes())?;
+        writer.write_all(&channel_mask.to_le_bytes())?;
+        writer.write_all(&(format.wav_format() as u16).to_le_bytes())?;
+        writer.write_all(&SUBFORMAT_GUID_TAIL)?;
+    }
+
+    // written before the data so that the header holds everything when streaming
+    for (id, body) in md.chunks.riff_chunks() {
+        writer.write_all(&id)?;
+        writer.write_all(&(body.len() as u32).to_le_bytes())?;
+        writer.write_all(&body)?;
+        if body.len() % 2 == 1 {
+            writer.write_all(&[0])?;
+        }
+    }
 
     writer.write_all(b"data")?;
-    writer.write_all(&(4 * audio_buffer.data.len()).to_le_bytes())?;
-    let (_, samples, _) = unsafe { audio_buffer.data.align_to::<u8>() };
-    writer.write_all(samples)?;
+    let data_size = if rf64 || samples.is_none() {
+        u32::MAX
+    } else {
+        data_size as u32
+    };
+    writer.write_all(&data_size.to_le_bytes())
+}
+
+/// Writes a WAV file block by block, without knowing its length up front.
+pub struct WavWriter<W: Write> {
+    writer: W,
+    format: SampleFormat,
+    channels: u16,
+    /// Metadata already written in the header.
+    header_chunks: ChunkMetadata,
+    header_size: u64,
+    samples: u64,
+}
+
+impl<W: Write> WavWriter<W> {
+    pub fn new(mut writer: W, metadata: &AudioMetadata, format: SampleFormat) -> io::Result<Self> {
+        let mut header = Vec::new();
+        write_streaming_wav_header(&mut header, metadata, format)?;
+        writer.write_all(&header)?;
+        Ok(Self {
+            writer,
+            format,
+            channels: metadata.channels,
+            header_chunks: metadata.chunks.clone(),
+            header_size: header.len() as u64,
+            samples: 0,
+        })
+    }
 
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        write_samples(&mut self.writer, samples, self.format, Endianness::Little)?;
+        self.samples += samples.len() as u64;
+        Ok(())
+    }
+
+    fn data_size(&self) -> u64 {
+        u64::from(self.format.bytes_per_sample()) * self.samples
+    }
+
+    /// Ends the data chunk, whose size is left unknown in the header.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.data_size() % 2 == 1 {
+            self.writer.write_all(&[0])?;
+        }
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Ends the data chunk and goes back to fill in the sizes, switching to RF64 if needed.
+    ///
+    /// The entries of `metadata` that aren't in the header yet, e.g. because they came after the
+    /// data in the file being processed, are written after the data.
+    pub fn finish_with_sizes(mut self, metadata: &ChunkMetadata) -> io::Result<W> {
+        let data_size = self.data_size();
+        let frames = self.samples / u64::from(self.channels);
+        let header_size = self.header_size;
+        let header_chunks = std::mem::take(&mut self.header_chunks);
+        let mut writer = self.finish()?;
+
+        let mut file_size = header_size + data_size + data_size % 2;
+        for (id, body) in metadata.missing_from(&header_chunks).riff_chunks() {
+            writer.write_all(&id)?;
+            writer.write_all(&(body.len() as u32).to_le_bytes())?;
+            writer.write_all(&body)?;
+            if body.len() % 2 == 1 {
+                writer.write_all(&[0])?;
+            }
+            file_size += 8 + body.len() as u64 + body.len() as u64 % 2;
+        }
+
+        let riff_size = file_size - 8;
+        if riff_size <= u64::from(u32::MAX) && data_size <= u64::from(u32::MAX) {
+            writer.seek(SeekFrom::Start(4))?;
+            writer.write_all(&(riff_size as u32).to_le_bytes())?;
+            writer.seek(SeekFrom::Start(header_size - 4))?;
+            writer.write_all(&(data_size as u32).to_le_bytes())?;
+        } else {
+            // the JUNK chunk becomes the ds64 chunk, the other sizes stay at 0xFFFFFFFF
+            writer.seek(SeekFrom::Start(0))?;
+            writer.write_all(b"RF64")?;
+            writer.seek(SeekFrom::Start(12))?;
+            writer.write_all(b"ds64")?;
+            writer.seek(SeekFrom::Start(20))?;
+            writer.write_all(&riff_size.to_le_bytes())?;
+            writer.write_all(&data_size.to_le_bytes())?;
+            writer.write_all(&frames.to_le_bytes())?;
+        }
+        writer.seek(SeekFrom::End(0))?;
+        Ok(writer)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+fn write_samples<W: Write>(
+    writer: &mut W,
+    samples: &[f32],
+    format: SampleFormat,
+    endianness: Endianness,
+) -> Result<(), io::Error> {
+    // encode in blocks to avoid holding a second copy of the whole file in memory
+    let mut bytes = Vec::with_capacity(4096 * usize::from(format.bytes_per_sample()));
+    for block in samples.chunks(4096) {
+        bytes.clear();
+        encode_samples(block, format, &mut bytes);
+        if endianness == Endianness::Big {
+            swap_sample_bytes(&mut bytes, format);
+        }
+        writer.write_all(&bytes)?;
+    }
     Ok(())
 }
 
-pub fn read_wav<R: Read>(reader: &mut R) -> Result<AudioBuffer, io::Error>
-where
-    R: Read,
-{
-    WavReader::new(reader).read()
+/// Reverses the bytes of each sample, converting them between little and big endian.
+fn swap_sample_bytes(bytes: &mut [u8], format: SampleFormat) {
+    let size = usize::from(format.bytes_per_sample());
+    for sample in bytes.chunks_exact_mut(size) {
+        sample.reverse();
+    }
 }
 
-struct WavReader<R: Read> {
-    reader: R,
-    format: WavFormat,
-    bit_depth: u16,
+/// Scales by 2^(bit_depth - 1) like the reader does, then rounds and clips to the integer range.
+fn quantize(sample: f32, bit_depth: u16) -> i64 {
+    let max = (1_i64 << (bit_depth - 1)) as f64;
+    (f64::from(sample) * max).round().clamp(-max, max - 1.) as i64
 }
 
-trait ReadByte: Read {
-    fn skip_bytes(&mut self, n: u64);
-    fn read_u8(&mut self) -> io::Result<u8>;
-    fn read_u16(&mut self) -> io::Result<u16>;
-    fn read_u32(&mut self) -> io::Result<u32>;
+fn encode_samples(samples: &[f32], format: SampleFormat, bytes: &mut Vec<u8>) {
+    match format {
+        SampleFormat::Pcm8 => bytes.extend(samples.iter().map(|&s| (quantize(s, 8) + 128) as u8)),
+        SampleFormat::Pcm16 => {
+            for &s in samples {
+                bytes.extend_from_slice(&(quantize(s, 16) as i16).to_le_bytes());
+            }
+        }
+        SampleFormat::Pcm24 => {
+            for &s in samples {
+                bytes.extend_from_slice(&(quantize(s, 24) as i32).to_le_bytes()[..3]);
+            }
+        }
+        SampleFormat::Pcm32 => {
+            for &s in samples {
+                bytes.extend_from_slice(&(quantize(s, 32) as i32).to_le_bytes());
+            }
+        }
+        SampleFormat::Float32 => {
+            for &s in samples {
+                bytes.extend_from_slice(&s.to_le_bytes());
+            }
+        }
+        SampleFormat::Float64 => {
+            for &s in samples {
+                bytes.extend_from_slice(&f64::from(s).to_le_bytes());
+            }
+        }
+    }
 }
 
-impl<R: Read> ReadByte for R {
-    fn skip_bytes(&mut self, n: u64) {
-        let _ = io::copy(&mut self.take(n), &mut io::sink());
+fn decode_samples(bytes: &[u8], format: SampleFormat) -> Vec<f32> {
+    // Integer samples are scaled by 2^(bit_depth - 1) so that both polarities use the same
+    // factor: the most negative value maps to exactly -1 and the most positive one to just
+    // under 1.
+    match format {
+        // 8 bit PCM is the only unsigned format, centered around 128
+        SampleFormat::Pcm8 => bytes
+            .iter()
+            .map(|&sample| (f32::from(sample) - 128.) / 128.)
+            .collect(),
+        SampleFormat::Pcm16 => bytes
+            .chunks_exact(2)
+            .map(|chunks| f32::from(i16::from_le_bytes([chunks[0], chunks[1]])) / 32_768.)
+            .collect(),
+        SampleFormat::Pcm24 => bytes
+            .chunks_exact(3)
+            .map(|chunks| {
+                // shift back down to sign extend the 24 bit value
+                let arr = [0, chunks[0], chunks[1], chunks[2]];
+                (i32::from_le_bytes(arr) >> 8) as f32 / 8_388_608.
+            })
+            .collect(),
+        SampleFormat::Pcm32 => bytes
+            .chunks_exact(4)
+            .map(|chunks| {
+                let arr = [chunks[0], chunks[1], chunks[2], chunks[3]];
+                (f64::from(i32::from_le_bytes(arr)) / 2_147_483_648.) as f32
+            })
+            .collect(),
+        SampleFormat::Float32 => bytes
+            .chunks_exact(4)
+            .map(|chunks| f32::from_le_bytes([chunks[0], chunks[1], chunks[2], chunks[3]]))
+            .collect(),
+        SampleFormat::Float64 => bytes
+            .chunks_exact(8)
+            .map(|chunks| {
+                let mut arr = [0_u8; 8];
+                arr.copy_from_slice(chunks);
+                f64::from_le_bytes(arr) as f32
+            })
+            .collect(),
     }
+}
 
-    fn read_u8(&mut self) -> io::Result<u8> {
-        let mut buf = [0_u8; 1];
-        self.read_exact(&mut buf)?;
-        Ok(buf[0])
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Container {
+    Wav,
+    Aiff,
+    Flac,
+}
+
+impl Container {
+    /// Guesses the container from a file name's extension, defaulting to WAV.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("aif") | Some("aiff") | Some("aifc") => Container::Aiff,
+            Some("flac") => Container::Flac,
+            _ => Container::Wav,
+        }
     }
 
-    fn read_u16(&mut self) -> io::Result<u16> {
-        let mut buf = [0_u8; 2];
-        self.read_exact(&mut buf)?;
-        Ok(u16::from_le_bytes(buf))
+    /// Format to write when none is asked for: `source` if the container can hold it.
+    pub fn default_format(self, source: SampleFormat) -> SampleFormat {
+        match self {
+            // a float has 24 bits of precision
+            Container::Flac if source.is_float() => SampleFormat::Pcm24,
+            _ => source,
+        }
     }
+}
 
-    fn read_u32(&mut self) -> io::Result<u32> {
-        let mut buf = [0_u8; 4];
-        self.read_exact(&mut buf)?;
-        Ok(u32::from_le_bytes(buf))
+/// Reads a WAV, AIFF or FLAC file, depending on its first bytes.
+pub fn read_audio<R: Read>(reader: &mut R) -> Result<AudioBuffer, WavError> {
+    let mut magic = [0_u8; 4];
+    reader.read_exact(&mut magic)?;
+    let mut reader = io::Cursor::new(magic).chain(reader);
+    match &magic {
+        b"RIFF" | b"RF64" | b"BW64" => read_wav(&mut reader),
+        b"FORM" => read_aiff(&mut reader),
+        b"fLaC" => read_flac(&mut reader),
+        _ => Err(WavError::BadMagic(magic)),
     }
 }
 
+pub fn write_audio<W: Write>(
+    writer: &mut W,
+    audio_buffer: &AudioBuffer,
+    container: Container,
+    format: SampleFormat,
+) -> Result<(), io::Error> {
+    match container {
+        Container::Wav => write_wav(writer, audio_buffer, format),
+        Container::Aiff => write_aiff(writer, audio_buffer, format),
+        Container::Flac => write_flac(writer, audio_buffer, format),
+    }
+}
+
+pub fn read_wav<R: Read>(reader: &mut R) -> Result<AudioBuffer, WavError> {
+    let mut wav = WavReader::new(reader)?;
+    let mut data = Vec::new();
+    while wav.read(&mut data, 1 << 16)? > 0 {}
+    Ok(AudioBuffer {
+        metadata: wav.finish(),
+        data,
+    })
+}
+
+/// Reads a WAV file block by block, for files that don't fit in memory.
+pub struct WavReader<R: Read> {
+    chunks: riff::Chunks<R>,
+    metadata: AudioMetadata,
+    bytes: Vec<u8>,
+}
+
 impl<R: Read> WavReader<R> {
-    fn new(reader: R) -> Self {
-        Self {
-            reader,
-            bit_depth: 32,
-            format: WavFormat::FLOAT,
+    /// Reads the chunks up to the data, whose samples can then be read with `read`.
+    pub fn new(reader: R) -> Result<Self, WavError> {
+        let mut chunks = riff::Chunks::new(reader)?;
+        if &chunks.form_type() != b"WAVE" {
+            return Err(WavError::BadMagic(chunks.form_type()));
         }
-    }
 
-    fn read(mut self) -> Result<AudioBuffer, io::Error> {
-        let mut buf = [0_u8; 4];
-        self.reader.read_exact(&mut buf)?;
-        if &buf != b"RIFF" {
-            panic!("no RIFF tag found");
+        let mut metadata = AudioMetadata {
+            channels: 2,
+            sample_rate: 44100,
+            valid_bits_per_sample: None,
+            channel_mask: None,
+            sample_format: SampleFormat::Float32,
+            chunks: ChunkMetadata::default(),
+        };
+        let mut format = None;
+        loop {
+            let header = chunks.next().ok_or(WavError::MissingData)??;
+            if read_chunk(&mut chunks, header, &mut metadata, &mut format)? {
+                break;
+            }
         }
+        Ok(Self {
+            chunks,
+            metadata,
+            bytes: Vec::new(),
+        })
+    }
 
-        // filesize - 8, not used right now but could be used to verify the file length
-        self.reader.skip_bytes(4);
+    /// Metadata found before the data, chunks that come after it are only known once `finish` is
+    /// called.
+    pub fn metadata(&self) -> &AudioMetadata {
+        &self.metadata
+    }
 
-        self.reader.read_exact(&mut buf)?;
-        if &buf != b"WAVE" {
-            panic!("no WAVE tag found");
+    /// Appends up to `frames` frames to `samples`, returning how many were read, 0 once all of
+    /// them have been.
+    pub fn read(&mut self, samples: &mut Vec<f32>, frames: usize) -> Result<usize, WavError> {
+        let format = self.metadata.sample_format;
+        let frame_size =
+            usize::from(self.metadata.channels.max(1)) * usize::from(format.bytes_per_sample());
+        let read = read_frames(&mut self.chunks, &mut self.bytes, frames, frame_size)?;
+        if read < frames && self.chunks.body_left() > 0 {
+            return Err(WavError::Truncated);
         }
+        samples.extend(decode_samples(&self.bytes, format));
+        Ok(read)
+    }
 
-        read_chunks(&mut self.reader)
+    /// Reads the chunks that follow the data, returning the complete metadata.
+    pub fn finish(mut self) -> AudioMetadata {
+        let mut format = Some(self.metadata.sample_format);
+        while let Some(Ok(header)) = self.chunks.next() {
+            // a broken chunk after the audio shouldn't prevent from reading it
+            if read_chunk(&mut self.chunks, header, &mut self.metadata, &mut format).is_err() {
+                break;
+            }
+        }
+        self.metadata.chunks.drop_orphan_labels();
+        self.metadata
     }
 }
 
-fn read_data<R: Read>(
-    mut wr: WavReader<R>,
-    audio_buffer: &mut AudioBuffer,
-) -> Result<&AudioBuffer, io::Error> {
-    let length = wr.reader.read_u32()? as usize;
+/// Reads up to `frames` frames into `bytes`, stopping early only at the end of `reader`. An
+/// incomplete last frame, as found in streamed files, is dropped. Returns the number of frames.
+fn read_frames<R: Read>(
+    reader: &mut R,
+    bytes: &mut Vec<u8>,
+    frames: usize,
+    frame_size: usize,
+) -> io::Result<usize> {
+    bytes.resize(frames * frame_size, 0);
+    let mut filled = 0;
+    while filled < bytes.len() {
+        match reader.read(&mut bytes[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let frames = filled / frame_size;
+    bytes.truncate(frames * frame_size);
+    Ok(frames)
+}
 
-    let mut v = Vec::with_capacity(length);
-    wr.reader.take(length as u64).read_to_end(&mut v)?;
-    match wr.format {
-        WavFormat::FLOAT => {
-            if wr.bit_depth != 32 {
-                panic!("wrong bit depth: only 32 is supported");
-            }
-            audio_buffer.data = v
-                .chunks_exact(4)
-                .map(|chunks| {
-                    f32::from_le_bytes([chunks[0], chunks[1], chunks[2], chunks[3]]) as f32
-                })
-                .collect();
-            Ok(audio_buffer)
-        }
-        WavFormat::PCM => {
-            match wr.bit_depth {
-                16 => {
-                    audio_buffer.data = v
-                        .chunks_exact(2)
-                        .map(|chunks| {
-                            f32::from(i16::from_le_bytes([chunks[0], chunks[1]]))
-                                / f32::from(i16::max_value())
-                        })
-                        .collect();
-                }
-                24 => {
-                    audio_buffer.data = v
-                        .chunks_exact(3)
-                        .map(|chunks| {
-                            let arr = [0, chunks[0], chunks[1], chunks[2]];
-                            (i32::from_le_bytes(arr)) as f32 / i32::max_value() as f32
-                        })
-                        .collect();
-                }
-                _ => panic!("{} bit not supported", wr.bit_depth),
-            }
-            Ok(audio_buffer)
+/// Reads a chunk of a WAV file, returns whether it was the data chunk, whose body is left unread.
+fn read_chunk<R: Read>(
+    chunks: &mut riff::Chunks<R>,
+    header: riff::ChunkHeader,
+    metadata: &mut AudioMetadata,
+    format: &mut Option<SampleFormat>,
+) -> Result<bool, WavError> {
+    match &header.id {
+        // the RIFF walker takes care of ds64 chunks
+        b"ds64" | b"fact" | b"JUNK" => {}
+        b"fmt " => *format = Some(read_fmt(chunks, header.size, metadata)?),
+        b"data" => {
+            metadata.sample_format = format.ok_or(WavError::MissingFmt)?;
+            return Ok(true);
         }
+        b"LIST" | b"cue " | b"smpl" | b"inst" | b"bext" => {
+            let body = chunks.read_body()?;
+            metadata.chunks.read_riff_chunk(&header.id, &body);
+        }
+        tag => eprintln!(
+            "unknown chunk {}",
+            std::str::from_utf8(tag).unwrap_or("which can't be printed")
+        ),
     }
+    Ok(false)
 }
 
-fn read_chunks<R: Read>(reader: R) -> Result<AudioBuffer, io::Error> {
-    fn _read_chunks<R: Read>(
-        mut wr: WavReader<R>,
-        audio_buffer: &mut AudioBuffer,
-    ) -> Result<&AudioBuffer, io::Error> {
+fn read_fmt<R: Read>(
+    reader: &mut R,
+    chunk_size: u64,
+    metadata: &mut AudioMetadata,
+) -> Result<SampleFormat, WavError> {
+    let mut format_tag = reader.read_u16()?;
+    metadata.channels = reader.read_u16()?;
+    metadata.sample_rate = reader.read_u32()?;
+    if metadata.channels == 0 {
+        return Err(WavError::InvalidHeader("no channels"));
+    }
+    if metadata.sample_rate == 0 {
+        return Err(WavError::InvalidHeader("zero sample rate"));
+    }
+    // byte rate and block align
+    reader.skip_bytes(6)?;
+    let bit_depth = reader.read_u16()?;
+    if format_tag == WAVE_FORMAT_EXTENSIBLE {
+        if chunk_size < u64::from(AudioBuffer::FMT_EXTENSIBLE_CHUNK_SIZE) {
+            return Err(WavError::UnsupportedCodec(format_tag));
+        }
+        // size of the extension, always 22 for WAVE_FORMAT_EXTENSIBLE
+        reader.skip_bytes(2)?;
+        metadata.valid_bits_per_sample = Some(reader.read_u16()?);
+        metadata.channel_mask = Some(reader.read_u32()?);
+        let sub_format = reader.read_u16()?;
+        let mut guid_tail = [0_u8; 14];
+        reader.read_exact(&mut guid_tail)?;
+        if guid_tail != SUBFORMAT_GUID_TAIL {
+            return Err(WavError::UnsupportedCodec(format_tag));
+        }
+        format_tag = sub_format;
+    }
+    let format = match format_tag {
+        0x0001 => WavFormat::PCM,
+        0x0003 => WavFormat::FLOAT,
+        x => return Err(WavError::UnsupportedCodec(x)),
+    };
+    SampleFormat::from_wav(format, bit_depth).ok_or(WavError::UnsupportedBitDepth(bit_depth))
+}
+
+trait ReadByte: Read {
+    fn skip_bytes(&mut self, n: u64) -> io::Result<()>;
+    fn read_tag(&mut self) -> io::Result<Option<[u8; 4]>>;
+    fn read_u16(&mut self) -> io::Result<u16>;
+    fn read_u32(&mut self) -> io::Result<u32>;
+}
+
+impl<R: Read> ReadByte for R {
+    fn skip_bytes(&mut self, n: u64) -> io::Result<()> {
+        if io::copy(&mut self.take(n), &mut io::sink())? < n {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    /// Reads a four-character code, or returns `None` if the reader is already at its end.
+    fn read_tag(&mut self) -> io::Result<Option<[u8; 4]>> {
         let mut buf = [0_u8; 4];
-        wr.reader.read_exact(&mut buf)?;
-        match &buf {
-            b"fact" => {
-                let chunk_size = wr.reader.read_u32()?;
-                wr.reader.skip_bytes(u64::from(chunk_size));
-                _read_chunks(wr, audio_buffer)
-            }
-            b"fmt " => {
-                let chunk_size = wr.reader.read_u32()?;
-                wr.format = match wr.reader.read_u16()? {
-                    0x0001 => WavFormat::PCM,
-                    0x0003 => WavFormat::FLOAT,
-                    x => panic!("unsupported format {}", x),
-                };
-                audio_buffer.metadata.channels = wr.reader.read_u16()?;
-                audio_buffer.metadata.sample_rate = wr.reader.read_u32()?;
-                wr.reader.skip_bytes(6);
-                wr.bit_depth = wr.reader.read_u16()?;
-                wr.reader.skip_bytes(u64::from(chunk_size) - 16);
-                _read_chunks(wr, audio_buffer)
-            }
-            b"data" => read_data(wr, audio_buffer),
-            tag => {
-                eprintln!(
-                    "unknown chunk {}",
-                    std::str::from_utf8(tag).unwrap_or("which can't be printed")
-                );
-                let chunk_size = wr.reader.read_u32()?;
-                wr.reader.skip_bytes(u64::from(chunk_size));
-                _read_chunks(wr, audio_buffer)
+        let mut read = 0;
+        while read < buf.len() {
+            match self.read(&mut buf[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => read += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
             }
         }
+        Ok(Some(buf))
     }
 
-    let mut audio_buffer = AudioBuffer {
-        metadata: AudioMetadata {
-            channels: 2,
-            sample_rate: 44100,
-        },
-        data: Vec::new(),
-    };
-    _read_chunks(WavReader::new(reader), &mut audio_buffer)?;
-    Ok(audio_buffer)
+    fn read_u16(&mut self) -> io::Result<u16> {
+        let mut buf = [0_u8; 2];
+        self.read_exact(&mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut buf = [0_u8; 4];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
 }
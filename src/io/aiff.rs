@@ -0,0 +1,454 @@
+use super::{decode_samples, riff, swap_sample_bytes, write_samples, Endianness, WavError};
+use crate::metadata::{ChunkMetadata, CuePoint, Instrument, SampleLoop, Sampler};
+use crate::types::*;
+
+use std::io;
+use std::io::{Read, Write};
+
+/// Version of the AIFF-C specification, the only one that exists.
+const AIFC_VERSION: u32 = 0xA280_5140;
+
+/// Text chunks and the LIST/INFO entries they correspond to.
+const TEXT_CHUNKS: [(&[u8; 4], &[u8; 4]); 4] = [
+    (b"NAME", b"INAM"),
+    (b"AUTH", b"IART"),
+    (b"(c) ", b"ICOP"),
+    (b"ANNO", b"ICMT"),
+];
+
+struct Comm {
+    channels: u16,
+    frames: u32,
+    bit_depth: u16,
+    sample_rate: f64,
+    compression: [u8; 4],
+}
+
+struct Marker {
+    id: u16,
+    position: u32,
+    name: String,
+}
+
+pub fn read_aiff<R: Read>(reader: &mut R) -> Result<AudioBuffer, WavError> {
+    let mut chunks = riff::Chunks::new(reader)?;
+    let aifc = match &chunks.form_type() {
+        b"AIFF" => false,
+        b"AIFC" => true,
+        _ => return Err(WavError::BadMagic(chunks.form_type())),
+    };
+
+    let mut comm = None;
+    let mut sound = None;
+    let mut markers = Vec::new();
+    let mut instrument = None;
+    let mut info = Vec::new();
+    while let Some(header) = chunks.next() {
+        let result = header.and_then(|header| {
+            match &header.id {
+                b"COMM" => comm = Some(read_comm(&chunks.read_body()?, aifc)?),
+                b"SSND" => {
+                    let body = chunks.read_body()?;
+                    let offset = 8 + be_u32(&body, 0) as usize;
+                    sound = Some(body.get(offset..).unwrap_or_default().to_vec());
+                }
+                b"MARK" => markers = read_markers(&chunks.read_body()?),
+                b"INST" => instrument = Some(chunks.read_body()?),
+                id => {
+                    if let Some((_, info_id)) = TEXT_CHUNKS.iter().find(|(text, _)| *text == id) {
+                        let text = chunks.read_body()?;
+                        let end = text.iter().position(|&b| b == 0).unwrap_or(text.len());
+                        let text = String::from_utf8_lossy(&text[..end]).into_owned();
+                        info.push((**info_id, text));
+                    }
+                }
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => {}
+            // a broken chunk after the audio shouldn't prevent from reading it
+            Err(_) if sound.is_some() && comm.is_some() => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    let comm = comm.ok_or(WavError::MissingFmt)?;
+    let mut sound = sound.ok_or(WavError::MissingData)?;
+    let (format, endianness) = sample_format(&comm)?;
+    let size =
+        comm.frames as usize * usize::from(comm.channels) * usize::from(format.bytes_per_sample());
+    if sound.len() < size {
+        return Err(WavError::Truncated);
+    }
+    sound.truncate(size);
+    if endianness == Endianness::Big {
+        swap_sample_bytes(&mut sound, format);
+    }
+    if format == SampleFormat::Pcm8 {
+        // 8 bit AIFF samples are signed, unlike WAV's
+        for b in &mut sound {
+            *b ^= 0x80;
+        }
+    }
+
+    let mut chunks = ChunkMetadata {
+        info,
+        ..ChunkMetadata::default()
+    };
+    if let Some(instrument) = instrument {
+        read_instrument(&instrument, &markers, comm.sample_rate, &mut chunks);
+    }
+    // markers that only delimit loops are part of the sampler metadata
+    let loop_markers: Vec<u32> = chunks
+        .sampler
+        .iter()
+        .flat_map(|sampler| sampler.loops.iter())
+        .flat_map(|l| vec![l.start, l.end.saturating_add(1)])
+        .collect();
+    for marker in markers {
+        if !loop_markers.contains(&marker.position) {
+            chunks.cues.push(CuePoint {
+                id: u32::from(marker.id),
+                position: marker.position,
+                label: Some(marker.name).filter(|name| !name.is_empty()),
+            })
+        }
+    }
+
+    Ok(AudioBuffer {
+        metadata: AudioMetadata {
+            channels: comm.channels,
+            sample_rate: comm.sample_rate.round() as u32,
+            valid_bits_per_sample: None,
+            channel_mask: None,
+            sample_format: format,
+            chunks,
+        },
+        data: decode_samples(&sound, format),
+    })
+}
+
+/// Writes `audio_buffer` as an AIFF file, or as an AIFF-C file for floating point formats.
+pub fn write_aiff<W: Write>(
+    writer: &mut W,
+    audio_buffer: &AudioBuffer,
+    format: SampleFormat,
+) -> Result<(), io::Error> {
+    let md = &audio_buffer.metadata;
+    let invalid = |message| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+    if md.channels == 0 {
+        return invalid("AIFF needs at least one channel");
+    }
+    let aifc = format.is_float();
+
+    let mut chunks: Vec<(&[u8; 4], Vec<u8>)> = Vec::new();
+    if aifc {
+        chunks.push((b"FVER", AIFC_VERSION.to_be_bytes().to_vec()));
+    }
+
+    let mut comm = Vec::new();
+    comm.extend_from_slice(&md.channels.to_be_bytes());
+    let frames = audio_buffer.data.len() / usize::from(md.channels);
+    comm.extend_from_slice(&(frames as u32).to_be_bytes());
+    comm.extend_from_slice(&format.bit_depth().to_be_bytes());
+    comm.extend_from_slice(&f64_to_extended(f64::from(md.sample_rate)));
+    match format {
+        SampleFormat::Float32 => {
+            comm.extend_from_slice(b"fl32");
+            put_pstring(&mut comm, "32-bit floating point");
+        }
+        SampleFormat::Float64 => {
+            comm.extend_from_slice(b"fl64");
+            put_pstring(&mut comm, "64-bit floating point");
+        }
+        _ => {}
+    }
+    chunks.push((b"COMM", comm));
+
+    let (markers, instrument) = markers_and_instrument(&md.chunks);
+    if !markers.is_empty() {
+        let mut mark = (markers.len() as u16).to_be_bytes().to_vec();
+        for marker in &markers {
+            mark.extend_from_slice(&marker.id.to_be_bytes());
+            mark.extend_from_slice(&marker.position.to_be_bytes());
+            put_pstring(&mut mark, &marker.name);
+        }
+        chunks.push((b"MARK", mark));
+    }
+    if let Some(instrument) = instrument {
+        chunks.push((b"INST", instrument));
+    }
+    for (id, text) in &md.chunks.info {
+        if let Some((text_id, _)) = TEXT_CHUNKS.iter().find(|(_, info)| *info == id) {
+            chunks.push((text_id, text.as_bytes().to_vec()));
+        }
+    }
+
+    let data_size = audio_buffer.data.len() as u64 * u64::from(format.bytes_per_sample());
+    let form_size = 4
+        + chunks
+            .iter()
+            .map(|(_, body)| 8 + body.len() as u64 + body.len() as u64 % 2)
+            .sum::<u64>()
+        + 8
+        + 8
+        + data_size
+        + data_size % 2;
+    // which also bounds the frame count and the size of every chunk
+    if form_size > u64::from(u32::MAX) {
+        return invalid("AIFF can't store more than 4GiB");
+    }
+
+    writer.write_all(b"FORM")?;
+    writer.write_all(&(form_size as u32).to_be_bytes())?;
+    writer.write_all(if aifc { b"AIFC" } else { b"AIFF" })?;
+    for (id, body) in chunks {
+        writer.write_all(id)?;
+        writer.write_all(&(body.len() as u32).to_be_bytes())?;
+        writer.write_all(&body)?;
+        if body.len() % 2 == 1 {
+            writer.write_all(&[0])?;
+        }
+    }
+
+    writer.write_all(b"SSND")?;
+    writer.write_all(&(8 + data_size as u32).to_be_bytes())?;
+    // offset and block size, neither is used
+    writer.write_all(&[0; 8])?;
+    if format == SampleFormat::Pcm8 {
+        let signed: Vec<u8> = audio_buffer
+            .data
+            .iter()
+            .map(|&s| ((s * 128.).round().clamp(-128., 127.) as i8) as u8)
+            .collect();
+        writer.write_all(&signed)?;
+    } else {
+        write_samples(writer, &audio_buffer.data, format, Endianness::Big)?;
+    }
+    if data_size % 2 == 1 {
+        writer.write_all(&[0])?;
+    }
+    Ok(())
+}
+
+fn sample_format(comm: &Comm) -> Result<(SampleFormat, Endianness), WavError> {
+    let pcm = |bit_depth| match bit_depth {
+        1..=8 => Ok(SampleFormat::Pcm8),
+        9..=16 => Ok(SampleFormat::Pcm16),
+        17..=24 => Ok(SampleFormat::Pcm24),
+        25..=32 => Ok(SampleFormat::Pcm32),
+        x => Err(WavError::UnsupportedBitDepth(x)),
+    };
+    match &comm.compression {
+        b"NONE" | b"twos" => Ok((pcm(comm.bit_depth)?, Endianness::Big)),
+        b"sowt" => Ok((pcm(comm.bit_depth)?, Endianness::Little)),
+        b"fl32" | b"FL32" => Ok((SampleFormat::Float32, Endianness::Big)),
+        b"fl64" | b"FL64" => Ok((SampleFormat::Float64, Endianness::Big)),
+        x => Err(WavError::UnsupportedCompression(*x)),
+    }
+}
+
+fn read_comm(body: &[u8], aifc: bool) -> Result<Comm, WavError> {
+    if body.len() < if aifc { 22 } else { 18 } {
+        return Err(WavError::Truncated);
+    }
+    let mut extended = [0; 10];
+    extended.copy_from_slice(&body[8..18]);
+    let mut compression = *b"NONE";
+    if aifc {
+        compression.copy_from_slice(&body[18..22]);
+    }
+    let comm = Comm {
+        channels: u16::from_be_bytes([body[0], body[1]]),
+        frames: be_u32(body, 2),
+        bit_depth: u16::from_be_bytes([body[6], body[7]]),
+        sample_rate: extended_to_f64(extended),
+        compression,
+    };
+    if comm.channels == 0 {
+        return Err(WavError::InvalidHeader("no channels"));
+    }
+    if !(1.0..=f64::from(u32::MAX)).contains(&comm.sample_rate.round()) {
+        return Err(WavError::InvalidHeader("sample rate out of range"));
+    }
+    Ok(comm)
+}
+
+fn read_markers(body: &[u8]) -> Vec<Marker> {
+    if body.len() < 2 {
+        return Vec::new();
+    }
+    let count = u16::from_be_bytes([body[0], body[1]]);
+    let mut markers = Vec::new();
+    let mut offset = 2;
+    for _ in 0..count {
+        if offset + 7 > body.len() {
+            break;
+        }
+        let name_len = usize::from(body[offset + 6]);
+        let name = body
+            .get(offset + 7..offset + 7 + name_len)
+            .unwrap_or_default();
+        markers.push(Marker {
+            id: u16::from_be_bytes([body[offset], body[offset + 1]]),
+            position: be_u32(body, offset + 2),
+            name: String::from_utf8_lossy(name).into_owned(),
+        });
+        // the name is padded so that the count and the text have an even length
+        offset += 6 + 1 + name_len + (name_len + 1) % 2;
+    }
+    markers
+}
+
+fn read_instrument(body: &[u8], markers: &[Marker], sample_rate: f64, chunks: &mut ChunkMetadata) {
+    if body.len() < 20 {
+        return;
+    }
+    let gain = i16::from_be_bytes([body[6], body[7]]);
+    chunks.instrument = Some(Instrument {
+        unshifted_note: body[0],
+        fine_tune: body[1] as i8,
+        gain: gain.clamp(i16::from(i8::MIN), i16::from(i8::MAX)) as i8,
+        low_note: body[2],
+        high_note: body[3],
+        low_velocity: body[4],
+        high_velocity: body[5],
+    });
+
+    let position = |id: u16| markers.iter().find(|m| m.id == id).map(|m| m.position);
+    let mut loops = Vec::new();
+    // sustain loop, then release loop
+    for (i, offset) in [8, 14].iter().enumerate() {
+        let play_mode = u16::from_be_bytes([body[*offset], body[offset + 1]]);
+        let begin = u16::from_be_bytes([body[offset + 2], body[offset + 3]]);
+        let end = u16::from_be_bytes([body[offset + 4], body[offset + 5]]);
+        if let (1..=2, Some(start), Some(end)) = (play_mode, position(begin), position(end)) {
+            loops.push(SampleLoop {
+                id: i as u32,
+                kind: u32::from(play_mode - 1),
+                start,
+                // AIFF loops end before the end marker, smpl ones on their last sample
+                end: end.saturating_sub(1),
+                fraction: 0,
+                play_count: 0,
+            })
+        }
+    }
+    chunks.sampler = Some(Sampler {
+        sample_period: (1e9 / sample_rate).round() as u32,
+        midi_unity_note: u32::from(body[0]),
+        loops,
+        ..Sampler::default()
+    });
+}
+
+/// Builds the markers for the cue points and the loops, and the INST chunk that refers to them.
+fn markers_and_instrument(chunks: &ChunkMetadata) -> (Vec<Marker>, Option<Vec<u8>>) {
+    // marker ids are 16 bit, keep room for the four loop markers
+    let mut markers: Vec<Marker> = chunks
+        .cues
+        .iter()
+        .take(usize::from(u16::MAX) - 4)
+        .enumerate()
+        .map(|(i, cue)| Marker {
+            id: i as u16 + 1,
+            position: cue.position,
+            name: cue.label.clone().unwrap_or_default(),
+        })
+        .collect();
+
+    let loops = chunks.sampler.as_ref().map_or(&[][..], |s| &s.loops[..]);
+    if chunks.instrument.is_none() && loops.is_empty() {
+        return (markers, None);
+    }
+
+    let mut inst = match &chunks.instrument {
+        Some(i) => vec![
+            i.unshifted_note,
+            i.fine_tune as u8,
+            i.low_note,
+            i.high_note,
+            i.low_velocity,
+            i.high_velocity,
+            0,
+            i.gain as u8,
+        ],
+        None => {
+            let note = chunks
+                .sampler
+                .as_ref()
+                .map_or(60, |s| s.midi_unity_note as u8);
+            vec![note, 0, 0, 127, 1, 127, 0, 0]
+        }
+    };
+    // sign extend the gain to 16 bits
+    inst[6] = if (inst[7] as i8) < 0 { 0xFF } else { 0 };
+
+    for i in 0..2 {
+        match loops.get(i) {
+            Some(l) => {
+                let begin = markers.len() as u16 + 1;
+                for (position, name) in
+                    [(l.start, "beg loop"), (l.end.saturating_add(1), "end loop")]
+                {
+                    markers.push(Marker {
+                        id: markers.len() as u16 + 1,
+                        position,
+                        name: String::from(name),
+                    });
+                }
+                inst.extend_from_slice(&(l.kind.min(1) as u16 + 1).to_be_bytes());
+                inst.extend_from_slice(&begin.to_be_bytes());
+                inst.extend_from_slice(&(begin + 1).to_be_bytes());
+            }
+            None => inst.extend_from_slice(&[0; 6]),
+        }
+    }
+    (markers, Some(inst))
+}
+
+fn put_pstring(body: &mut Vec<u8>, s: &str) {
+    let bytes = &s.as_bytes()[..s.len().min(255)];
+    body.push(bytes.len() as u8);
+    body.extend_from_slice(bytes);
+    if bytes.len() % 2 == 0 {
+        body.push(0);
+    }
+}
+
+fn be_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut buf = [0; 4];
+    if let Some(b) = bytes.get(offset..offset + 4) {
+        buf.copy_from_slice(b);
+    }
+    u32::from_be_bytes(buf)
+}
+
+/// Converts an 80 bit IEEE 754 extended precision number, as used for AIFF sample rates.
+fn extended_to_f64(bytes: [u8; 10]) -> f64 {
+    let sign = if bytes[0] & 0x80 != 0 { -1. } else { 1. };
+    let exponent = i32::from(u16::from_be_bytes([bytes[0] & 0x7F, bytes[1]]));
+    let mut mantissa = [0; 8];
+    mantissa.copy_from_slice(&bytes[2..]);
+    let mantissa = u64::from_be_bytes(mantissa);
+    if exponent == 0 && mantissa == 0 {
+        return 0.;
+    }
+    sign * mantissa as f64 * 2_f64.powi(exponent - 16383 - 63)
+}
+
+fn f64_to_extended(x: f64) -> [u8; 10] {
+    let mut bytes = [0; 10];
+    if x == 0. {
+        return bytes;
+    }
+    let bits = x.abs().to_bits();
+    // only normal numbers are expected for sample rates
+    let exponent = ((bits >> 52) & 0x7FF) as i32 - 1023 + 16383;
+    let mantissa = (1 << 63) | ((bits & ((1 << 52) - 1)) << 11);
+    let sign = if x < 0. { 0x8000 } else { 0 };
+    bytes[..2].copy_from_slice(&(sign | exponent as u16).to_be_bytes());
+    bytes[2..].copy_from_slice(&mantissa.to_be_bytes());
+    bytes
+}
@@ -0,0 +1,963 @@
+//! Free Lossless Audio Codec, as described by RFC 9639.
+//!
+//! The decoder handles everything the format allows. The encoder sticks to the fixed predictors,
+//! picking the best one for each channel of each block along with the best stereo decorrelation.
+
+use super::{quantize, ReadByte, WavError};
+use crate::metadata::ChunkMetadata;
+use crate::types::*;
+
+use std::io;
+use std::io::{Read, Write};
+use std::num::Wrapping;
+
+/// Samples per channel in each frame written.
+const BLOCK_SIZE: usize = 4096;
+
+const STREAMINFO: u8 = 0;
+const VORBIS_COMMENT: u8 = 4;
+
+/// Vorbis comment fields and the LIST/INFO entries they correspond to.
+const VORBIS_TAGS: [(&str, &[u8; 4]); 8] = [
+    ("TITLE", b"INAM"),
+    ("ARTIST", b"IART"),
+    ("ALBUM", b"IPRD"),
+    ("GENRE", b"IGNR"),
+    ("DATE", b"ICRD"),
+    ("TRACKNUMBER", b"ITRK"),
+    ("COMMENT", b"ICMT"),
+    ("COPYRIGHT", b"ICOP"),
+];
+
+/// Sample rates that frame headers can encode directly, indexed by their code.
+const SAMPLE_RATES: [u32; 12] = [
+    0, 88200, 176_400, 192_000, 8000, 16000, 22050, 24000, 32000, 44100, 48000, 96000,
+];
+
+/// Bit depths that frame headers can encode directly, indexed by their code.
+const BIT_DEPTHS: [u32; 8] = [0, 8, 12, 0, 16, 20, 24, 32];
+
+struct StreamInfo {
+    sample_rate: u32,
+    channels: usize,
+    bit_depth: u32,
+    /// Samples per channel, 0 when unknown.
+    frames: u64,
+}
+
+/// How the two channels of a stereo frame are stored.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stereo {
+    Independent,
+    LeftSide,
+    SideRight,
+    MidSide,
+}
+
+pub fn read_flac<R: Read>(reader: &mut R) -> Result<AudioBuffer, WavError> {
+    let mut magic = [0_u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != b"fLaC" {
+        return Err(WavError::BadMagic(magic));
+    }
+
+    let mut info = None;
+    let mut chunks = ChunkMetadata::default();
+    loop {
+        let mut header = [0_u8; 4];
+        reader.read_exact(&mut header)?;
+        let size = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+        match header[0] & 0x7F {
+            STREAMINFO => {
+                let mut body = vec![0; size as usize];
+                reader.read_exact(&mut body)?;
+                info = Some(read_stream_info(&body)?);
+            }
+            VORBIS_COMMENT => {
+                let mut body = vec![0; size as usize];
+                reader.read_exact(&mut body)?;
+                read_vorbis_comment(&body, &mut chunks);
+            }
+            // padding, seek tables, pictures...
+            _ => reader.skip_bytes(u64::from(size))?,
+        }
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+    let info = info.ok_or(WavError::MissingFmt)?;
+
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let scale = 1. / (1_u64 << (info.bit_depth - 1)) as f64;
+    let wanted = (info.frames as usize).saturating_mul(info.channels);
+    // the frame count comes from the file, which could claim anything
+    let mut data = Vec::with_capacity(wanted.min(1 << 24));
+    let mut position = 0;
+    // stop at the announced length in case a tag follows the frames
+    while position < bytes.len() && (info.frames == 0 || data.len() < wanted) {
+        let (channels, size) = read_frame(&bytes[position..], &info)?;
+        position += size;
+        for i in 0..channels[0].len() {
+            data.extend(channels.iter().map(|c| (c[i] as f64 * scale) as f32));
+        }
+    }
+    if data.len() < wanted {
+        return Err(WavError::Truncated);
+    }
+    if info.frames != 0 {
+        data.truncate(wanted);
+    }
+
+    let sample_format = match info.bit_depth {
+        0..=8 => SampleFormat::Pcm8,
+        9..=16 => SampleFormat::Pcm16,
+        17..=24 => SampleFormat::Pcm24,
+        _ => SampleFormat::Pcm32,
+    };
+    let bit_depth = info.bit_depth as u16;
+    Ok(AudioBuffer {
+        metadata: AudioMetadata {
+            channels: info.channels as u16,
+            sample_rate: info.sample_rate,
+            valid_bits_per_sample: Some(bit_depth).filter(|&b| b != sample_format.bit_depth()),
+            channel_mask: None,
+            sample_format,
+            chunks,
+        },
+        data,
+    })
+}
+
+fn read_stream_info(body: &[u8]) -> Result<StreamInfo, WavError> {
+    let mut r = BitReader::new(body);
+    // block and frame sizes, only useful to allocate buffers up front
+    r.bits(16 + 16)?;
+    r.bits(24 + 24)?;
+    let info = StreamInfo {
+        sample_rate: r.bits(20)? as u32,
+        channels: r.bits(3)? as usize + 1,
+        bit_depth: r.bits(5)? as u32 + 1,
+        frames: r.bits(36)?,
+    };
+    if info.bit_depth < 4 {
+        return Err(WavError::UnsupportedBitDepth(info.bit_depth as u16));
+    }
+    if info.sample_rate == 0 {
+        return Err(WavError::InvalidHeader("zero sample rate"));
+    }
+    Ok(info)
+}
+
+/// Maps the fields of a VORBIS_COMMENT block to LIST/INFO entries, stopping at the first
+/// malformed one.
+fn read_vorbis_comment(mut body: &[u8], chunks: &mut ChunkMetadata) -> Option<()> {
+    fn u32_le(body: &mut &[u8]) -> Option<u32> {
+        let bytes = body.get(..4)?;
+        let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        *body = &body[4..];
+        Some(value)
+    }
+    fn string(body: &mut &[u8]) -> Option<String> {
+        let len = u32_le(body)? as usize;
+        let bytes = body.get(..len)?;
+        *body = &body[len..];
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    // vendor string
+    string(&mut body)?;
+    for _ in 0..u32_le(&mut body)? {
+        let comment = string(&mut body)?;
+        if let Some((name, value)) = comment.split_once('=') {
+            let tag = VORBIS_TAGS
+                .iter()
+                .find(|(tag, _)| tag.eq_ignore_ascii_case(name));
+            if let Some((_, id)) = tag {
+                chunks.info.push((**id, value.to_string()));
+            }
+        }
+    }
+    Some(())
+}
+
+/// Decodes the frame at the start of `bytes`, returning its channels and its size in bytes.
+fn read_frame(bytes: &[u8], info: &StreamInfo) -> Result<(Vec<Vec<i64>>, usize), WavError> {
+    let mut r = BitReader::new(bytes);
+    // sync code, reserved bit and blocking strategy
+    if r.bits(15)? != 0b111_1111_1111_1100 {
+        return Err(WavError::Corrupt("lost frame sync"));
+    }
+    r.bits(1)?;
+    let block_size_code = r.bits(4)?;
+    let sample_rate_code = r.bits(4)?;
+    let channel_code = r.bits(4)?;
+    let bit_depth = match r.bits(3)? as usize {
+        0 => info.bit_depth,
+        code if BIT_DEPTHS[code] != 0 => BIT_DEPTHS[code],
+        _ => return Err(WavError::Corrupt("reserved bit depth")),
+    };
+    r.bits(1)?;
+    // frame or sample number, coded like UTF-8
+    let first = r.bits(8)?;
+    let continuation_bytes = match (first as u8).leading_ones() {
+        0 => 0,
+        n @ 2..=7 => n - 1,
+        _ => return Err(WavError::Corrupt("bad frame number")),
+    };
+    for _ in 0..continuation_bytes {
+        if r.bits(8)? >> 6 != 0b10 {
+            return Err(WavError::Corrupt("bad frame number"));
+        }
+    }
+    let block_size = match block_size_code {
+        0 => return Err(WavError::Corrupt("reserved block size")),
+        1 => 192,
+        2..=5 => 576 << (block_size_code - 2),
+        6 => r.bits(8)? as usize + 1,
+        7 => r.bits(16)? as usize + 1,
+        _ => 256 << (block_size_code - 8),
+    };
+    match sample_rate_code {
+        12 => r.bits(8)?,
+        13 | 14 => r.bits(16)?,
+        15 => return Err(WavError::Corrupt("reserved sample rate")),
+        _ => 0,
+    };
+    let header_size = r.position / 8;
+    if r.bits(8)? as u8 != crc8(&bytes[..header_size]) {
+        return Err(WavError::Corrupt("frame header checksum mismatch"));
+    }
+
+    let (channels, stereo) = match channel_code {
+        0..=7 => (channel_code as usize + 1, Stereo::Independent),
+        8 => (2, Stereo::LeftSide),
+        9 => (2, Stereo::SideRight),
+        10 => (2, Stereo::MidSide),
+        _ => return Err(WavError::Corrupt("reserved channel assignment")),
+    };
+    if channels != info.channels {
+        return Err(WavError::Corrupt("channel count changed"));
+    }
+    let mut subframes = Vec::with_capacity(channels);
+    for channel in 0..channels {
+        // the side channel needs an extra bit
+        let side = match stereo {
+            Stereo::Independent => false,
+            Stereo::SideRight => channel == 0,
+            _ => channel == 1,
+        };
+        subframes.push(read_subframe(&mut r, block_size, bit_depth + side as u32)?);
+    }
+
+    r.align();
+    let frame_size = r.position / 8;
+    if r.bits(16)? as u16 != crc16(&bytes[..frame_size]) {
+        return Err(WavError::Corrupt("frame checksum mismatch"));
+    }
+
+    if stereo != Stereo::Independent {
+        let (a, b) = subframes.split_at_mut(1);
+        for (a, b) in a[0].iter_mut().zip(b[0].iter_mut()) {
+            let (left, right) = match stereo {
+                Stereo::LeftSide => (*a, a.wrapping_sub(*b)),
+                Stereo::SideRight => (a.wrapping_add(*b), *b),
+                _ => {
+                    let mid = (*a << 1) | (*b & 1);
+                    (mid.wrapping_add(*b) >> 1, mid.wrapping_sub(*b) >> 1)
+                }
+            };
+            *a = left;
+            *b = right;
+        }
+    }
+    Ok((subframes, frame_size + 2))
+}
+
+fn read_subframe(
+    r: &mut BitReader,
+    block_size: usize,
+    bit_depth: u32,
+) -> Result<Vec<i64>, WavError> {
+    if r.bits(1)? != 0 {
+        return Err(WavError::Corrupt("bad subframe padding"));
+    }
+    let kind = r.bits(6)?;
+    let wasted_bits = if r.bits(1)? == 1 {
+        r.unary()? as u32 + 1
+    } else {
+        0
+    };
+    if wasted_bits >= bit_depth {
+        return Err(WavError::Corrupt("too many wasted bits"));
+    }
+    let bit_depth = bit_depth - wasted_bits;
+
+    let mut samples = Vec::with_capacity(block_size);
+    match kind {
+        0 => samples.resize(block_size, r.signed(bit_depth)?),
+        1 => {
+            for _ in 0..block_size {
+                samples.push(r.signed(bit_depth)?);
+            }
+        }
+        8..=12 => {
+            let order = kind as usize - 8;
+            read_warm_up(r, order, block_size, bit_depth, &mut samples)?;
+            read_residual(r, order, block_size, &mut samples)?;
+            for i in order..block_size {
+                let s = |back: usize| Wrapping(samples[i - back]);
+                // corrupt residuals could overflow, and are only caught by the frame checksum
+                let prediction = match order {
+                    0 => Wrapping(0),
+                    1 => s(1),
+                    2 => Wrapping(2) * s(1) - s(2),
+                    3 => Wrapping(3) * s(1) - Wrapping(3) * s(2) + s(3),
+                    _ => Wrapping(4) * s(1) - Wrapping(6) * s(2) + Wrapping(4) * s(3) - s(4),
+                }
+                .0;
+                samples[i] = samples[i].wrapping_add(prediction);
+            }
+        }
+        32..=63 => {
+            let order = kind as usize - 31;
+            read_warm_up(r, order, block_size, bit_depth, &mut samples)?;
+            let precision = r.bits(4)? as u32 + 1;
+            if precision == 16 {
+                return Err(WavError::Corrupt("bad LPC precision"));
+            }
+            let shift = r.signed(5)?;
+            if shift < 0 {
+                return Err(WavError::Corrupt("negative LPC shift"));
+            }
+            let mut coefficients = Vec::with_capacity(order);
+            for _ in 0..order {
+                coefficients.push(r.signed(precision)?);
+            }
+            read_residual(r, order, block_size, &mut samples)?;
+            for i in order..block_size {
+                let prediction = coefficients
+                    .iter()
+                    .zip(samples[i - order..i].iter().rev())
+                    .fold(0_i64, |sum, (c, s)| sum.wrapping_add(c.wrapping_mul(*s)));
+                samples[i] = samples[i].wrapping_add(prediction >> shift);
+            }
+        }
+        _ => return Err(WavError::Corrupt("reserved subframe type")),
+    }
+
+    if wasted_bits > 0 {
+        for s in &mut samples {
+            *s <<= wasted_bits;
+        }
+    }
+    Ok(samples)
+}
+
+fn read_warm_up(
+    r: &mut BitReader,
+    order: usize,
+    block_size: usize,
+    bit_depth: u32,
+    samples: &mut Vec<i64>,
+) -> Result<(), WavError> {
+    if order > block_size {
+        return Err(WavError::Corrupt("predictor order exceeds block size"));
+    }
+    for _ in 0..order {
+        samples.push(r.signed(bit_depth)?);
+    }
+    Ok(())
+}
+
+/// Reads the Rice coded residual of a predicted subframe, appending it to `samples`.
+fn read_residual(
+    r: &mut BitReader,
+    order: usize,
+    block_size: usize,
+    samples: &mut Vec<i64>,
+) -> Result<(), WavError> {
+    let parameter_bits = match r.bits(2)? {
+        0 => 4,
+        1 => 5,
+        _ => return Err(WavError::Corrupt("reserved residual coding method")),
+    };
+    let escape = (1 << parameter_bits) - 1;
+    let partition_order = r.bits(4)?;
+    let partition_size = block_size >> partition_order;
+    if partition_size << partition_order != block_size || partition_size < order {
+        return Err(WavError::Corrupt("bad partition order"));
+    }
+    for partition in 0..1 << partition_order {
+        let count = if partition == 0 {
+            partition_size - order
+        } else {
+            partition_size
+        };
+        let parameter = r.bits(parameter_bits)? as u32;
+        if parameter == escape {
+            let bits = r.bits(5)? as u32;
+            for _ in 0..count {
+                samples.push(r.signed(bits)?);
+            }
+        } else {
+            for _ in 0..count {
+                let folded = (r.unary()? << parameter) | r.bits(parameter)?;
+                samples.push((folded >> 1) as i64 ^ -((folded & 1) as i64));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes `audio_buffer` as a FLAC file, which can only hold integer samples.
+pub fn write_flac<W: Write>(
+    writer: &mut W,
+    audio_buffer: &AudioBuffer,
+    format: SampleFormat,
+) -> Result<(), io::Error> {
+    let md = &audio_buffer.metadata;
+    let invalid = |message| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+    if format.is_float() {
+        return invalid("FLAC can't store floating point samples");
+    }
+    if md.channels == 0 || md.channels > 8 {
+        return invalid("FLAC can't store more than 8 channels");
+    }
+    if md.sample_rate == 0 || md.sample_rate >= 1 << 20 {
+        return invalid("sample rate out of FLAC's range");
+    }
+    let channels = usize::from(md.channels);
+    let bit_depth = format.bit_depth();
+    let frames = (audio_buffer.data.len() / channels) as u64;
+
+    let mut encoded = Vec::new();
+    let mut frame_sizes = (u32::MAX, 0);
+    let mut block = vec![Vec::with_capacity(BLOCK_SIZE); channels];
+    for (number, samples) in audio_buffer.data.chunks(BLOCK_SIZE * channels).enumerate() {
+        for (channel, block) in block.iter_mut().enumerate() {
+            block.clear();
+            block.extend(
+                samples
+                    .iter()
+                    .skip(channel)
+                    .step_by(channels)
+                    .map(|&s| quantize(s, bit_depth)),
+            );
+        }
+        let start = encoded.len();
+        write_frame(
+            &mut encoded,
+            &block,
+            number as u64,
+            md.sample_rate,
+            bit_depth,
+        );
+        let size = (encoded.len() - start) as u32;
+        frame_sizes = (frame_sizes.0.min(size), frame_sizes.1.max(size));
+    }
+    if frame_sizes.1 == 0 {
+        frame_sizes.0 = 0;
+    }
+
+    writer.write_all(b"fLaC")?;
+    let mut stream_info = BitWriter::default();
+    stream_info.put(BLOCK_SIZE as u64, 16);
+    stream_info.put(BLOCK_SIZE as u64, 16);
+    stream_info.put(u64::from(frame_sizes.0), 24);
+    stream_info.put(u64::from(frame_sizes.1), 24);
+    stream_info.put(u64::from(md.sample_rate), 20);
+    stream_info.put(channels as u64 - 1, 3);
+    stream_info.put(u64::from(bit_depth) - 1, 5);
+    stream_info.put(frames, 36);
+    // no MD5 signature of the samples
+    stream_info.bytes.extend_from_slice(&[0; 16]);
+
+    let mut comment = Vec::new();
+    let vendor = concat!("screech ", env!("CARGO_PKG_VERSION"));
+    let fields: Vec<String> = md
+        .chunks
+        .info
+        .iter()
+        .filter_map(|(id, text)| {
+            let (name, _) = VORBIS_TAGS.iter().find(|(_, tag)| *tag == id)?;
+            Some(format!("{}={}", name, text))
+        })
+        .collect();
+    comment.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    comment.extend_from_slice(vendor.as_bytes());
+    comment.extend_from_slice(&(fields.len() as u32).to_le_bytes());
+    for field in &fields {
+        comment.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        comment.extend_from_slice(field.as_bytes());
+    }
+
+    for (last, kind, body) in &[
+        (false, STREAMINFO, &stream_info.bytes),
+        (true, VORBIS_COMMENT, &comment),
+    ] {
+        let size = body.len() as u32;
+        writer.write_all(&[(*last as u8) << 7 | kind])?;
+        writer.write_all(&size.to_be_bytes()[1..])?;
+        writer.write_all(body)?;
+    }
+    writer.write_all(&encoded)
+}
+
+fn write_frame(
+    encoded: &mut Vec<u8>,
+    channels: &[Vec<i64>],
+    number: u64,
+    sample_rate: u32,
+    bit_depth: u16,
+) {
+    let block_size = channels[0].len();
+    let bit_depth = u32::from(bit_depth);
+
+    // pick the stereo decorrelation that needs the fewest bits
+    let mut stereo = Stereo::Independent;
+    let mut subframes: Vec<Subframe> = channels
+        .iter()
+        .map(|c| Subframe::new(c, bit_depth))
+        .collect();
+    // the side channel of 32 bit audio would need 33 bits, which residuals can't always hold
+    if channels.len() == 2 && bit_depth < 32 {
+        let (left, right) = (&channels[0], &channels[1]);
+        let side: Vec<i64> = left.iter().zip(right).map(|(l, r)| l - r).collect();
+        let mid: Vec<i64> = left.iter().zip(right).map(|(l, r)| (l + r) >> 1).collect();
+        let side = Subframe::new(&side, bit_depth + 1);
+        let mid = Subframe::new(&mid, bit_depth);
+        let [left, right] = [subframes[0].bits, subframes[1].bits];
+        let candidates = [
+            (Stereo::Independent, left + right),
+            (Stereo::LeftSide, left + side.bits),
+            (Stereo::SideRight, side.bits + right),
+            (Stereo::MidSide, mid.bits + side.bits),
+        ];
+        let best = candidates.iter().min_by_key(|(_, bits)| *bits).unwrap().0;
+        stereo = best;
+        match best {
+            Stereo::Independent => {}
+            Stereo::LeftSide => subframes[1] = side,
+            Stereo::SideRight => subframes[0] = side,
+            Stereo::MidSide => subframes = vec![mid, side],
+        }
+    }
+
+    let mut w = BitWriter::default();
+    w.put(0b11_1111_1111_1110, 14);
+    // reserved bit, then fixed block size
+    w.put(0, 2);
+    let (block_size_code, block_size_bits) = match block_size {
+        192 => (1, 0),
+        576 | 1152 | 2304 | 4608 => (2 + (block_size / 576).trailing_zeros(), 0),
+        256 | 512 | 1024 | 2048 | 4096 | 8192 | 16384 | 32768 => {
+            (8 + (block_size / 256).trailing_zeros(), 0)
+        }
+        1..=256 => (6, 8),
+        _ => (7, 16),
+    };
+    w.put(u64::from(block_size_code), 4);
+    let sample_rate_code = SAMPLE_RATES[1..]
+        .iter()
+        .position(|&rate| rate == sample_rate)
+        .map_or(0, |i| i + 1);
+    w.put(sample_rate_code as u64, 4);
+    let channel_code = match stereo {
+        Stereo::Independent => channels.len() as u64 - 1,
+        Stereo::LeftSide => 8,
+        Stereo::SideRight => 9,
+        Stereo::MidSide => 10,
+    };
+    w.put(channel_code, 4);
+    let bit_depth_code = BIT_DEPTHS.iter().position(|&b| b == bit_depth).unwrap_or(0);
+    w.put(bit_depth_code as u64, 3);
+    w.put(0, 1);
+    w.put_utf8(number);
+    if block_size_bits > 0 {
+        w.put(block_size as u64 - 1, block_size_bits);
+    }
+    w.align();
+    let crc = crc8(&w.bytes);
+    w.put(u64::from(crc), 8);
+
+    for subframe in &subframes {
+        subframe.write(&mut w);
+    }
+    w.align();
+    let crc = crc16(&w.bytes);
+    w.put(u64::from(crc), 16);
+    encoded.extend_from_slice(&w.bytes);
+}
+
+/// Cheapest encoding found for the samples of one channel.
+struct Subframe {
+    /// Samples shifted right by `wasted_bits`.
+    samples: Vec<i64>,
+    bit_depth: u32,
+    wasted_bits: u32,
+    kind: SubframeKind,
+    /// Size of the encoded subframe.
+    bits: u64,
+}
+
+enum SubframeKind {
+    Constant,
+    Verbatim,
+    Fixed {
+        order: usize,
+        residual: Vec<i64>,
+        rice: Rice,
+    },
+}
+
+impl Subframe {
+    fn new(samples: &[i64], bit_depth: u32) -> Self {
+        // low bits that are zero in every sample don't need to be stored
+        let wasted_bits = samples
+            .iter()
+            .fold(0, |acc, s| acc | s)
+            .trailing_zeros()
+            .min(bit_depth - 1);
+        let samples: Vec<i64> = samples.iter().map(|s| s >> wasted_bits).collect();
+        let bit_depth = bit_depth - wasted_bits;
+        let header_bits = 8 + u64::from(wasted_bits);
+
+        let (kind, bits) = if samples.iter().all(|&s| s == samples[0]) {
+            (SubframeKind::Constant, u64::from(bit_depth))
+        } else {
+            let mut best = (
+                SubframeKind::Verbatim,
+                u64::from(bit_depth) * samples.len() as u64,
+            );
+            for order in 0..=4.min(samples.len() - 1) {
+                let residual = fixed_residual(&samples, order);
+                // decoders only have to handle residuals that fit in 32 bits
+                if residual
+                    .iter()
+                    .any(|&r| r <= i64::from(i32::MIN) || r > i64::from(i32::MAX))
+                {
+                    continue;
+                }
+                let rice = Rice::new(&residual, order, samples.len());
+                let bits = order as u64 * u64::from(bit_depth) + rice.bits;
+                if bits < best.1 {
+                    best = (
+                        SubframeKind::Fixed {
+                            order,
+                            residual,
+                            rice,
+                        },
+                        bits,
+                    );
+                }
+            }
+            best
+        };
+        Self {
+            samples,
+            bit_depth,
+            wasted_bits,
+            kind,
+            bits: header_bits + bits,
+        }
+    }
+
+    fn write(&self, w: &mut BitWriter) {
+        let kind = match &self.kind {
+            SubframeKind::Constant => 0,
+            SubframeKind::Verbatim => 1,
+            SubframeKind::Fixed { order, .. } => 8 + *order as u64,
+        };
+        w.put(kind, 7);
+        if self.wasted_bits > 0 {
+            w.put(1, 1);
+            w.put_unary(u64::from(self.wasted_bits - 1));
+        } else {
+            w.put(0, 1);
+        }
+        match &self.kind {
+            SubframeKind::Constant => w.put_signed(self.samples[0], self.bit_depth),
+            SubframeKind::Verbatim => {
+                for &s in &self.samples {
+                    w.put_signed(s, self.bit_depth);
+                }
+            }
+            SubframeKind::Fixed {
+                order,
+                residual,
+                rice,
+            } => {
+                for &s in &self.samples[..*order] {
+                    w.put_signed(s, self.bit_depth);
+                }
+                rice.write(w, residual, *order, self.samples.len());
+            }
+        }
+    }
+}
+
+/// Difference between each sample and what the fixed predictor of `order` guesses from the ones
+/// before it.
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    (order..samples.len())
+        .map(|i| {
+            let s = samples;
+            s[i] - match order {
+                0 => 0,
+                1 => s[i - 1],
+                2 => 2 * s[i - 1] - s[i - 2],
+                3 => 3 * s[i - 1] - 3 * s[i - 2] + s[i - 3],
+                _ => 4 * s[i - 1] - 6 * s[i - 2] + 4 * s[i - 3] - s[i - 4],
+            }
+        })
+        .collect()
+}
+
+/// Partitioned Rice coding of a residual.
+struct Rice {
+    partition_order: u32,
+    parameters: Vec<u32>,
+    /// Size of the coded residual, headers included.
+    bits: u64,
+}
+
+impl Rice {
+    /// Largest parameter of the 5 bit coding method, 31 being the escape code.
+    const MAX_PARAMETER: u32 = 30;
+
+    fn new(residual: &[i64], order: usize, block_size: usize) -> Self {
+        let folded: Vec<u64> = residual.iter().map(|&r| fold(r)).collect();
+        let mut best: Option<Self> = None;
+        for partition_order in 0..=8 {
+            let partition_size = block_size >> partition_order;
+            if partition_size << partition_order != block_size || partition_size <= order {
+                break;
+            }
+            let mut parameters = Vec::with_capacity(1 << partition_order);
+            let mut bits = 0;
+            for partition in partitions(&folded, partition_order, order, block_size) {
+                let (parameter, partition_bits) = best_parameter(partition);
+                parameters.push(parameter);
+                bits += partition_bits;
+            }
+            let parameter_bits = if parameters.iter().any(|&p| p >= 15) {
+                5
+            } else {
+                4
+            };
+            bits += 2 + 4 + parameter_bits * parameters.len() as u64;
+            if best.as_ref().map_or(true, |best| bits < best.bits) {
+                best = Some(Self {
+                    partition_order,
+                    parameters,
+                    bits,
+                });
+            }
+        }
+        best.unwrap()
+    }
+
+    fn write(&self, w: &mut BitWriter, residual: &[i64], order: usize, block_size: usize) {
+        let wide = self.parameters.iter().any(|&p| p >= 15);
+        let parameter_bits = if wide { 5 } else { 4 };
+        w.put(wide as u64, 2);
+        w.put(u64::from(self.partition_order), 4);
+        for (partition, &parameter) in
+            partitions(residual, self.partition_order, order, block_size).zip(&self.parameters)
+        {
+            w.put(u64::from(parameter), parameter_bits);
+            for &r in partition {
+                let folded = fold(r);
+                w.put_unary(folded >> parameter);
+                w.put(folded, parameter);
+            }
+        }
+    }
+}
+
+/// Zigzag encoding of a residual: 0, -1, 1, -2... become 0, 1, 2, 3...
+fn fold(r: i64) -> u64 {
+    ((r << 1) ^ (r >> 63)) as u64
+}
+
+/// Splits a residual into the partitions of `partition_order`, the first one being shorter as it
+/// doesn't include the warm up samples.
+fn partitions<T>(
+    residual: &[T],
+    partition_order: u32,
+    order: usize,
+    block_size: usize,
+) -> impl Iterator<Item = &[T]> {
+    let partition_size = block_size >> partition_order;
+    (0..1 << partition_order).map(move |partition| {
+        let start = (partition * partition_size).saturating_sub(order);
+        &residual[start..(partition + 1) * partition_size - order]
+    })
+}
+
+/// Finds the Rice parameter that codes `folded` with the fewest bits.
+fn best_parameter(folded: &[u64]) -> (u32, u64) {
+    let cost = |parameter: u32| -> u64 {
+        folded.iter().map(|f| f >> parameter).sum::<u64>()
+            + u64::from(parameter + 1) * folded.len() as u64
+    };
+    // the best parameter is close to the logarithm of the mean
+    let mean = folded.iter().sum::<u64>() / folded.len().max(1) as u64;
+    let guess = (64 - mean.leading_zeros()).min(Rice::MAX_PARAMETER);
+    (guess.saturating_sub(1)..=(guess + 1).min(Rice::MAX_PARAMETER))
+        .map(|parameter| (parameter, cost(parameter)))
+        .min_by_key(|&(_, bits)| bits)
+        .unwrap()
+}
+
+/// Reads big endian bit fields from a byte slice.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    /// Position in bits.
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn byte(&self) -> Result<u8, WavError> {
+        self.bytes
+            .get(self.position / 8)
+            .copied()
+            .ok_or(WavError::Truncated)
+    }
+
+    /// Reads an unsigned value of up to 64 bits.
+    fn bits(&mut self, mut n: u32) -> Result<u64, WavError> {
+        let mut value = 0_u64;
+        while n > 0 {
+            let available = 8 - (self.position % 8) as u32;
+            let taken = available.min(n);
+            let bits = (u32::from(self.byte()?) >> (available - taken)) & ((1 << taken) - 1);
+            value = value << taken | u64::from(bits);
+            n -= taken;
+            self.position += taken as usize;
+        }
+        Ok(value)
+    }
+
+    /// Reads a two's complement value of `n` bits.
+    fn signed(&mut self, n: u32) -> Result<i64, WavError> {
+        if n == 0 {
+            return Ok(0);
+        }
+        let value = self.bits(n)?;
+        Ok(((value << (64 - n)) as i64) >> (64 - n))
+    }
+
+    /// Counts the zeros before the next one.
+    fn unary(&mut self) -> Result<u64, WavError> {
+        let mut zeros = 0;
+        loop {
+            let offset = (self.position % 8) as u32;
+            let rest = self.byte()? << offset;
+            if rest == 0 {
+                zeros += u64::from(8 - offset);
+                self.position += (8 - offset) as usize;
+            } else {
+                let leading = rest.leading_zeros();
+                self.position += leading as usize + 1;
+                return Ok(zeros + u64::from(leading));
+            }
+        }
+    }
+
+    fn align(&mut self) {
+        self.position = (self.position + 7) / 8 * 8;
+    }
+}
+
+/// Writes big endian bit fields.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    /// Number of bits in `buffer`, always less than 8 between calls.
+    len: u32,
+}
+
+impl BitWriter {
+    /// Writes the `n` low bits of `value`, `n` being at most 56.
+    fn put(&mut self, value: u64, n: u32) {
+        if n == 0 {
+            return;
+        }
+        self.buffer = self.buffer << n | (value & (u64::MAX >> (64 - n)));
+        self.len += n;
+        while self.len >= 8 {
+            self.len -= 8;
+            self.bytes.push((self.buffer >> self.len) as u8);
+        }
+    }
+
+    fn put_signed(&mut self, value: i64, n: u32) {
+        self.put(value as u64, n);
+    }
+
+    /// Writes `n` zeros followed by a one.
+    fn put_unary(&mut self, mut n: u64) {
+        while n >= 32 {
+            self.put(0, 32);
+            n -= 32;
+        }
+        self.put(1, n as u32 + 1);
+    }
+
+    /// Writes a frame number with the variable length coding of UTF-8, extended to 36 bits.
+    fn put_utf8(&mut self, n: u64) {
+        if n < 0x80 {
+            self.put(n, 8);
+            return;
+        }
+        // each continuation byte holds 6 bits, the first byte 6 - continuation bytes
+        let mut continuation_bytes = 1;
+        while n >> (6 * continuation_bytes) >= 1 << (6 - continuation_bytes) {
+            continuation_bytes += 1;
+        }
+        let prefix = !(0xFF_u64 >> (continuation_bytes + 1)) & 0xFF;
+        self.put(prefix | n >> (6 * continuation_bytes), 8);
+        for i in (0..continuation_bytes).rev() {
+            self.put(0x80 | (n >> (6 * i) & 0x3F), 8);
+        }
+    }
+
+    fn align(&mut self) {
+        if self.len > 0 {
+            self.put(0, 8 - self.len);
+        }
+    }
+}
+
+/// CRC-8 of frame headers, with polynomial x^8 + x^2 + x + 1.
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                crc << 1 ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// CRC-16 of whole frames, with polynomial x^16 + x^15 + x^2 + 1.
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ u16::from(byte) << 8, |crc, _| {
+            if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x8005
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
@@ -0,0 +1,167 @@
+//! Headerless streams of interleaved samples, whose layout has to be given explicitly.
+
+use super::{decode_samples, read_frames, swap_sample_bytes, write_samples, Endianness, WavError};
+use crate::metadata::ChunkMetadata;
+use crate::types::*;
+
+use std::io;
+use std::io::{Read, Write};
+
+/// Encoding of raw samples, written like `s16le`, `f32be` or `u8`.
+///
+/// 8 bit samples are unsigned like in WAV files, all others are signed integers or floats.
+/// Little endian is assumed when the byte order is left out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawFormat {
+    pub format: SampleFormat,
+    pub endianness: Endianness,
+}
+
+/// Layout of a raw stream, written like `f32le:2:48000` for the encoding, channel count and
+/// sample rate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawSpec {
+    pub format: RawFormat,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+impl std::str::FromStr for RawFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, endianness) = if let Some(name) = s.strip_suffix("le") {
+            (name, Endianness::Little)
+        } else if let Some(name) = s.strip_suffix("be") {
+            (name, Endianness::Big)
+        } else {
+            (s, Endianness::Little)
+        };
+        let format = match name {
+            "u8" => SampleFormat::Pcm8,
+            "s16" => SampleFormat::Pcm16,
+            "s24" => SampleFormat::Pcm24,
+            "s32" => SampleFormat::Pcm32,
+            "f32" => SampleFormat::Float32,
+            "f64" => SampleFormat::Float64,
+            _ => return Err(format!("unknown raw format {}", s)),
+        };
+        Ok(Self { format, endianness })
+    }
+}
+
+impl std::str::FromStr for RawSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || format!("raw spec {} isn't of the form format:channels:rate", s);
+        let mut parts = s.split(':');
+        let (format, channels, sample_rate) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(format), Some(channels), Some(sample_rate)) if parts.next().is_none() => {
+                (format, channels, sample_rate)
+            }
+            _ => return Err(usage()),
+        };
+        let spec = Self {
+            format: format.parse()?,
+            channels: channels.parse().map_err(|_| usage())?,
+            sample_rate: sample_rate.parse().map_err(|_| usage())?,
+        };
+        spec.check()?;
+        Ok(spec)
+    }
+}
+
+impl RawSpec {
+    fn check(&self) -> Result<(), &'static str> {
+        if self.channels == 0 {
+            return Err("raw streams need at least one channel");
+        }
+        if self.sample_rate == 0 {
+            return Err("raw streams need a sample rate");
+        }
+        Ok(())
+    }
+}
+
+/// Reads interleaved samples until the end of `reader`, ignoring an incomplete last frame.
+pub fn read_raw<R: Read>(reader: &mut R, spec: &RawSpec) -> Result<AudioBuffer, WavError> {
+    let mut raw = RawReader::new(reader, spec)?;
+    let mut data = Vec::new();
+    while raw.read(&mut data, 1 << 16)? > 0 {}
+    Ok(AudioBuffer {
+        metadata: raw.metadata,
+        data,
+    })
+}
+
+/// Reads a raw stream block by block.
+pub struct RawReader<R: Read> {
+    reader: R,
+    endianness: Endianness,
+    metadata: AudioMetadata,
+    bytes: Vec<u8>,
+}
+
+impl<R: Read> RawReader<R> {
+    pub fn new(reader: R, spec: &RawSpec) -> Result<Self, WavError> {
+        spec.check().map_err(WavError::InvalidHeader)?;
+        Ok(Self {
+            reader,
+            endianness: spec.format.endianness,
+            metadata: AudioMetadata {
+                channels: spec.channels,
+                sample_rate: spec.sample_rate,
+                valid_bits_per_sample: None,
+                channel_mask: None,
+                sample_format: spec.format.format,
+                chunks: ChunkMetadata::default(),
+            },
+            bytes: Vec::new(),
+        })
+    }
+
+    pub fn metadata(&self) -> &AudioMetadata {
+        &self.metadata
+    }
+
+    /// Appends up to `frames` frames to `samples`, returning how many were read, 0 at the end of
+    /// the stream.
+    pub fn read(&mut self, samples: &mut Vec<f32>, frames: usize) -> Result<usize, WavError> {
+        let format = self.metadata.sample_format;
+        let frame_size =
+            usize::from(self.metadata.channels) * usize::from(format.bytes_per_sample());
+        let read = read_frames(&mut self.reader, &mut self.bytes, frames, frame_size)?;
+        if self.endianness == Endianness::Big {
+            swap_sample_bytes(&mut self.bytes, format);
+        }
+        samples.extend(decode_samples(&self.bytes, format));
+        Ok(read)
+    }
+}
+
+/// Writes the samples of `audio_buffer` without any header or metadata.
+pub fn write_raw<W: Write>(
+    writer: &mut W,
+    audio_buffer: &AudioBuffer,
+    format: RawFormat,
+) -> Result<(), io::Error> {
+    let md = &audio_buffer.metadata;
+    RawSpec {
+        format,
+        channels: md.channels,
+        sample_rate: md.sample_rate,
+    }
+    .check()
+    .map_err(|message| io::Error::new(io::ErrorKind::InvalidInput, message))?;
+    write_raw_samples(writer, &audio_buffer.data, format)
+}
+
+/// Writes interleaved samples as they come, e.g. one block at a time.
+pub fn write_raw_samples<W: Write>(
+    writer: &mut W,
+    samples: &[f32],
+    format: RawFormat,
+) -> Result<(), io::Error> {
+    write_samples(writer, samples, format.format, format.endianness)
+}
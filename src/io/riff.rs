@@ -0,0 +1,237 @@
+//! Walks over the chunks of a RIFF file (or its RF64/BW64 variants) without interpreting them.
+//! The big endian IFF files used by AIFF share the same structure and are handled as well.
+//!
+//! ```no_run
+//! use std::io::Read;
+//!
+//! let mut file = std::fs::File::open("in.wav").unwrap();
+//! let mut chunks = screech::io::riff::Chunks::new(&mut file).unwrap();
+//! while let Some(header) = chunks.next() {
+//!     let header = header.unwrap();
+//!     println!("{} {}", String::from_utf8_lossy(&header.id), header.size);
+//!     if &header.id == b"LIST" {
+//!         let mut body = Vec::new();
+//!         chunks.read_to_end(&mut body).unwrap();
+//!     }
+//! }
+//! ```
+
+use super::{ReadByte, WavError};
+
+use std::io;
+use std::io::Read;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkHeader {
+    pub id: [u8; 4],
+    /// Size of the body, without the padding byte that follows odd sized chunks. `u64::MAX` for a
+    /// data chunk written by a streaming tool, which lasts until the end of the file.
+    pub size: u64,
+}
+
+/// Iterator over the headers of the chunks of a RIFF file.
+///
+/// The body of the current chunk can be read through the `Read` implementation; whatever is left
+/// of it is skipped when moving on to the next chunk. Truncated files are reported as
+/// `WavError::Truncated`, either when a header or a body is cut short, or when a chunk claims to
+/// be longer than what the RIFF header leaves for it.
+pub struct Chunks<R: Read> {
+    reader: R,
+    form_type: [u8; 4],
+    /// IFF files, which start with FORM instead of RIFF, store their sizes as big endian.
+    big_endian: bool,
+    /// Bytes left in the RIFF chunk according to its header, `None` if the header doesn't know.
+    remaining: Option<u64>,
+    /// Bytes left to read in the current chunk's body.
+    body_left: u64,
+    /// Whether the current chunk is followed by a padding byte.
+    padded: bool,
+    /// Whether the current chunk goes on until the end of the file.
+    unbounded: bool,
+    /// Body of the ds64 chunk, which is read ahead to get the real sizes of RF64 files.
+    ds64: Option<Vec<u8>>,
+    ds64_read: usize,
+    done: bool,
+}
+
+impl<R: Read> Chunks<R> {
+    /// Reads the RIFF header, which must be followed by the form type, e.g. WAVE.
+    pub fn new(mut reader: R) -> Result<Self, WavError> {
+        let mut magic = [0_u8; 4];
+        reader.read_exact(&mut magic)?;
+        // RF64 and its BW64 successor only differ from RIFF by their ds64 chunk
+        let (rf64, big_endian) = match &magic {
+            b"RIFF" => (false, false),
+            b"RF64" | b"BW64" => (true, false),
+            b"FORM" => (false, true),
+            _ => return Err(WavError::BadMagic(magic)),
+        };
+        let size = read_size(&mut reader, big_endian)?;
+        let mut form_type = [0_u8; 4];
+        reader.read_exact(&mut form_type)?;
+
+        // streamed files don't know their size when writing the header
+        let remaining = match size {
+            0 | u32::MAX => None,
+            size => Some(u64::from(size).saturating_sub(4)),
+        };
+        Ok(Self {
+            reader,
+            form_type,
+            big_endian,
+            remaining: if rf64 { None } else { remaining },
+            body_left: 0,
+            padded: false,
+            unbounded: false,
+            ds64: None,
+            ds64_read: 0,
+            done: false,
+        })
+    }
+
+    pub fn form_type(&self) -> [u8; 4] {
+        self.form_type
+    }
+
+    /// Bytes of the current chunk's body that haven't been read yet.
+    pub fn body_left(&self) -> u64 {
+        self.body_left
+    }
+
+    /// Reads what is left of the current chunk's body.
+    pub fn read_body(&mut self) -> Result<Vec<u8>, WavError> {
+        let mut body = Vec::with_capacity(self.body_left.min(1 << 24) as usize);
+        self.read_to_end(&mut body)?;
+        if self.body_left > 0 {
+            return Err(WavError::Truncated);
+        }
+        Ok(body)
+    }
+
+    fn ds64_u64(&self, offset: usize) -> Option<u64> {
+        let bytes = self.ds64.as_ref()?.get(offset..offset + 8)?;
+        let mut buf = [0_u8; 8];
+        buf.copy_from_slice(bytes);
+        Some(u64::from_le_bytes(buf))
+    }
+
+    /// Whether a body of `size` bytes goes past the end of the RIFF chunk.
+    fn overflows(&self, size: u64) -> bool {
+        self.remaining.is_some_and(|remaining| size > remaining)
+    }
+
+    fn consume(&mut self, n: u64) {
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_sub(n);
+        }
+    }
+
+    fn next_header(&mut self) -> Result<Option<ChunkHeader>, WavError> {
+        // skip the rest of the previous chunk
+        let left = self.body_left;
+        self.body_left = 0;
+        self.reader.skip_bytes(left)?;
+        self.consume(left);
+        if self.padded {
+            self.padded = false;
+            // writers regularly forget the padding of the last chunk
+            if (&mut self.reader).take(1).read_to_end(&mut Vec::new())? == 0 {
+                return Ok(None);
+            }
+            self.consume(1);
+        }
+
+        if self.remaining == Some(0) {
+            return Ok(None);
+        }
+        let id = match self.reader.read_tag()? {
+            Some(id) => id,
+            None if self.remaining.is_none() => return Ok(None),
+            None => return Err(WavError::Truncated),
+        };
+        let mut size = u64::from(read_size(&mut self.reader, self.big_endian)?);
+        self.consume(8);
+
+        if &id == b"ds64" && self.ds64.is_none() {
+            if self.overflows(size) {
+                return Err(WavError::Truncated);
+            }
+            let mut body = Vec::new();
+            (&mut self.reader).take(size).read_to_end(&mut body)?;
+            if (body.len() as u64) < size {
+                return Err(WavError::Truncated);
+            }
+            self.ds64 = Some(body);
+            self.ds64_read = 0;
+            // the RIFF size doesn't include the RF64 header
+            self.remaining = self
+                .ds64_u64(0)
+                .map(|riff_size| riff_size.saturating_sub(4 + 8 + size + size % 2));
+            self.padded = size % 2 == 1;
+            return Ok(Some(ChunkHeader { id, size }));
+        }
+        self.ds64_read = self.ds64.as_ref().map_or(0, |ds64| ds64.len());
+
+        if &id == b"data" && size == u64::from(u32::MAX) {
+            if let Some(data_size) = self.ds64_u64(8) {
+                size = data_size;
+            }
+        }
+        // tools writing to a pipe can't go back to fill in the size of the data
+        self.unbounded = &id == b"data"
+            && self.ds64.is_none()
+            && (size == u64::from(u32::MAX) || (size == 0 && self.remaining.is_none()));
+        if self.unbounded {
+            size = u64::MAX;
+        } else if self.overflows(size) {
+            return Err(WavError::Truncated);
+        }
+        self.body_left = size;
+        self.padded = !self.unbounded && size % 2 == 1;
+        Ok(Some(ChunkHeader { id, size }))
+    }
+}
+
+fn read_size<R: Read>(reader: &mut R, big_endian: bool) -> io::Result<u32> {
+    let size = reader.read_u32()?;
+    Ok(if big_endian { size.swap_bytes() } else { size })
+}
+
+impl<R: Read> Iterator for Chunks<R> {
+    type Item = Result<ChunkHeader, WavError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let header = self.next_header().transpose();
+        if !matches!(header, Some(Ok(_))) {
+            self.done = true;
+        }
+        header
+    }
+}
+
+impl<R: Read> Read for Chunks<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(ds64) = &self.ds64 {
+            if self.ds64_read < ds64.len() {
+                let n = buf.len().min(ds64.len() - self.ds64_read);
+                buf[..n].copy_from_slice(&ds64[self.ds64_read..self.ds64_read + n]);
+                self.ds64_read += n;
+                return Ok(n);
+            }
+        }
+        let max = buf
+            .len()
+            .min(self.body_left.min(usize::MAX as u64) as usize);
+        let n = self.reader.read(&mut buf[..max])?;
+        if n == 0 && max > 0 && self.unbounded {
+            self.body_left = 0;
+            self.remaining = Some(0);
+        }
+        self.body_left -= n as u64;
+        self.consume(n as u64);
+        Ok(n)
+    }
+}
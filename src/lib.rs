@@ -1,7 +1,19 @@
+pub mod automation;
+pub mod batch;
+pub mod combine;
 pub mod distort;
+pub mod dither;
+pub mod effect;
 pub mod gain;
+pub mod graph;
 pub mod io;
+pub mod metadata;
+pub mod mix;
 pub mod phase;
 pub mod pitch;
+pub mod preset;
+pub mod processor;
 pub mod pseudo_cycle;
+pub mod region;
+pub mod resample;
 pub mod types;
@@ -1,20 +1,78 @@
-use screech::distort::*;
-use screech::gain::*;
+use screech::batch::*;
+use screech::combine::{Combine, Input};
+use screech::dither::*;
+use screech::effect::*;
+use screech::graph::*;
 use screech::io::*;
-use screech::phase::*;
-use screech::pitch::*;
-use screech::pseudo_cycle::*;
-use screech::types::AudioBuffer;
+use screech::preset::*;
+use screech::processor::Processor;
+use screech::region::{Region, Time};
+use screech::types::{AudioBuffer, AudioMetadata, SampleFormat};
 use std::env::args;
+use std::fmt;
 use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Cursor, Read, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::Arc;
 
 #[derive(Debug)]
 enum CliError {
     Io(std::io::Error),
     ParseInt(std::num::ParseIntError),
     ParseFloat(std::num::ParseFloatError),
+    Wav(WavError),
     Arguments(String),
+    /// An effect panicked while processing one file of a batch.
+    Panicked,
+    /// Some files of a batch failed, `code` is the exit code of the first one.
+    Batch {
+        failed: usize,
+        total: usize,
+        code: i32,
+    },
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Io(e) => write!(f, "{}", e),
+            CliError::ParseInt(e) => write!(f, "{}", e),
+            CliError::ParseFloat(e) => write!(f, "{}", e),
+            CliError::Wav(e) => write!(f, "{}", e),
+            CliError::Arguments(s) => write!(f, "{}", s),
+            CliError::Panicked => write!(f, "processing panicked"),
+            CliError::Batch { failed, total, .. } => {
+                write!(f, "{} of {} files failed", failed, total)
+            }
+        }
+    }
+}
+
+impl CliError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Io(_) => 2,
+            CliError::ParseInt(_) | CliError::ParseFloat(_) => 3,
+            CliError::Wav(e) => match e {
+                WavError::Io(_) => 2,
+                WavError::BadMagic(_) => 4,
+                WavError::UnsupportedCodec(_) => 5,
+                WavError::UnsupportedBitDepth(_) => 6,
+                WavError::Truncated => 7,
+                WavError::MissingFmt => 8,
+                WavError::MissingData => 9,
+                WavError::UnsupportedCompression(_) => 10,
+                WavError::Corrupt(_) => 11,
+                WavError::InvalidHeader(_) => 12,
+            },
+            CliError::Arguments(_) => 1,
+            CliError::Panicked => 101,
+            CliError::Batch { code, .. } => *code,
+        }
+    }
 }
 
 impl From<std::io::Error> for CliError {
@@ -35,259 +93,866 @@ impl From<std::num::ParseFloatError> for CliError {
     }
 }
 
-fn run<F: Fn(AudioBuffer) -> AudioBuffer>(
-    f: F,
-    mut audio_buffer: AudioBuffer,
-    iterations: u32,
-) -> AudioBuffer {
-    for _ in 0..iterations {
-        audio_buffer = f(audio_buffer)
+impl From<ParamError> for CliError {
+    fn from(err: ParamError) -> Self {
+        match err {
+            ParamError::ParseInt(e) => CliError::ParseInt(e),
+            ParamError::ParseFloat(e) => CliError::ParseFloat(e),
+            e => CliError::Arguments(e.to_string()),
+        }
     }
-    audio_buffer
 }
 
-fn do_main(
-    in_filename: &str,
-    out_filename: &String,
-    mut option_arguments: &[String],
-) -> Result<(), CliError> {
-    let mut audio_buffer = read_wav(&mut File::open(in_filename)?)?;
+impl From<WavError> for CliError {
+    fn from(err: WavError) -> Self {
+        CliError::Wav(err)
+    }
+}
 
-    while !option_arguments.is_empty() {
-        let iterations = match option_arguments[0].parse::<u32>() {
-            Ok(i) => {
-                option_arguments = &option_arguments[1..];
-                i
-            }
-            Err(_) => 1,
+/// Removes `flag` and its value from `args`, returning the value if the flag was present.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> Result<Option<String>, CliError> {
+    match args.iter().position(|arg| arg == flag) {
+        Some(i) if i + 1 < args.len() => {
+            let value = args.remove(i + 1);
+            args.remove(i);
+            Ok(Some(value))
+        }
+        Some(_) => Err(CliError::Arguments(format!("{} takes a value", flag))),
+        None => Ok(None),
+    }
+}
+
+/// Removes every occurrence of `flag` and its value from `args`.
+fn take_flags(args: &mut Vec<String>, flag: &str) -> Result<Vec<String>, CliError> {
+    let mut values = Vec::new();
+    while let Some(value) = take_flag(args, flag)? {
+        values.push(value);
+    }
+    Ok(values)
+}
+
+struct InputOptions {
+    raw: Option<RawSpec>,
+    /// Names and paths of the inputs that the chain can bring in besides the main one.
+    extra: Vec<(String, String)>,
+}
+
+impl InputOptions {
+    fn take_from(args: &mut Vec<String>) -> Result<Self, CliError> {
+        let raw = match take_flag(args, "--raw-in")? {
+            Some(r) => Some(r.parse::<RawSpec>().map_err(CliError::Arguments)?),
+            None => None,
         };
-        if "interpolate".starts_with(&option_arguments[0]) {
-            audio_buffer = run(|ab: AudioBuffer| interpolate(&ab), audio_buffer, iterations);
-            option_arguments = &option_arguments[1..];
-        } else if "fractalize".starts_with(&option_arguments[0]) {
-            if option_arguments.len() < 2 {
-                return Err(CliError::Arguments(String::from(
-                    "fractalize takes an integral depth",
+        let mut extra: Vec<(String, String)> = Vec::new();
+        for with in take_flags(args, "--with")? {
+            let (name, path) = with.split_once('=').ok_or_else(|| {
+                CliError::Arguments(format!("--with takes <name>=<file>, got {}", with))
+            })?;
+            let valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            if !valid {
+                return Err(CliError::Arguments(format!(
+                    "input name {} must start with a letter and only have letters, digits, _ \
+                     and -",
+                    name
                 )));
             }
-            let depth = option_arguments[1].parse::<u32>()?;
-            audio_buffer = run(
-                |ab: AudioBuffer| fractalize(&ab, depth),
-                audio_buffer,
-                iterations,
-            );
-            option_arguments = &option_arguments[2..];
-        } else if "expand".starts_with(&option_arguments[0]) {
-            audio_buffer = expand(audio_buffer);
-            option_arguments = &option_arguments[1..];
-        } else if "reversepseudocycles".starts_with(&option_arguments[0]) {
-            audio_buffer = run(
-                |ab: AudioBuffer| reverse_pseudo_cycles(ab),
-                audio_buffer,
-                iterations,
-            );
-            option_arguments = &option_arguments[1..];
-        } else if "fold".starts_with(&option_arguments[0]) {
-            audio_buffer = run(|ab: AudioBuffer| fold(ab), audio_buffer, iterations);
-            option_arguments = &option_arguments[1..];
-        } else if "hardclip".starts_with(&option_arguments[0]) {
-            if option_arguments.len() < 2 {
-                return Err(CliError::Arguments(String::from(
-                    "hardclip takes a decimal threshold",
+            if extra.iter().any(|(other, _)| other == name) {
+                return Err(CliError::Arguments(format!(
+                    "input {} is given twice",
+                    name
                 )));
             }
-            audio_buffer = hard_clip(audio_buffer, option_arguments[1].parse::<f32>()?);
-            option_arguments = &option_arguments[2..];
-        } else if "softclip".starts_with(&option_arguments[0]) {
-            if option_arguments.len() < 2 {
+            if path == "-" {
                 return Err(CliError::Arguments(String::from(
-                    "softclip takes a decimal amount",
+                    "only the main input can be stdin",
                 )));
             }
-            let amount = option_arguments[1].parse::<f32>()?;
-            audio_buffer = run(
-                |ab: AudioBuffer| soft_clip(ab, amount),
-                audio_buffer,
-                iterations,
-            );
-            option_arguments = &option_arguments[2..];
-        } else if "tense".starts_with(&option_arguments[0]) {
-            if option_arguments.len() < 2 {
-                return Err(CliError::Arguments(String::from(
-                    "tense takes a decimal tension",
-                )));
+            extra.push((name.to_string(), path.to_string()));
+        }
+        Ok(Self { raw, extra })
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.extra.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Decodes the extra inputs, which are never raw.
+    fn load_extra(&self) -> Result<Vec<Input>, CliError> {
+        let options = InputOptions {
+            raw: None,
+            extra: Vec::new(),
+        };
+        self.extra
+            .iter()
+            .map(|(name, path)| {
+                let audio = Source::open(path, &options)?.read_all()?;
+                Ok(Input {
+                    name: name.clone(),
+                    audio: Arc::new(audio),
+                })
+            })
+            .collect()
+    }
+}
+
+struct ChainOptions {
+    /// Chain that comes before the effects given on the command line.
+    preset: Option<String>,
+    /// Where to save the whole chain.
+    save_preset: Option<String>,
+    /// Part of the file the chain is restricted to.
+    region: Option<Region>,
+}
+
+impl ChainOptions {
+    fn take_from(args: &mut Vec<String>) -> Result<Self, CliError> {
+        let mut region = match take_flag(args, "--range")? {
+            Some(r) => Some(r.parse::<Region>().map_err(CliError::Arguments)?),
+            None => None,
+        };
+        if let Some(crossfade) = take_flag(args, "--crossfade")? {
+            let crossfade = crossfade.parse::<Time>().map_err(CliError::Arguments)?;
+            match &mut region {
+                Some(region) => region.crossfade = crossfade,
+                None => {
+                    return Err(CliError::Arguments(String::from(
+                        "--crossfade is the length of the fades around --range",
+                    )))
+                }
             }
-            let tension = option_arguments[1].parse::<f32>()?;
-            audio_buffer = run(
-                |ab: AudioBuffer| tense(ab, tension),
-                audio_buffer,
-                iterations,
-            );
-            option_arguments = &option_arguments[2..];
-        } else if "tensepseudocycles".starts_with(&option_arguments[0]) {
-            if option_arguments.len() < 2 {
+        }
+        Ok(Self {
+            preset: take_flag(args, "--preset")?,
+            save_preset: take_flag(args, "--save-preset")?,
+            region,
+        })
+    }
+
+    /// Every variant of the chain, named after its swept values.
+    fn chain(
+        &self,
+        option_arguments: &[String],
+        inputs: &[String],
+    ) -> Result<Vec<Variant>, CliError> {
+        let mut chain: Vec<Node<Sweep>> = match &self.preset {
+            Some(path) => read_preset(&std::fs::read_to_string(path)?)
+                .map_err(|e| CliError::Arguments(format!("{}: {}", path, e)))?
+                .into_iter()
+                .map(|invocation| Node::Effect(Sweep::from(invocation)))
+                .collect(),
+            None => Vec::new(),
+        };
+        chain.extend(parse_chain(option_arguments, inputs)?);
+        let variants = expand_graph(&chain);
+        if let Some(path) = &self.save_preset {
+            if effects(&chain).into_iter().any(Sweep::is_swept) {
                 return Err(CliError::Arguments(String::from(
-                    "tensepseudocycles takes a decimal tension",
+                    "a preset holds a single chain, sweeps can't be saved",
                 )));
             }
-            let tension = option_arguments[1].parse::<f32>()?;
-            audio_buffer = run(
-                |ab: AudioBuffer| tense_pseudo_cycles(ab, tension),
-                audio_buffer,
-                iterations,
-            );
-            option_arguments = &option_arguments[2..];
-        } else if "decimate".starts_with(&option_arguments[0]) {
-            if option_arguments.len() < 2 {
-                return Err(CliError::Arguments(String::from(
-                    "decimate takes a decimal depth",
-                )));
+            let invocations = linear(&variants[0].1).ok_or_else(|| {
+                CliError::Arguments(String::from(
+                    "a preset holds a linear chain, branches can't be saved",
+                ))
+            })?;
+            std::fs::write(path, write_preset(&invocations))?;
+        }
+        Ok(variants)
+    }
+}
+
+/// Chain with its swept values filled in, and the label naming them.
+type Variant = (String, Vec<Node<Invocation>>);
+
+struct OutputOptions {
+    format: Option<SampleFormat>,
+    /// Headerless output, which fixes the sample format.
+    raw: Option<RawFormat>,
+    dither: Option<Dither>,
+    seed: u64,
+}
+
+impl OutputOptions {
+    fn take_from(args: &mut Vec<String>) -> Result<Self, CliError> {
+        let format = match take_flag(args, "--format")? {
+            Some(f) => Some(f.parse::<SampleFormat>().map_err(CliError::Arguments)?),
+            None => None,
+        };
+        let raw = match take_flag(args, "--raw-out")? {
+            Some(r) => Some(r.parse::<RawFormat>().map_err(CliError::Arguments)?),
+            None => None,
+        };
+        if format.is_some() && raw.is_some() {
+            return Err(CliError::Arguments(String::from(
+                "--raw-out already sets the sample format, --format can't be used with it",
+            )));
+        }
+        let dither = match take_flag(args, "--dither")? {
+            Some(d) => Some(d.parse::<Dither>().map_err(CliError::Arguments)?),
+            None => None,
+        };
+        let seed = match take_flag(args, "--seed")? {
+            Some(s) => s.parse::<u64>()?,
+            None => 0,
+        };
+        Ok(Self {
+            format,
+            raw,
+            dither,
+            seed,
+        })
+    }
+}
+
+/// Several inputs processed in parallel, instead of one input and one output.
+struct BatchOptions {
+    /// Files or glob patterns.
+    inputs: Vec<String>,
+    /// Output names, with `{dir}`, `{stem}` and `{chain}` filled in for each input.
+    output: String,
+    jobs: usize,
+}
+
+impl BatchOptions {
+    fn take_from(args: &mut Vec<String>) -> Result<Option<Self>, CliError> {
+        let inputs = take_flags(args, "--input")?;
+        let output = take_flag(args, "--output")?;
+        let jobs = match take_flag(args, "--jobs")? {
+            Some(j) => j.parse::<usize>()?,
+            None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        };
+        if inputs.is_empty() {
+            return match output {
+                Some(_) => Err(CliError::Arguments(String::from(
+                    "--output names the outputs of --input",
+                ))),
+                None => Ok(None),
+            };
+        }
+        Ok(Some(Self {
+            inputs,
+            output: output.unwrap_or_else(|| String::from("{dir}/{stem}_{chain}.wav")),
+            jobs,
+        }))
+    }
+}
+
+/// Parses the `[mix <ratio>] [iterations] option` arguments, whose parameters can be swept, and
+/// the `split` and `channels` groups of branches, and the operations on the extra `inputs`.
+fn parse_chain(
+    option_arguments: &[String],
+    inputs: &[String],
+) -> Result<Vec<Node<Sweep>>, CliError> {
+    let (chain, rest) = parse_nodes(option_arguments, inputs)?;
+    match rest.first() {
+        None => Ok(chain),
+        Some(word) => Err(CliError::Arguments(format!(
+            "{} is only allowed in a split or channels group",
+            word
+        ))),
+    }
+}
+
+/// Parses nodes up to the end of the branch they are in, returning the arguments after them.
+fn parse_nodes<'a>(
+    mut option_arguments: &'a [String],
+    inputs: &[String],
+) -> Result<(Vec<Node<Sweep>>, &'a [String]), CliError> {
+    let mut chain = Vec::new();
+    loop {
+        match option_arguments.first().map(String::as_str) {
+            None | Some("branch" | "sum" | "channel" | "merge") => {
+                return Ok((chain, option_arguments))
             }
-            audio_buffer = decimate(audio_buffer, option_arguments[1].parse::<f32>()?);
-            option_arguments = &option_arguments[2..];
-        } else if "delaypitch".starts_with(&option_arguments[0]) {
-            if option_arguments.len() < 3 {
-                return Err(CliError::Arguments(String::from(
-                    "delaypitch takes a decimal factor and an integer log_size",
-                )));
+            Some("split") => {
+                let mut branches = Vec::new();
+                option_arguments = &option_arguments[1..];
+                loop {
+                    match option_arguments {
+                        [branch, gain, rest @ ..] if branch == "branch" => {
+                            let (branch, rest) = parse_nodes(rest, inputs)?;
+                            branches.push((float(&GAIN, gain)?, branch));
+                            option_arguments = rest;
+                        }
+                        [sum, rest @ ..] if sum == "sum" && !branches.is_empty() => {
+                            option_arguments = rest;
+                            break;
+                        }
+                        _ => {
+                            return Err(CliError::Arguments(String::from(
+                                "split takes branch <gain> followed by its options, for every \
+                                 branch, and ends with sum",
+                            )))
+                        }
+                    }
+                }
+                chain.push(Node::Split(branches));
+                continue;
             }
-            let factor = option_arguments[1].parse::<f32>()?;
-            let log_size = option_arguments[2].parse::<u8>()?;
-            audio_buffer = run(
-                |ab: AudioBuffer| delay_pitch(ab, factor, log_size),
-                audio_buffer,
-                iterations,
-            );
-            option_arguments = &option_arguments[3..];
-        } else if "delayrotate".starts_with(&option_arguments[0]) {
-            if option_arguments.len() < 4 {
-                return Err(CliError::Arguments(String::from(
-                    "delayrotate takes a delay, feedback and frequency",
-                )));
+            Some("channels") => {
+                let mut branches = Vec::new();
+                option_arguments = &option_arguments[1..];
+                loop {
+                    match option_arguments {
+                        [channel, index, rest @ ..] if channel == "channel" => {
+                            let (branch, rest) = parse_nodes(rest, inputs)?;
+                            branches.push((index.parse::<u16>()?, branch));
+                            option_arguments = rest;
+                        }
+                        [merge, rest @ ..] if merge == "merge" && !branches.is_empty() => {
+                            option_arguments = rest;
+                            break;
+                        }
+                        _ => {
+                            return Err(CliError::Arguments(String::from(
+                                "channels takes channel <index> followed by its options, for \
+                                 every channel, and ends with merge",
+                            )))
+                        }
+                    }
+                }
+                chain.push(Node::Channels(branches));
+                continue;
             }
-            let delay = option_arguments[1].parse::<usize>()?;
-            let feedback = option_arguments[2].parse::<f32>()?;
-            let frequency = option_arguments[3].parse::<f32>()?;
-            audio_buffer = run(
-                |ab: AudioBuffer| delay_rotate(ab, delay, feedback, frequency),
-                audio_buffer,
-                iterations,
-            );
-            option_arguments = &option_arguments[4..];
-        } else if "speed".starts_with(&option_arguments[0]) {
-            if option_arguments.len() < 2 {
-                return Err(CliError::Arguments(String::from(
-                    "speed takes a decimal speed",
-                )));
+            Some(_) => {}
+        }
+        if let Some((node, taken)) = parse_combine(option_arguments, inputs)? {
+            chain.push(node);
+            option_arguments = &option_arguments[taken..];
+            continue;
+        }
+        let mix = match option_arguments {
+            [mix, ratio, _, ..] if mix == "mix" => {
+                option_arguments = &option_arguments[2..];
+                Some(MIX.parse_sweep(ratio)?)
             }
-            let s = option_arguments[1].parse::<f32>()?;
-            audio_buffer = run(|ab: AudioBuffer| speed(ab, s), audio_buffer, iterations);
-            option_arguments = &option_arguments[2..];
-        } else if "gain".starts_with(&option_arguments[0]) {
-            if option_arguments.len() < 2 {
+            [mix, ..] if mix == "mix" => {
                 return Err(CliError::Arguments(String::from(
-                    "gain takes a decimal gain",
-                )));
+                    "mix takes a ratio followed by the option it mixes",
+                )))
             }
-            let g = option_arguments[1].parse::<f32>()?;
-            audio_buffer = run(|ab: AudioBuffer| gain(ab, g), audio_buffer, iterations);
-            option_arguments = &option_arguments[2..];
-        } else if "dc".starts_with(&option_arguments[0]) {
-            if option_arguments.len() < 2 {
-                return Err(CliError::Arguments(String::from("dc takes a decimal dc")));
+            _ => None,
+        };
+        let iterations = match option_arguments[0].parse::<u32>() {
+            Ok(i) => {
+                option_arguments = &option_arguments[1..];
+                i
             }
-            let dc = option_arguments[1].parse::<f32>()?;
-            audio_buffer = run(|ab: AudioBuffer| add_dc(ab, dc), audio_buffer, iterations);
-            option_arguments = &option_arguments[2..];
-        } else if "removedc".starts_with(&option_arguments[0]) {
-            audio_buffer = remove_dc(audio_buffer);
-            option_arguments = &option_arguments[1..];
-        } else if "normalize".starts_with(&option_arguments[0]) {
-            audio_buffer = normalize(audio_buffer);
-            option_arguments = &option_arguments[1..];
+            Err(_) => 1,
+        };
+        let effect = option_arguments
+            .first()
+            .and_then(|name| find(name))
+            .ok_or_else(|| {
+                CliError::Arguments(format!(
+                    "Unknown option {}\n{}",
+                    option_arguments.first().map_or("", |name| name.as_str()),
+                    usage(),
+                ))
+            })?;
+        let params = effect.params();
+        let required = params.iter().filter(|param| !param.optional).count();
+        if option_arguments.len() <= required {
+            return Err(CliError::Arguments(effect.expected_args()));
+        }
+        option_arguments = &option_arguments[1..];
+        let mut values = Vec::new();
+        for param in params {
+            // an optional argument is only taken if it is one, else it starts the next option
+            let value = match option_arguments.first() {
+                Some(arg) if param.optional => param.parse_sweep(arg).ok(),
+                Some(arg) => Some(param.parse_sweep(arg)?),
+                None => None,
+            };
+            match value {
+                Some(value) => {
+                    values.push(value);
+                    option_arguments = &option_arguments[1..];
+                }
+                None => values.push(vec![param.default.clone()]),
+            }
+        }
+        chain.push(Node::Effect(Sweep {
+            effect,
+            iterations,
+            values,
+            mix,
+        }));
+    }
+}
+
+/// Gain of a `split` branch or of a mixed input.
+static GAIN: Param = Param::float("gain", 1.);
+
+/// How much `duck` lowers the input, from not at all at 0 to silence at 1.
+static DEPTH: Param = Param::float("depth", 0.5).at_least(0.).at_most(1.);
+
+/// Parses a constant float given to `param`, which checks it is finite and in range.
+fn float(param: &'static Param, arg: &str) -> Result<f32, CliError> {
+    match param.parse(arg)? {
+        Value::Float(x) => Ok(x as f32),
+        value => unreachable!("{} isn't a constant float", value),
+    }
+}
+
+/// Parses an operation on one of the extra `inputs`, if the arguments start with one, returning
+/// the number of arguments it takes.
+fn parse_combine(
+    option_arguments: &[String],
+    inputs: &[String],
+) -> Result<Option<(Node<Sweep>, usize)>, CliError> {
+    let arity = match option_arguments.first().map(String::as_str) {
+        // mix is also the dry/wet ratio of an option, input names can't be mistaken for ratios
+        Some("mix")
+            if option_arguments
+                .get(1)
+                .is_some_and(|name| inputs.contains(name)) =>
+        {
+            2
+        }
+        Some("concat" | "ring") => 1,
+        Some("crossfade") => 2,
+        Some("duck") => 3,
+        _ => return Ok(None),
+    };
+    let operation = option_arguments[0].as_str();
+    if option_arguments.len() <= arity {
+        return Err(CliError::Arguments(match operation {
+            "mix" => String::from("mix takes an input and its gain"),
+            "crossfade" => String::from("crossfade takes an input and the length of the fade"),
+            "duck" => String::from("duck takes an input, a depth and a release time"),
+            _ => format!("{} takes an input", operation),
+        }));
+    }
+    let name = &option_arguments[1];
+    if !inputs.contains(name) {
+        return Err(CliError::Arguments(format!(
+            "there is no input named {}, inputs are given with --with <name>=<file>",
+            name
+        )));
+    }
+    let args = &option_arguments[2..=arity];
+    let combine = match operation {
+        "mix" => Combine::Mix(float(&GAIN, &args[0])?),
+        "concat" => Combine::Concat,
+        "crossfade" => Combine::Crossfade(args[0].parse().map_err(CliError::Arguments)?),
+        "ring" => Combine::Ring,
+        _ => Combine::Duck {
+            depth: float(&DEPTH, &args[0])?,
+            release: args[1].parse().map_err(CliError::Arguments)?,
+        },
+    };
+    Ok(Some((Node::Input(name.clone(), combine), 1 + arity)))
+}
+
+/// Frames read, processed and written at a time when streaming.
+const BLOCK_FRAMES: usize = 4096;
+
+/// Input file, read block by block when its container allows it.
+enum Source {
+    Wav(WavReader<Box<dyn Read>>),
+    Raw(RawReader<Box<dyn Read>>),
+    /// Already decoded audio and the number of samples handed out so far.
+    Buffer(AudioBuffer, usize),
+}
+
+impl Source {
+    fn open(in_filename: &str, input_options: &InputOptions) -> Result<Self, CliError> {
+        // - stands for stdin and stdout, which can't seek so both are only read or written in order
+        let mut input: Box<dyn Read> = if in_filename == "-" {
+            Box::new(io::stdin())
         } else {
-            return Err(CliError::Arguments(format!(
-                "Unknown option {}\n{}",
-                option_arguments[0], USAGE,
-            )));
+            Box::new(BufReader::new(File::open(in_filename)?))
+        };
+        if let Some(spec) = &input_options.raw {
+            return Ok(Source::Raw(RawReader::new(input, spec)?));
+        }
+
+        // the magic is put back in front of the rest, as stdin can't seek
+        let mut magic = Vec::new();
+        input.by_ref().take(4).read_to_end(&mut magic)?;
+        let mut input: Box<dyn Read> = Box::new(Cursor::new(magic.clone()).chain(input));
+        Ok(match &magic[..] {
+            b"RIFF" | b"RF64" | b"BW64" => Source::Wav(WavReader::new(input)?),
+            _ => Source::Buffer(read_audio(&mut input)?, 0),
+        })
+    }
+
+    fn metadata(&self) -> &AudioMetadata {
+        match self {
+            Source::Wav(wav) => wav.metadata(),
+            Source::Raw(raw) => raw.metadata(),
+            Source::Buffer(audio_buffer, _) => &audio_buffer.metadata,
+        }
+    }
+
+    /// Appends up to `frames` frames to `samples`, returning how many were read.
+    fn read(&mut self, samples: &mut Vec<f32>, frames: usize) -> Result<usize, WavError> {
+        match self {
+            Source::Wav(wav) => wav.read(samples, frames),
+            Source::Raw(raw) => raw.read(samples, frames),
+            Source::Buffer(audio_buffer, position) => {
+                let channels = usize::from(audio_buffer.metadata.channels);
+                let end = audio_buffer.data.len().min(*position + frames * channels);
+                samples.extend_from_slice(&audio_buffer.data[*position..end]);
+                let read = (end - *position) / channels;
+                *position = end;
+                Ok(read)
+            }
+        }
+    }
+
+    /// Complete metadata, including what came after the samples.
+    fn finish(self) -> AudioMetadata {
+        match self {
+            Source::Wav(wav) => wav.finish(),
+            Source::Raw(raw) => raw.metadata().clone(),
+            Source::Buffer(audio_buffer, _) => audio_buffer.metadata,
+        }
+    }
+
+    fn read_all(mut self) -> Result<AudioBuffer, WavError> {
+        if let Source::Buffer(audio_buffer, 0) = self {
+            return Ok(audio_buffer);
         }
+        let mut data = Vec::new();
+        while self.read(&mut data, 1 << 16)? > 0 {}
+        Ok(AudioBuffer {
+            metadata: self.finish(),
+            data,
+        })
     }
-    write_wav(&mut File::create(out_filename)?, &audio_buffer).or_else(|e| Err(e.into()))
 }
 
-static OPTIONS: &str = "\
-interpolate
-fractalize <depth>
-expand
-reversepseudocycles
-fold
-hardclip <threshold>
-softclip <amount>
-tense <tension>
-tensepseudocycles <tension>
-decimate <depth>
-delaypitch <factor> <log_size>
-delayrotate <delay> <feedback> <frequency>
-speed <speed>
-gain <gain>
-dc <dc>
-removedc
-normalize";
+/// Runs the blocks of `source` through `processor` and `ditherer` into `write`.
+fn stream<F: FnMut(&[f32]) -> io::Result<()>>(
+    source: &mut Source,
+    processor: &mut dyn Processor,
+    ditherer: &mut Ditherer,
+    mut write: F,
+) -> Result<(), CliError> {
+    let mut block = Vec::new();
+    let mut frames = BLOCK_FRAMES.max(processor.lookahead());
+    loop {
+        block.clear();
+        if source.read(&mut block, frames)? == 0 {
+            return Ok(());
+        }
+        processor.process(&mut block);
+        ditherer.process(&mut block);
+        write(&block)?;
+        frames = BLOCK_FRAMES;
+    }
+}
+
+fn open_output(out_filename: &str) -> Result<Box<dyn Write>, CliError> {
+    Ok(if out_filename == "-" {
+        Box::new(BufWriter::new(io::stdout()))
+    } else {
+        Box::new(BufWriter::new(File::create(out_filename)?))
+    })
+}
+
+fn do_main(
+    mut source: Source,
+    out_filename: &str,
+    chain: &[Node<Invocation>],
+    inputs: &[Input],
+    region: Option<Region>,
+    output_options: &OutputOptions,
+) -> Result<(), CliError> {
+    let metadata = source.metadata().clone();
+    let mut stage = build(chain, &metadata, inputs).map_err(CliError::Arguments)?;
+    if region.is_some() && sample_rate(chain, metadata.sample_rate) != metadata.sample_rate {
+        return Err(CliError::Arguments(String::from(
+            "--range can't be used when the sample rate changes",
+        )));
+    }
+    if let Some(region) = region {
+        stage = region.restrict(stage);
+    }
+
+    let source_format = source.metadata().sample_format;
+    let container = Container::from_path(out_filename);
+    let format = match (output_options.raw, output_options.format) {
+        (Some(raw), _) => raw.format,
+        (None, Some(format)) => format,
+        (None, None) => container.default_format(source_format),
+    };
+    let kind = output_options
+        .dither
+        .unwrap_or_else(|| Dither::default_for(source_format, format, !chain.is_empty()));
+
+    let streamable = output_options.raw.is_some() || matches!(container, Container::Wav);
+    if let (true, Stage::Stream(processor)) = (streamable, &mut stage) {
+        let mut ditherer = Ditherer::new(kind, format, metadata.channels, output_options.seed);
+        return match output_options.raw {
+            Some(raw) => {
+                let mut output = open_output(out_filename)?;
+                stream(&mut source, processor.as_mut(), &mut ditherer, |block| {
+                    write_raw_samples(&mut output, block, raw)
+                })?;
+                output.flush().map_err(|e| e.into())
+            }
+            None if out_filename == "-" => {
+                // chunks that come after the samples in the input are lost, the header is
+                // already written by the time they are read
+                let output = BufWriter::new(io::stdout());
+                let mut wav = WavWriter::new(output, &metadata, format)?;
+                stream(&mut source, processor.as_mut(), &mut ditherer, |block| {
+                    wav.write(block)
+                })?;
+                wav.finish()?.flush().map_err(|e| e.into())
+            }
+            None => {
+                let output = BufWriter::new(File::create(out_filename)?);
+                let mut wav = WavWriter::new(output, &metadata, format)?;
+                stream(&mut source, processor.as_mut(), &mut ditherer, |block| {
+                    wav.write(block)
+                })?;
+                let chunks = source.finish().chunks;
+                wav.finish_with_sizes(&chunks)?
+                    .flush()
+                    .map_err(|e| e.into())
+            }
+        };
+    }
+
+    let mut audio_buffer = stage.apply(source.read_all()?);
+    audio_buffer = dither(audio_buffer, format, kind, output_options.seed);
+    let mut output = open_output(out_filename)?;
+    match output_options.raw {
+        Some(raw) => write_raw(&mut output, &audio_buffer, raw)?,
+        None => write_audio(&mut output, &audio_buffer, container, format)?,
+    }
+    output.flush().map_err(|e| e.into())
+}
+
+/// Output file and the chain rendered into it.
+type Output<'a> = (String, &'a [Node<Invocation>]);
+
+/// Renders each `(output, chain)` pair from `source`, which is only decoded once.
+fn do_variants(
+    source: Source,
+    outputs: &[Output],
+    inputs: &[Input],
+    region: Option<Region>,
+    output_options: &OutputOptions,
+) -> Result<(), CliError> {
+    if let [(out_filename, chain)] = outputs {
+        return do_main(source, out_filename, chain, inputs, region, output_options);
+    }
+    let audio_buffer = source.read_all()?;
+    for (out_filename, chain) in outputs {
+        let source = Source::Buffer(audio_buffer.clone(), 0);
+        do_main(source, out_filename, chain, inputs, region, output_options)?;
+    }
+    Ok(())
+}
+
+/// Processes every input of `batch`, going on after failures, which are all reported at the end.
+fn do_batch(
+    batch: &BatchOptions,
+    variants: &[Variant],
+    inputs: &[Input],
+    region: Option<Region>,
+    input_options: &InputOptions,
+    output_options: &OutputOptions,
+) -> Result<(), CliError> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for pattern in &batch.inputs {
+        if pattern == "-" {
+            return Err(CliError::Arguments(String::from(
+                "stdin can't be one of several inputs",
+            )));
+        }
+        let paths = glob(pattern)?;
+        if paths.is_empty() {
+            return Err(CliError::Arguments(format!("no file matches {}", pattern)));
+        }
+        files.extend(paths);
+    }
+    let mut jobs: Vec<(PathBuf, Vec<Output>)> = Vec::new();
+    for input in files {
+        let mut outputs = Vec::new();
+        for (_, chain) in variants {
+            let output = output_path(&batch.output, &input, &graph_name(chain))
+                .map_err(CliError::Arguments)?;
+            let taken = jobs
+                .iter()
+                .flat_map(|(_, outputs)| outputs)
+                .chain(&outputs)
+                .any(|(o, _)| *o == output);
+            if taken || Path::new(&output) == input {
+                return Err(CliError::Arguments(format!(
+                    "{} would be written more than once or overwrite an input, the output \
+                     template needs {{dir}} and {{stem}}, and {{chain}} for sweeps",
+                    output
+                )));
+            }
+            outputs.push((output, chain.as_slice()));
+        }
+        jobs.push((input, outputs));
+    }
+
+    let results = run_parallel(&jobs, batch.jobs, |(input, outputs)| {
+        catch_unwind(AssertUnwindSafe(|| {
+            let source = Source::open(&input.to_string_lossy(), input_options)?;
+            do_variants(source, outputs, inputs, region, output_options)
+        }))
+        .unwrap_or(Err(CliError::Panicked))
+    });
+
+    let mut failed = 0;
+    let mut code = 0;
+    for ((input, _), result) in jobs.iter().zip(results) {
+        if let Err(e) = result {
+            eprintln!("{}: {}", input.display(), e);
+            if failed == 0 {
+                code = e.exit_code();
+            }
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(CliError::Batch {
+            failed,
+            total: jobs.len(),
+            code,
+        });
+    }
+    Ok(())
+}
 
 static USAGE: &str = "\
-usage: screech input_file [[iterations] option]... output_file
+usage: screech [--format <format>] [--dither <dither>] [--seed <seed>]
+               [--raw-in <raw_format>:<channels>:<sample_rate>] [--raw-out <raw_format>]
+               [--with <name>=<file>]...
+               [--preset <preset_file>] [--save-preset <preset_file>]
+               [--range <start>..<end>] [--crossfade <time>]
+               input_file [[mix <ratio>] [iterations] option]... output_file
+       screech [--input <input_file_or_glob>]... [--output <template>] [--jobs <jobs>]
+               [other flags] [[mix <ratio>] [iterations] option]...
+input_file and output_file can be - for stdin and stdout, output to stdout is WAV unless
+--raw-out is used
+with --input, every input is processed in parallel and output names come from the template,
+where {dir}, {stem} and {chain} are filled in (defaults to {dir}/{stem}_{chain}.wav)
+parameters in [] in the list of options can be left out, taking their default
+parameters listed after ~ in the list of options can be automated, instead of a number:
+  time:value,...   breakpoints, with times in seconds
+  @file.csv        breakpoints from time,value lines
+  lfo:shape:frequency:low:high   an LFO, shape is sine, triangle, saw or square
+mix <ratio> before [iterations] option mixes its result with its input, from only the input at 0
+to only the result at 1, the ratio can be automated
+option parameters can be swept to render every combination: [a,b,c] lists values and a..b:n
+spreads n of them from a to b, the swept values are added to output_file's name, automations
+can't be swept
+split branch <gain> [option]... branch <gain> [option]... sum
+  feeds the input to every branch, processed by its own options, and sums their results
+channels channel <index> [option]... channel <index> [option]... merge
+  processes each listed channel, from 0, with its own options, the others are left alone
+groups can be nested, and branches can be empty to keep the input as is
+--with gives extra inputs, with the channels of input_file (any channels for duck) and
+resampled to its sample rate, that these bring in by name:
+  mix <name> <gain>          adds the input at that gain
+  concat <name>              appends the input
+  crossfade <name> <time>    appends the input, fading to it over that time
+  ring <name>                multiplies by the input
+  duck <name> <depth> <release>   turns down by up to depth, from 0 to 1, when the input is
+                             loud, coming back up over the release time
+--range restricts the options to part of the input, crossfading over --crossfade at its edges
+(defaults to 10ms), times are in frames, or in seconds with s or ms after them, and either
+end of the range can be left out
+the options of --preset come before those of the command line, --save-preset writes all of
+them to a file that --preset can read
+available formats:
+  pcm8 pcm16 pcm24 pcm32 float32 float64 (defaults to the input file's format, or pcm24
+  when writing floating point audio to FLAC)
+available raw formats, for headerless files:
+  u8 s16 s24 s32 f32 f64, followed by le or be for the byte order (defaults to le)
+available dithers:
+  none rect tpdf shaped (defaults to tpdf when writing pcm8 or pcm16 after processing or
+  reducing the bit depth, none otherwise)
 available options:
-  interpolate
-  fractalize <depth>
-  expand
-  reversepseudocycles
-  fold
-  hardclip <threshold>
-  softclip <amount>
-  tense <tension>
-  tensepseudocycles <tension>
-  decimate <depth>
-  delaypitch <factor> <log_size>
-  delayrotate <delay> <feedback> <frequency>
-  speed <speed>
-  gain <gain>
-  dc <dc>
-  removedc
-  normalize
-
-short versions are tried in that order";
+";
+
+fn usage() -> String {
+    let mut usage = String::from(USAGE);
+    for effect in EFFECTS {
+        usage += &format!("  {}", effect.synopsis());
+        let automatable: Vec<&str> = effect
+            .params()
+            .iter()
+            .filter(|param| param.automatable)
+            .map(|param| param.name)
+            .collect();
+        if !automatable.is_empty() {
+            usage += &format!("    ~ {}", automatable.join(" "));
+        }
+        usage += "\n";
+    }
+    usage + "\nshort versions are tried in that order"
+}
 
 fn main() {
-    let args: Vec<String> = args().collect();
+    let mut args: Vec<String> = args().collect();
 
     if args.len() == 2 && args[1] == "dump_options" {
-        println!("{OPTIONS}");
+        for effect in EFFECTS {
+            println!("{}", effect.synopsis());
+        }
         return;
     }
-
-    if args.len() < 3 {
-        eprintln!("{USAGE}");
-        exit(1);
+    if args.len() == 3 && args[1] == "dump_options" && args[2] == "--json" {
+        println!("{}", catalogue_json());
+        return;
     }
 
-    if let Err(err) = do_main(&args[1], &args[args.len() - 1], &args[2..args.len() - 1]) {
-        match err {
-            CliError::Io(e) => {
-                eprintln!("{}", e.to_string());
-                exit(2)
-            }
-            CliError::ParseInt(e) => {
-                eprintln!("{}", e.to_string());
-                exit(3)
-            }
-            CliError::ParseFloat(e) => {
-                eprintln!("{}", e.to_string());
-                exit(3)
-            }
-            CliError::Arguments(s) => {
-                eprintln!("{}", s);
-                exit(1);
-            }
+    let result = InputOptions::take_from(&mut args).and_then(|input_options| {
+        let chain_options = ChainOptions::take_from(&mut args)?;
+        let output_options = OutputOptions::take_from(&mut args)?;
+        if let Some(batch) = BatchOptions::take_from(&mut args)? {
+            let variants = chain_options.chain(&args[1..], &input_options.names())?;
+            let inputs = input_options.load_extra()?;
+            return do_batch(
+                &batch,
+                &variants,
+                &inputs,
+                chain_options.region,
+                &input_options,
+                &output_options,
+            );
+        }
+        if args.len() < 3 {
+            return Err(CliError::Arguments(usage()));
         }
+        let variants = chain_options.chain(&args[2..args.len() - 1], &input_options.names())?;
+        let out_filename = &args[args.len() - 1];
+        if variants.len() > 1 && out_filename == "-" {
+            return Err(CliError::Arguments(String::from(
+                "a sweep writes several files, it can't output to stdout",
+            )));
+        }
+        let outputs: Vec<Output> = variants
+            .iter()
+            .map(|(label, chain)| (variant_path(out_filename, label), chain.as_slice()))
+            .collect();
+        let inputs = input_options.load_extra()?;
+        let source = Source::open(&args[1], &input_options)?;
+        do_variants(
+            source,
+            &outputs,
+            &inputs,
+            chain_options.region,
+            &output_options,
+        )
+    });
+
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        exit(err.exit_code());
     }
 }
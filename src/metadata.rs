@@ -0,0 +1,396 @@
+//! Descriptive chunks that accompany the audio data: text tags, markers, loops and broadcast
+//! information. Positions are expressed in frames, i.e. samples per channel.
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChunkMetadata {
+    /// LIST/INFO entries, e.g. INAM for the title or IART for the artist.
+    pub info: Vec<([u8; 4], String)>,
+    pub cues: Vec<CuePoint>,
+    pub sampler: Option<Sampler>,
+    pub instrument: Option<Instrument>,
+    pub broadcast: Option<Broadcast>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CuePoint {
+    pub id: u32,
+    pub position: u32,
+    /// Label from the LIST/adtl chunk.
+    pub label: Option<String>,
+}
+
+/// Contents of the `smpl` chunk.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sampler {
+    pub manufacturer: u32,
+    pub product: u32,
+    pub sample_period: u32,
+    pub midi_unity_note: u32,
+    pub midi_pitch_fraction: u32,
+    pub smpte_format: u32,
+    pub smpte_offset: u32,
+    pub loops: Vec<SampleLoop>,
+    pub sampler_data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SampleLoop {
+    pub id: u32,
+    /// 0 for forward, 1 for alternating and 2 for backward loops.
+    pub kind: u32,
+    pub start: u32,
+    pub end: u32,
+    pub fraction: u32,
+    /// 0 for infinite looping.
+    pub play_count: u32,
+}
+
+/// Contents of the `inst` chunk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instrument {
+    pub unshifted_note: u8,
+    pub fine_tune: i8,
+    pub gain: i8,
+    pub low_note: u8,
+    pub high_note: u8,
+    pub low_velocity: u8,
+    pub high_velocity: u8,
+}
+
+/// Contents of the `bext` chunk from the Broadcast Wave Format.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Broadcast {
+    pub description: String,
+    pub originator: String,
+    pub originator_reference: String,
+    pub origination_date: String,
+    pub origination_time: String,
+    /// Position of the first sample since midnight, in samples.
+    pub time_reference: u64,
+    /// Version, UMID, loudness values and coding history, kept as is.
+    pub extension: Vec<u8>,
+}
+
+impl ChunkMetadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Moves cue points and loops so that they stay on the same audio after its length has been
+    /// multiplied by `ratio`.
+    pub fn rescale(&mut self, ratio: f64) {
+        self.remap(|position| (f64::from(position) * ratio).round() as u32);
+    }
+
+    /// Moves every position to `f(position)`, for effects that don't stretch time evenly.
+    pub fn remap<F: Fn(u32) -> u32>(&mut self, f: F) {
+        for cue in &mut self.cues {
+            cue.position = f(cue.position);
+        }
+        if let Some(sampler) = &mut self.sampler {
+            for sample_loop in &mut sampler.loops {
+                sample_loop.start = f(sample_loop.start);
+                sample_loop.end = f(sample_loop.end);
+            }
+        }
+    }
+
+    /// Entries that `header` doesn't have, or has with other values, which readers merge into it
+    /// when they come in later chunks.
+    pub(crate) fn missing_from(&self, header: &ChunkMetadata) -> ChunkMetadata {
+        ChunkMetadata {
+            info: self
+                .info
+                .iter()
+                .filter(|(id, _)| !header.info.iter().any(|(i, _)| i == id))
+                .cloned()
+                .collect(),
+            cues: self
+                .cues
+                .iter()
+                .filter(|cue| !header.cues.contains(cue))
+                .cloned()
+                .collect(),
+            sampler: self
+                .sampler
+                .clone()
+                .filter(|_| self.sampler != header.sampler),
+            instrument: self
+                .instrument
+                .clone()
+                .filter(|_| self.instrument != header.instrument),
+            broadcast: self
+                .broadcast
+                .clone()
+                .filter(|_| self.broadcast != header.broadcast),
+        }
+    }
+
+    /// Merges the contents of a RIFF chunk into the metadata, ignoring chunks that aren't known.
+    pub(crate) fn read_riff_chunk(&mut self, id: &[u8; 4], body: &[u8]) {
+        let mut body = Bytes(body);
+        match id {
+            b"LIST" => self.read_list(body),
+            b"cue " => {
+                let count = body.u32();
+                for _ in 0..count {
+                    if body.0.len() < 24 {
+                        break;
+                    }
+                    let id = body.u32();
+                    let position = body.u32();
+                    // data chunk id, chunk start, block start, then the actual sample offset
+                    body.skip(12);
+                    let sample_offset = body.u32();
+                    let position = if position == 0 {
+                        sample_offset
+                    } else {
+                        position
+                    };
+                    match self.cues.iter_mut().find(|cue| cue.id == id) {
+                        Some(cue) => cue.position = position,
+                        None => self.cues.push(CuePoint {
+                            id,
+                            position,
+                            label: None,
+                        }),
+                    }
+                }
+            }
+            b"smpl" => {
+                let mut sampler = Sampler {
+                    manufacturer: body.u32(),
+                    product: body.u32(),
+                    sample_period: body.u32(),
+                    midi_unity_note: body.u32(),
+                    midi_pitch_fraction: body.u32(),
+                    smpte_format: body.u32(),
+                    smpte_offset: body.u32(),
+                    ..Sampler::default()
+                };
+                let count = body.u32();
+                let data_size = body.u32();
+                for _ in 0..count {
+                    if body.0.len() < 24 {
+                        break;
+                    }
+                    sampler.loops.push(SampleLoop {
+                        id: body.u32(),
+                        kind: body.u32(),
+                        start: body.u32(),
+                        end: body.u32(),
+                        fraction: body.u32(),
+                        play_count: body.u32(),
+                    })
+                }
+                sampler.sampler_data = body.take(data_size as usize).to_vec();
+                self.sampler = Some(sampler);
+            }
+            b"inst" => {
+                let b = body.take(7);
+                if b.len() == 7 {
+                    self.instrument = Some(Instrument {
+                        unshifted_note: b[0],
+                        fine_tune: b[1] as i8,
+                        gain: b[2] as i8,
+                        low_note: b[3],
+                        high_note: b[4],
+                        low_velocity: b[5],
+                        high_velocity: b[6],
+                    })
+                }
+            }
+            b"bext" => {
+                self.broadcast = Some(Broadcast {
+                    description: body.string(256),
+                    originator: body.string(32),
+                    originator_reference: body.string(32),
+                    origination_date: body.string(10),
+                    origination_time: body.string(8),
+                    time_reference: u64::from(body.u32()) | u64::from(body.u32()) << 32,
+                    extension: body.0.to_vec(),
+                })
+            }
+            _ => {}
+        }
+    }
+
+    fn read_list(&mut self, mut body: Bytes) {
+        let list_type = body.take(4).to_vec();
+        while body.0.len() >= 8 {
+            let mut id = [0; 4];
+            id.copy_from_slice(body.take(4));
+            let size = body.u32() as usize;
+            let mut sub_chunk = Bytes(body.take(size));
+            body.skip(size % 2);
+            match &list_type[..] {
+                b"INFO" => self.info.push((id, sub_chunk.string(size))),
+                b"adtl" if &id == b"labl" => {
+                    let cue_id = sub_chunk.u32();
+                    let label = sub_chunk.string(size);
+                    if let Some(cue) = self.cues.iter_mut().find(|cue| cue.id == cue_id) {
+                        cue.label = Some(label);
+                    } else {
+                        // the cue chunk might come after its labels
+                        self.cues.push(CuePoint {
+                            id: cue_id,
+                            position: ORPHAN_POSITION,
+                            label: Some(label),
+                        })
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Drops the labels whose cue point never showed up.
+    pub(crate) fn drop_orphan_labels(&mut self) {
+        self.cues.retain(|cue| cue.position != ORPHAN_POSITION);
+    }
+
+    /// Serializes the metadata as RIFF chunks, without padding.
+    pub(crate) fn riff_chunks(&self) -> Vec<([u8; 4], Vec<u8>)> {
+        let mut chunks = Vec::new();
+
+        if let Some(bext) = &self.broadcast {
+            let mut body = Vec::new();
+            put_string(&mut body, &bext.description, 256);
+            put_string(&mut body, &bext.originator, 32);
+            put_string(&mut body, &bext.originator_reference, 32);
+            put_string(&mut body, &bext.origination_date, 10);
+            put_string(&mut body, &bext.origination_time, 8);
+            body.extend_from_slice(&(bext.time_reference as u32).to_le_bytes());
+            body.extend_from_slice(&((bext.time_reference >> 32) as u32).to_le_bytes());
+            body.extend_from_slice(&bext.extension);
+            chunks.push((*b"bext", body));
+        }
+
+        if !self.info.is_empty() {
+            let mut body = b"INFO".to_vec();
+            for (id, text) in &self.info {
+                let mut text = text.as_bytes().to_vec();
+                text.push(0);
+                put_sub_chunk(&mut body, id, &text);
+            }
+            chunks.push((*b"LIST", body));
+        }
+
+        let cues = &self.cues;
+        if !cues.is_empty() {
+            let mut body = (cues.len() as u32).to_le_bytes().to_vec();
+            for cue in cues {
+                for x in &[cue.id, cue.position] {
+                    body.extend_from_slice(&x.to_le_bytes());
+                }
+                body.extend_from_slice(b"data");
+                for x in &[0, 0, cue.position] {
+                    body.extend_from_slice(&x.to_le_bytes());
+                }
+            }
+            chunks.push((*b"cue ", body));
+
+            if cues.iter().any(|cue| cue.label.is_some()) {
+                let mut body = b"adtl".to_vec();
+                for cue in cues {
+                    if let Some(label) = &cue.label {
+                        let mut labl = cue.id.to_le_bytes().to_vec();
+                        labl.extend_from_slice(label.as_bytes());
+                        labl.push(0);
+                        put_sub_chunk(&mut body, b"labl", &labl);
+                    }
+                }
+                chunks.push((*b"LIST", body));
+            }
+        }
+
+        if let Some(smpl) = &self.sampler {
+            let mut body = Vec::new();
+            for x in &[
+                smpl.manufacturer,
+                smpl.product,
+                smpl.sample_period,
+                smpl.midi_unity_note,
+                smpl.midi_pitch_fraction,
+                smpl.smpte_format,
+                smpl.smpte_offset,
+                smpl.loops.len() as u32,
+                smpl.sampler_data.len() as u32,
+            ] {
+                body.extend_from_slice(&x.to_le_bytes());
+            }
+            for l in &smpl.loops {
+                for x in &[l.id, l.kind, l.start, l.end, l.fraction, l.play_count] {
+                    body.extend_from_slice(&x.to_le_bytes());
+                }
+            }
+            body.extend_from_slice(&smpl.sampler_data);
+            chunks.push((*b"smpl", body));
+        }
+
+        if let Some(inst) = &self.instrument {
+            let body = vec![
+                inst.unshifted_note,
+                inst.fine_tune as u8,
+                inst.gain as u8,
+                inst.low_note,
+                inst.high_note,
+                inst.low_velocity,
+                inst.high_velocity,
+            ];
+            chunks.push((*b"inst", body));
+        }
+
+        chunks
+    }
+}
+
+/// Position of cue points that have only been seen in a label so far.
+const ORPHAN_POSITION: u32 = u32::MAX;
+
+fn put_sub_chunk(body: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    body.extend_from_slice(id);
+    body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    body.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        body.push(0);
+    }
+}
+
+/// Writes `s` as a fixed size, zero padded field.
+fn put_string(body: &mut Vec<u8>, s: &str, size: usize) {
+    let bytes = s.as_bytes();
+    let len = bytes.len().min(size);
+    body.extend_from_slice(&bytes[..len]);
+    body.resize(body.len() + size - len, 0);
+}
+
+/// Cursor over a chunk's body that yields zeros once exhausted, so that malformed chunks don't
+/// prevent the audio from being read.
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, n: usize) -> &'a [u8] {
+        let (taken, rest) = self.0.split_at(n.min(self.0.len()));
+        self.0 = rest;
+        taken
+    }
+
+    fn skip(&mut self, n: usize) {
+        self.take(n);
+    }
+
+    fn u32(&mut self) -> u32 {
+        let mut buf = [0; 4];
+        let bytes = self.take(4);
+        buf[..bytes.len()].copy_from_slice(bytes);
+        u32::from_le_bytes(buf)
+    }
+
+    /// Reads a fixed size text field, stopping at the first zero byte.
+    fn string(&mut self, size: usize) -> String {
+        let bytes = self.take(size);
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).into_owned()
+    }
+}
@@ -0,0 +1,84 @@
+//! Blending an effect's output with its input.
+
+use std::collections::VecDeque;
+
+use crate::automation::Automation;
+use crate::effect::Stage;
+use crate::processor::Processor;
+use crate::types::{AudioBuffer, AudioMetadata};
+
+/// Processor whose output is mixed with its input, delayed by its latency so that both line up.
+pub struct Mix<P> {
+    wet: P,
+    /// From only the input at 0 to only the output at 1.
+    ratio: Automation,
+    /// Input that hasn't been mixed yet.
+    dry: VecDeque<f32>,
+    channels: usize,
+    sample_rate: f64,
+    /// Frames processed so far.
+    position: usize,
+}
+
+impl<P: Processor> Mix<P> {
+    pub fn new(wet: P, ratio: Automation, metadata: &AudioMetadata) -> Self {
+        let channels = usize::from(metadata.channels);
+        Self {
+            dry: VecDeque::from(vec![0.; wet.latency() * channels]),
+            wet,
+            ratio,
+            channels,
+            sample_rate: f64::from(metadata.sample_rate),
+            position: 0,
+        }
+    }
+}
+
+impl<P: Processor> Processor for Mix<P> {
+    fn process(&mut self, block: &mut [f32]) {
+        self.dry.extend(block.iter());
+        self.wet.process(block);
+        for frame in block.chunks_exact_mut(self.channels) {
+            let ratio = self.ratio.at(self.position as f64 / self.sample_rate) as f32;
+            for s in frame {
+                let dry = self.dry.pop_front().unwrap_or(0.);
+                *s = dry + (*s - dry) * ratio;
+            }
+            self.position += 1;
+        }
+    }
+
+    fn lookahead(&self) -> usize {
+        self.wet.lookahead()
+    }
+
+    fn latency(&self) -> usize {
+        self.wet.latency()
+    }
+}
+
+/// Mixes the output of `stage` with its input, `ratio` going from only the input at 0 to only
+/// the output at 1.
+pub fn mix(stage: Stage, ratio: Automation, metadata: &AudioMetadata) -> Stage {
+    match stage {
+        Stage::Stream(processor) => Stage::stream(Mix::new(processor, ratio, metadata)),
+        Stage::Whole(mut f) => Stage::whole(move |audio: AudioBuffer| {
+            let dry = audio.data.clone();
+            let mut wet = f(audio);
+            let channels = usize::from(wet.metadata.channels);
+            let sample_rate = f64::from(wet.metadata.sample_rate);
+            // when the effect changes the length, whichever is shorter is mixed as silence
+            if wet.data.len() < dry.len() {
+                wet.data.resize(dry.len(), 0.);
+            }
+            for (i, frame) in wet.data.chunks_exact_mut(channels).enumerate() {
+                let ratio = ratio.at(i as f64 / sample_rate) as f32;
+                for (channel, s) in frame.iter_mut().enumerate() {
+                    let dry = dry.get(i * channels + channel).copied().unwrap_or(0.);
+                    *s = dry + (*s - dry) * ratio;
+                }
+            }
+            wet
+        }),
+    }
+}
@@ -1,6 +1,78 @@
 use std::f32::consts::PI;
 
-use crate::types::{AudioBuffer, Complex};
+use crate::automation::Automated;
+use crate::effect::{Args, Effect, Param, Stage};
+use crate::processor::Processor;
+use crate::types::{AudioBuffer, AudioMetadata, Complex};
+
+/// Feedback delay whose feedback is rotated in the complex plane by an LFO.
+pub struct DelayRotate {
+    feedback: f32,
+    /// Phase the LFO moves by every frame.
+    lfo_step: f32,
+    sample_rate: f32,
+    /// One delay line per channel.
+    delay_lines: Vec<Vec<Complex>>,
+    index: usize,
+    /// Phase of the LFO, which moves by steps so that its frequency can change.
+    phase: f32,
+}
+
+impl DelayRotate {
+    pub fn new(
+        delay: usize,
+        feedback: f32,
+        frequency: f32,
+        channels: u16,
+        sample_rate: u32,
+    ) -> Self {
+        let mut delay_rotate = Self {
+            feedback,
+            lfo_step: 0.,
+            sample_rate: sample_rate as f32,
+            delay_lines: vec![vec![Complex::zero(); delay]; usize::from(channels)],
+            index: 0,
+            phase: 0.,
+        };
+        delay_rotate.set_frequency(frequency);
+        delay_rotate
+    }
+
+    /// Changes the frequency of the LFO, in Hz.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.lfo_step = 2. * PI * frequency / self.sample_rate;
+    }
+}
+
+impl Processor for DelayRotate {
+    fn process(&mut self, block: &mut [f32]) {
+        let channels = self.delay_lines.len();
+        let feedback = self.feedback;
+        for frame in block.chunks_exact_mut(channels) {
+            for (channel, s) in frame.iter_mut().enumerate() {
+                // TODO only compute cos/sin every once in a while to save compute, maybe have a
+                //      quality parameter
+                let lfo = 1. + (self.phase + PI * channel as f32).cos();
+                let delay_line = &mut self.delay_lines[channel];
+                let out_complex =
+                    feedback * Complex::new(lfo.cos(), lfo.sin()) * delay_line[self.index]
+                        + (1. - feedback) * *s;
+                delay_line[self.index] = out_complex;
+                *s = out_complex.r;
+            }
+            self.index += 1;
+            if self.index >= self.delay_lines[0].len() {
+                self.index = 0;
+            }
+            self.phase = (self.phase + self.lfo_step) % (2. * PI);
+        }
+    }
+
+    fn latency(&self) -> usize {
+        // the input goes straight through, only its echoes come out later
+        0
+    }
+}
 
 pub fn delay_rotate(
     mut audio: AudioBuffer,
@@ -8,30 +80,50 @@ pub fn delay_rotate(
     feedback: f32,
     frequency: f32,
 ) -> AudioBuffer {
-    let mut delay_buffer = Vec::with_capacity(delay);
-    delay_buffer.resize(delay, Complex::zero());
-    let lfo_step = 2. * PI * frequency / audio.metadata.sample_rate as f32;
-
-    let channels = audio.metadata.channels as usize;
-    let samples_per_channel = audio.data.len() / channels;
-    for channel in 0..channels {
-        let mut t = 0;
-        let mut i = 0;
-        for sample in 0..samples_per_channel {
-            // TODO only compute cos/sin every once in a while to save compute, maybe have a
-            //      quality parameter
-            let lfo = 1. + (t as f32 * lfo_step + PI * channel as f32).cos();
-            t += 1;
-            let out_complex = feedback * Complex::new(lfo.cos(), lfo.sin()) * delay_buffer[i]
-                + (1. - feedback) * audio.data[channel + channels * sample];
-            delay_buffer[i] = out_complex;
-            i += 1;
-            if i >= delay {
-                i = 0;
-            }
+    let md = &audio.metadata;
+    DelayRotate::new(delay, feedback, frequency, md.channels, md.sample_rate)
+        .process(&mut audio.data);
+    audio
+}
 
-            audio.data[channel + channels * sample] = out_complex.r;
-        }
+pub struct DelayRotateEffect;
+
+impl Effect for DelayRotateEffect {
+    fn name(&self) -> &'static str {
+        "delayrotate"
+    }
+
+    fn description(&self) -> &'static str {
+        "Feedback delay whose feedback is rotated in the complex plane by an LFO"
+    }
+
+    fn params(&self) -> &'static [Param] {
+        static PARAMS: [Param; 3] = [
+            Param::int("delay", 1000).at_least(1.).unit("samples"),
+            Param::float("feedback", 0.5)
+                .at_least(-1.)
+                .at_most(1.)
+                .automatable(),
+            Param::float("frequency", 1.)
+                .at_least(0.)
+                .unit("Hz")
+                .automatable(),
+        ];
+        &PARAMS
+    }
+
+    fn build(&self, args: &Args, metadata: &AudioMetadata) -> Stage {
+        let delay_rotate = DelayRotate::new(
+            args.int("delay") as usize,
+            0.,
+            0.,
+            metadata.channels,
+            metadata.sample_rate,
+        );
+        Stage::stream(
+            Automated::new(delay_rotate, metadata)
+                .control(args.automation("feedback"), |d, x| d.feedback = x)
+                .control(args.automation("frequency"), DelayRotate::set_frequency),
+        )
     }
-    audio
 }
@@ -1,92 +1,217 @@
-use crate::types::AudioBuffer;
+use crate::automation::Automation;
+use crate::effect::{Args, Effect, Param, Stage};
+use crate::processor::Processor;
+use crate::resample::{stretch, Interpolation, Interpolator};
+use crate::types::{AudioBuffer, AudioMetadata};
 
-pub fn delay_pitch(mut audio: AudioBuffer, factor: f32, log_size: u8) -> AudioBuffer {
-    if factor == 1. {
-        return audio;
+/// Pitch shifter reading a circular buffer at `factor` times the speed it is written at.
+pub struct DelayPitch {
+    factor: f32,
+    /// What the factor follows along the input and the input's sample rate, when it changes.
+    automation: Option<(Automation, f64)>,
+    /// One circular buffer per channel, whose size is a power of two.
+    buffers: Vec<Vec<f32>>,
+    interpolator: Interpolator,
+    read: f64,
+    write: usize,
+    primed: bool,
+}
+
+impl DelayPitch {
+    /// Uses buffers of `2^log_size` samples shared out between the channels, or less when the
+    /// input turns out to be shorter.
+    pub fn new(factor: f32, log_size: u8, channels: u16) -> Self {
+        Self::with_buffer_size(factor, 2_usize.pow(u32::from(log_size)), channels)
     }
 
-    let channels = audio.metadata.channels as usize;
-    let samples_per_channel = audio.data.len() / channels;
+    fn with_buffer_size(factor: f32, size: usize, channels: u16) -> Self {
+        let channels = usize::from(channels);
+        // the masking needs a power of two, which is rounded down to fit in the size
+        let share = (size / channels).max(1);
+        let share = if share.is_power_of_two() {
+            share
+        } else {
+            share.next_power_of_two() / 2
+        };
+        Self {
+            factor,
+            automation: None,
+            buffers: vec![vec![0.; share]; channels],
+            interpolator: Interpolator::new(Interpolation::Linear),
+            read: 0.,
+            write: 0,
+            primed: false,
+        }
+    }
 
-    let data_len = audio.data.len();
-    let data_len_bounded_size = if data_len.is_power_of_two() {
-        data_len
-    } else {
-        1 + (usize::MAX >> data_len.leading_zeros() + 1)
-    };
-    let buffer_size = std::cmp::min(2usize.pow(log_size as u32), data_len_bounded_size) / channels;
-    let buffer_mask = buffer_size - 1;
-    let mut buffer = vec![0.; buffer_size];
+    /// Has the factor follow `factor` along an input at `sample_rate`, the buffers being primed
+    /// or not depending on where it starts.
+    pub fn automate(mut self, factor: Automation, sample_rate: u32) -> Self {
+        self.factor = factor.at(0.) as f32;
+        self.automation = match factor {
+            Automation::Constant(_) => None,
+            factor => Some((factor, f64::from(sample_rate))),
+        };
+        self
+    }
 
-    for channel in 0..channels {
-        if factor > 1. {
+    /// Reads the buffers with `interpolation` instead of linearly.
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolator = Interpolator::new(interpolation);
+        self
+    }
+}
+
+impl Processor for DelayPitch {
+    fn process(&mut self, block: &mut [f32]) {
+        if self.factor == 1. && self.automation.is_none() {
+            return;
+        }
+
+        let channels = self.buffers.len();
+        if !self.primed && self.factor > 1. {
+            // a first block shorter than the lookahead is the whole input, there's no point in a
+            // buffer longer than it, whose end would be read before it is written
+            let length = (block.len() / channels).next_power_of_two();
+            for buffer in &mut self.buffers {
+                buffer.truncate(length);
+            }
             // initialize the buffer to avoid initial silence
-            for i in 0..buffer.len() {
-                buffer[i] = audio.data[channel + i * channels];
+            let size = self.buffers[0].len();
+            for (i, frame) in block.chunks_exact(channels).take(size).enumerate() {
+                for (buffer, &s) in self.buffers.iter_mut().zip(frame) {
+                    buffer[i] = s;
+                }
             }
         }
+        self.primed = true;
 
-        let mut read: f32 = 0.;
-        let mut write = 0;
-        for i in 0..samples_per_channel {
-            buffer[write & buffer_mask] = audio.data[channel + i * channels];
-            write += 1;
+        let buffer_size = self.buffers[0].len();
+        let buffer_mask = buffer_size - 1;
 
-            let first = read.floor();
-            let second = read.ceil();
-            let ratio = read - first;
-            audio.data[channel + i * channels] = buffer[first as usize] * ratio
-                + buffer[second as usize & buffer_mask] * (1. - ratio);
-
-            read += factor;
+        for frame in block.chunks_exact_mut(channels) {
+            if let Some((factor, sample_rate)) = &self.automation {
+                self.factor = factor.at(self.write as f64 / sample_rate) as f32;
+            }
+            let cutoff = (1. / f64::from(self.factor)).min(1.);
+            for (buffer, s) in self.buffers.iter_mut().zip(frame) {
+                buffer[self.write & buffer_mask] = *s;
+                // negative indices wrap around too, the size being a power of two
+                let sample = |i: isize| buffer[i as usize & buffer_mask];
+                *s = self.interpolator.at(sample, self.read, cutoff);
+            }
+            self.write += 1;
 
-            if read >= buffer_size as f32 {
-                read -= buffer_size as f32;
+            self.read += f64::from(self.factor);
+            if self.read >= buffer_size as f64 {
+                self.read -= buffer_size as f64;
             }
         }
     }
 
-    audio
+    fn lookahead(&self) -> usize {
+        // the buffers are primed with the start of the first block
+        if self.factor > 1. && !self.primed {
+            self.buffers[0].len()
+        } else {
+            0
+        }
+    }
+
+    fn latency(&self) -> usize {
+        // the delay sweeps through the buffer instead of being fixed, there's nothing to line up
+        0
+    }
 }
 
-pub fn speed(mut audio: AudioBuffer, speed: f32) -> AudioBuffer {
-    if speed == 1. {
+pub fn delay_pitch(mut audio: AudioBuffer, factor: f32, log_size: u8) -> AudioBuffer {
+    if audio.data.is_empty() {
         return audio;
     }
-
-    let chs = audio.metadata.channels as usize;
-    let new_len = (audio.data.len() as f32 / speed) as usize;
-    let samples_per_channel = new_len / chs;
-
-    if speed > 1. {
-        for ch in 0..chs {
-            let mut read: f32 = 0.;
-            for i in 0..samples_per_channel {
-                let first = read.floor();
-                let second = read.ceil();
-                let ratio = read - first;
-                audio.data[ch + i * chs] = audio.data[ch + first as usize * chs] * ratio
-                    + audio.data[ch + second as usize * chs] * (1. - ratio);
-                read += speed;
-            }
-        }
-        audio.data.truncate(new_len);
-        audio.data.shrink_to_fit();
+    // no point in a buffer longer than the audio
+    let data_len = audio.data.len();
+    let data_len_bounded_size = if data_len.is_power_of_two() {
+        data_len
     } else {
-        let mut buffer = vec![1.; new_len];
-        for ch in 0..chs {
-            let mut read: f32 = 0.;
-            for i in 0..samples_per_channel {
-                let first = read.floor();
-                let second = read.ceil();
-                let ratio = read - first;
-                buffer[ch + i * chs] = audio.data[ch + first as usize * chs] * ratio
-                    + audio.data[ch + second as usize * chs] * (1. - ratio);
-                read += speed;
-            }
-        }
-        audio.data = buffer;
+        1 + (usize::MAX >> (data_len.leading_zeros() + 1))
+    };
+    let size = std::cmp::min(2usize.pow(log_size as u32), data_len_bounded_size);
+    DelayPitch::with_buffer_size(factor, size, audio.metadata.channels).process(&mut audio.data);
+    audio
+}
+
+/// Plays `audio` `speed` times faster, reading between its samples linearly.
+pub fn speed(audio: AudioBuffer, speed: f32) -> AudioBuffer {
+    stretch(
+        audio,
+        &Automation::Constant(f64::from(speed)),
+        Interpolation::Linear,
+    )
+}
+
+/// Like `speed`, with a speed that follows `speed` along the input.
+pub fn varispeed(audio: AudioBuffer, speed: &Automation) -> AudioBuffer {
+    stretch(audio, speed, Interpolation::Linear)
+}
+
+pub struct DelayPitchEffect;
+
+impl Effect for DelayPitchEffect {
+    fn name(&self) -> &'static str {
+        "delaypitch"
     }
 
-    audio
+    fn description(&self) -> &'static str {
+        "Shifts the pitch by factor through a circular delay of 2^log_size samples"
+    }
+
+    fn params(&self) -> &'static [Param] {
+        static PARAMS: [Param; 3] = [
+            Param::float("factor", 2.).at_least(0.).automatable(),
+            Param::int("log_size", 12).at_least(1.).at_most(30.),
+            Param::choice("quality", Interpolation::CHOICES, "linear").optional(),
+        ];
+        &PARAMS
+    }
+
+    fn build(&self, args: &Args, metadata: &AudioMetadata) -> Stage {
+        Stage::stream(
+            DelayPitch::new(1., args.int("log_size") as u8, metadata.channels)
+                .automate(args.automation("factor"), metadata.sample_rate)
+                .interpolation(Interpolation::from_choice(args.choice("quality"))),
+        )
+    }
+}
+
+pub struct SpeedEffect;
+
+impl Effect for SpeedEffect {
+    fn name(&self) -> &'static str {
+        "speed"
+    }
+
+    fn description(&self) -> &'static str {
+        "Changes speed and pitch together by resampling"
+    }
+
+    fn params(&self) -> &'static [Param] {
+        static PARAMS: [Param; 2] = [
+            Param::float("speed", 1.)
+                .at_least(0.01)
+                .at_most(100.)
+                .automatable(),
+            Param::choice("quality", Interpolation::CHOICES, "linear").optional(),
+        ];
+        &PARAMS
+    }
+
+    fn build(&self, args: &Args, _: &AudioMetadata) -> Stage {
+        let automation = args.automation("speed");
+        let interpolation = Interpolation::from_choice(args.choice("quality"));
+        Stage::whole(move |audio| stretch(audio, &automation, interpolation))
+    }
+
+    fn changes_length(&self, _: &Args) -> bool {
+        true
+    }
 }
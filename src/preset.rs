@@ -0,0 +1,236 @@
+//! Effect chains saved to files, in a small subset of TOML.
+//!
+//! Each effect is an `[[effect]]` table holding its `name`, an optional number of `iterations`,
+//! an optional dry/wet `mix` and its parameters by name, which take their default value when
+//! left out. Automated parameters are strings, written like on the command line:
+//!
+//! ```toml
+//! [[effect]]
+//! name = "softclip"
+//! iterations = 3
+//! mix = 0.5
+//! amount = "lfo:sine:0.5:0.2:0.8"
+//!
+//! [[effect]]
+//! name = "fractalize"
+//! iterations = 2
+//! depth = 3
+//! ```
+
+use std::fmt;
+
+use crate::effect::{find, Args, Invocation, Param, ParamError, ParamKind, Value, MIX};
+
+#[derive(Debug)]
+pub struct PresetError {
+    /// Line of the problem, starting at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for PresetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+fn error<T, M: fmt::Display>(line: usize, message: M) -> Result<T, PresetError> {
+    Err(PresetError {
+        line,
+        message: message.to_string(),
+    })
+}
+
+/// `key = value` line of a table.
+struct Entry<'a> {
+    line: usize,
+    key: &'a str,
+    value: &'a str,
+}
+
+/// Parses a preset into the chain it describes.
+pub fn read_preset(text: &str) -> Result<Vec<Invocation>, PresetError> {
+    // line of each table's header, and its entries
+    let mut tables: Vec<(usize, Vec<Entry>)> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') {
+            if line != "[[effect]]" {
+                return error(line_number, format!("unknown table {}", line));
+            }
+            tables.push((line_number, Vec::new()));
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => return error(line_number, "expected key = value"),
+        };
+        match tables.last_mut() {
+            Some((_, entries)) => {
+                if entries.iter().any(|entry| entry.key == key) {
+                    return error(line_number, format!("{} is given twice", key));
+                }
+                entries.push(Entry {
+                    line: line_number,
+                    key,
+                    value,
+                });
+            }
+            None => return error(line_number, "keys have to be in an [[effect]] table"),
+        }
+    }
+
+    tables
+        .iter()
+        .map(|(line, entries)| read_invocation(*line, entries))
+        .collect()
+}
+
+fn read_invocation(line: usize, entries: &[Entry]) -> Result<Invocation, PresetError> {
+    let name = match entries.iter().find(|entry| entry.key == "name") {
+        Some(entry) => match parse_string(entry.value) {
+            Some(name) => name,
+            None => return error(entry.line, "name has to be a string"),
+        },
+        None => return error(line, "effect without a name"),
+    };
+    let effect = match find(&name) {
+        Some(effect) if !name.is_empty() => effect,
+        _ => return error(line, format!("unknown effect {}", name)),
+    };
+
+    let mut iterations = 1;
+    let mut args = Args::defaults(effect.params());
+    let mut mix = None;
+    for entry in entries {
+        match entry.key {
+            "name" => {}
+            "iterations" => match entry.value.parse() {
+                Ok(i) => iterations = i,
+                Err(e) => return error(entry.line, format!("iterations: {}", e)),
+            },
+            "mix" => mix = Some(read_value(&MIX, entry)?),
+            key => {
+                let i = match effect.params().iter().position(|param| param.name == key) {
+                    Some(i) => i,
+                    None => {
+                        return error(
+                            entry.line,
+                            format!("{} has no parameter {}", effect.name(), key),
+                        )
+                    }
+                };
+                let value = read_value(&effect.params()[i], entry)?;
+                if let Err(e) = args.set(i, value) {
+                    return error(entry.line, param_error(key, e));
+                }
+            }
+        }
+    }
+    Ok(Invocation {
+        effect,
+        iterations,
+        args,
+        mix,
+    })
+}
+
+fn read_value(param: &'static Param, entry: &Entry) -> Result<Value, PresetError> {
+    let key = entry.key;
+    let value = match (param.kind, parse_string(entry.value)) {
+        (ParamKind::Enum(_), Some(s)) => param.parse(&s),
+        // automations are written the way they are on the command line
+        (ParamKind::Float, Some(s)) if param.automatable => match param.parse(&s) {
+            Ok(Value::Float(_)) => return error(entry.line, format!("{} has to be a number", key)),
+            result => result,
+        },
+        (ParamKind::Enum(_), None) => {
+            return error(entry.line, format!("{} has to be a string", key))
+        }
+        (_, Some(_)) => return error(entry.line, format!("{} has to be a number", key)),
+        (_, None) => param.parse(entry.value),
+    };
+    value.or_else(|e| error(entry.line, param_error(key, e)))
+}
+
+fn param_error(key: &str, e: ParamError) -> String {
+    match e {
+        ParamError::ParseInt(e) => format!("{}: {}", key, e),
+        ParamError::ParseFloat(e) => format!("{}: {}", key, e),
+        e => e.to_string(),
+    }
+}
+
+/// Removes a `#` comment, unless it is in a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Parses a basic string, where only `\"` and `\\` are escaped.
+fn parse_string(value: &str) -> Option<String> {
+    let inner = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut s = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                c @ ('"' | '\\') => s.push(c),
+                _ => return None,
+            },
+            '"' => return None,
+            c => s.push(c),
+        }
+    }
+    Some(s)
+}
+
+/// Writes `chain` so that `read_preset` gives it back.
+pub fn write_preset(chain: &[Invocation]) -> String {
+    let tables: Vec<String> = chain
+        .iter()
+        .map(|invocation| {
+            let mut table = format!("[[effect]]\nname = \"{}\"\n", invocation.effect.name());
+            if invocation.iterations != 1 {
+                table += &format!("iterations = {}\n", invocation.iterations);
+            }
+            if let Some(mix) = &invocation.mix {
+                table += &write_value(&MIX, mix);
+            }
+            for (param, value) in invocation
+                .args
+                .params()
+                .iter()
+                .zip(invocation.args.values())
+            {
+                table += &write_value(param, value);
+            }
+            table
+        })
+        .collect();
+    tables.join("\n")
+}
+
+fn write_value(param: &Param, value: &Value) -> String {
+    match value {
+        // with a decimal point, so that it stays a float in TOML
+        Value::Float(x) => format!("{} = {:?}\n", param.name, x),
+        Value::Int(i) => format!("{} = {}\n", param.name, i),
+        Value::Enum(s) => format!("{} = \"{}\"\n", param.name, s),
+        Value::Automation(a) => format!("{} = \"{}\"\n", param.name, a),
+    }
+}
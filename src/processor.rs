@@ -0,0 +1,54 @@
+//! Effects that work on a stream of blocks instead of a whole `AudioBuffer`, so that files don't
+//! have to fit in memory.
+
+/// Effect applied to interleaved audio one block at a time.
+///
+/// Blocks always hold whole frames, their size may change from one call to the next. Whatever
+/// the effect needs to remember, such as a delay line, is kept between calls, so processing a
+/// file in several blocks gives the same result as processing it in one go, as long as the first
+/// block holds at least `lookahead` frames.
+pub trait Processor {
+    fn process(&mut self, block: &mut [f32]);
+
+    /// Frames that the first block has to hold for the result not to depend on the block size,
+    /// for effects that peek ahead at the start of the stream.
+    fn lookahead(&self) -> usize {
+        0
+    }
+
+    /// Frames by which the output lags behind the input.
+    fn latency(&self) -> usize {
+        0
+    }
+}
+
+impl<P: Processor + ?Sized> Processor for Box<P> {
+    fn process(&mut self, block: &mut [f32]) {
+        (**self).process(block)
+    }
+
+    fn lookahead(&self) -> usize {
+        (**self).lookahead()
+    }
+
+    fn latency(&self) -> usize {
+        (**self).latency()
+    }
+}
+
+/// Processors applied one after the other.
+impl<P: Processor> Processor for Vec<P> {
+    fn process(&mut self, block: &mut [f32]) {
+        for processor in self {
+            processor.process(block)
+        }
+    }
+
+    fn lookahead(&self) -> usize {
+        self.iter().map(P::lookahead).max().unwrap_or(0)
+    }
+
+    fn latency(&self) -> usize {
+        self.iter().map(P::latency).sum()
+    }
+}
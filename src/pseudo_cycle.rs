@@ -1,4 +1,6 @@
-use crate::types::AudioBuffer;
+use crate::automation::Automation;
+use crate::effect::{Args, Effect, Param, Stage};
+use crate::types::{AudioBuffer, AudioMetadata};
 
 pub fn fractalize(buffer: &AudioBuffer, depth: u32) -> AudioBuffer {
     let mut new_data = vec![0.; buffer.data.len()];
@@ -145,8 +147,8 @@ pub fn reverse_pseudo_cycles(mut audio: AudioBuffer) -> AudioBuffer {
             let buffer_len = cycle_end - cycle_beg;
             buffer.resize(buffer_len, 0.);
 
-            for i in 0..buffer_len {
-                buffer[i] = audio.data[ch + (cycle_beg + i) * chs];
+            for (i, b) in buffer.iter_mut().enumerate() {
+                *b = audio.data[ch + (cycle_beg + i) * chs];
             }
 
             for i in 0..buffer_len {
@@ -159,9 +161,15 @@ pub fn reverse_pseudo_cycles(mut audio: AudioBuffer) -> AudioBuffer {
     audio
 }
 
-pub fn tense_pseudo_cycles(mut audio: AudioBuffer, tension: f32) -> AudioBuffer {
+pub fn tense_pseudo_cycles(audio: AudioBuffer, tension: f32) -> AudioBuffer {
+    vary_tense_pseudo_cycles(audio, &Automation::Constant(f64::from(tension)))
+}
+
+/// Like `tense_pseudo_cycles`, with a tension that follows `tension` along the input.
+pub fn vary_tense_pseudo_cycles(mut audio: AudioBuffer, tension: &Automation) -> AudioBuffer {
     let chs = audio.metadata.channels as usize;
     let spc = audio.data.len() / chs;
+    let sample_rate = f64::from(audio.metadata.sample_rate);
 
     for ch in 0..chs {
         let mut cycle_end = 0;
@@ -195,6 +203,7 @@ pub fn tense_pseudo_cycles(mut audio: AudioBuffer, tension: f32) -> AudioBuffer
 
             for i in cycle_beg..cycle_end {
                 let sample = audio.data[ch + chs * i];
+                let tension = tension.at(i as f64 / sample_rate) as f32;
                 audio.data[ch + chs * i] =
                     sample.signum() * max * (1. - (1. - sample.abs() / max).powf(tension));
             }
@@ -204,3 +213,99 @@ pub fn tense_pseudo_cycles(mut audio: AudioBuffer, tension: f32) -> AudioBuffer
     }
     audio
 }
+
+pub struct InterpolateEffect;
+
+impl Effect for InterpolateEffect {
+    fn name(&self) -> &'static str {
+        "interpolate"
+    }
+
+    fn description(&self) -> &'static str {
+        "Averages each pseudo-cycle with the previous one stretched to its length"
+    }
+
+    fn build(&self, _: &Args, _: &AudioMetadata) -> Stage {
+        Stage::whole(|audio| interpolate(&audio))
+    }
+}
+
+pub struct FractalizeEffect;
+
+impl Effect for FractalizeEffect {
+    fn name(&self) -> &'static str {
+        "fractalize"
+    }
+
+    fn description(&self) -> &'static str {
+        "Adds copies of each pseudo-cycle squeezed 2 to depth times"
+    }
+
+    fn params(&self) -> &'static [Param] {
+        static PARAMS: [Param; 1] = [Param::int("depth", 2).at_least(1.).at_most(u32::MAX as f64)];
+        &PARAMS
+    }
+
+    fn build(&self, args: &Args, _: &AudioMetadata) -> Stage {
+        let depth = args.int("depth") as u32;
+        Stage::whole(move |audio| fractalize(&audio, depth))
+    }
+}
+
+pub struct ExpandEffect;
+
+impl Effect for ExpandEffect {
+    fn name(&self) -> &'static str {
+        "expand"
+    }
+
+    fn description(&self) -> &'static str {
+        "Normalizes every pseudo-cycle on its own"
+    }
+
+    fn build(&self, _: &Args, _: &AudioMetadata) -> Stage {
+        Stage::whole(expand)
+    }
+
+    fn repeats(&self) -> bool {
+        false
+    }
+}
+
+pub struct ReversePseudoCyclesEffect;
+
+impl Effect for ReversePseudoCyclesEffect {
+    fn name(&self) -> &'static str {
+        "reversepseudocycles"
+    }
+
+    fn description(&self) -> &'static str {
+        "Reverses every pseudo-cycle in place"
+    }
+
+    fn build(&self, _: &Args, _: &AudioMetadata) -> Stage {
+        Stage::whole(reverse_pseudo_cycles)
+    }
+}
+
+pub struct TensePseudoCyclesEffect;
+
+impl Effect for TensePseudoCyclesEffect {
+    fn name(&self) -> &'static str {
+        "tensepseudocycles"
+    }
+
+    fn description(&self) -> &'static str {
+        "Applies tense to every pseudo-cycle relative to its own peak"
+    }
+
+    fn params(&self) -> &'static [Param] {
+        static PARAMS: [Param; 1] = [Param::float("tension", 0.5).at_least(0.).automatable()];
+        &PARAMS
+    }
+
+    fn build(&self, args: &Args, _: &AudioMetadata) -> Stage {
+        let tension = args.automation("tension");
+        Stage::whole(move |audio| vary_tense_pseudo_cycles(audio, &tension))
+    }
+}
@@ -0,0 +1,172 @@
+//! Applying effects to part of a file only.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::effect::Stage;
+use crate::types::{AudioBuffer, AudioMetadata};
+
+/// Position or duration in a file, like `1.5s`, `20ms` or `44100` frames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Time {
+    Frames(usize),
+    Seconds(f64),
+}
+
+impl Time {
+    pub fn frames(self, sample_rate: u32) -> usize {
+        match self {
+            Time::Frames(frames) => frames,
+            Time::Seconds(seconds) => (seconds * f64::from(sample_rate)).round() as usize,
+        }
+    }
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Time::Frames(frames) => write!(f, "{}", frames),
+            Time::Seconds(seconds) => write!(f, "{}s", seconds),
+        }
+    }
+}
+
+impl FromStr for Time {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let seconds = if let Some(ms) = s.strip_suffix("ms") {
+            ms.parse::<f64>().map(|ms| ms / 1000.)
+        } else if let Some(seconds) = s.strip_suffix('s') {
+            seconds.parse::<f64>()
+        } else {
+            return s
+                .parse()
+                .map(Time::Frames)
+                .map_err(|e| format!("time {}: {}", s, e));
+        };
+        match seconds {
+            Ok(seconds) if seconds >= 0. => Ok(Time::Seconds(seconds)),
+            Ok(_) => Err(format!("time {} is negative", s)),
+            Err(e) => Err(format!("time {}: {}", s, e)),
+        }
+    }
+}
+
+/// Part of a file that effects are restricted to, parsed from `start..end` where either end can
+/// be left out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub start: Time,
+    /// The end of the file when `None`.
+    pub end: Option<Time>,
+    /// Length of the fades from the untouched audio to the processed one and back.
+    pub crossfade: Time,
+}
+
+impl Region {
+    pub const DEFAULT_CROSSFADE: Time = Time::Seconds(0.01);
+
+    /// Start and end frames in `metadata`'s sample rate, clamped to the `frames` of the file.
+    fn frames(&self, metadata: &AudioMetadata, frames: usize) -> (usize, usize) {
+        let start = self.start.frames(metadata.sample_rate).min(frames);
+        let end = self
+            .end
+            .map_or(frames, |end| end.frames(metadata.sample_rate));
+        (start, end.clamp(start, frames))
+    }
+
+    /// Applies `stage` to the region of `audio` only. The result, which can be longer or shorter
+    /// than the region, replaces it, crossfading with the original audio at both edges.
+    pub fn apply(&self, mut audio: AudioBuffer, stage: &mut Stage) -> AudioBuffer {
+        let channels = usize::from(audio.metadata.channels);
+        let (start, end) = self.frames(&audio.metadata, audio.data.len() / channels);
+        if start == end {
+            return audio;
+        }
+
+        let dry = audio.data[start * channels..end * channels].to_vec();
+        let mut wet = stage.apply(AudioBuffer {
+            metadata: AudioMetadata {
+                chunks: Default::default(),
+                ..audio.metadata.clone()
+            },
+            data: dry.clone(),
+        });
+
+        let dry_frames = end - start;
+        let wet_frames = wet.data.len() / channels;
+        let fade = self
+            .crossfade
+            .frames(audio.metadata.sample_rate)
+            .min(dry_frames / 2)
+            .min(wet_frames / 2);
+        // the dry audio is lined up with the start of the region at its start and with the end
+        // of the region at its end, so that it always joins the audio around it
+        for i in 0..fade {
+            let gain = (i as f32 + 0.5) / fade as f32;
+            for channel in 0..channels {
+                let head = i * channels + channel;
+                wet.data[head] = dry[head] * (1. - gain) + wet.data[head] * gain;
+                let wet_tail = (wet_frames - 1 - i) * channels + channel;
+                let dry_tail = (dry_frames - 1 - i) * channels + channel;
+                wet.data[wet_tail] = dry[dry_tail] * (1. - gain) + wet.data[wet_tail] * gain;
+            }
+        }
+
+        audio
+            .data
+            .splice(start * channels..end * channels, wet.data);
+        let (start, end, wet_end) = (start as f64, end as f64, (start + wet_frames) as f64);
+        audio.metadata.chunks.remap(|position| {
+            let position = f64::from(position);
+            let moved = if position < start {
+                position
+            } else if position < end {
+                start + (position - start) * (wet_end - start) / (end - start)
+            } else {
+                position + wet_end - end
+            };
+            moved.round() as u32
+        });
+        audio
+    }
+
+    /// Restricts `stage` to the region, which needs the whole file at once.
+    pub fn restrict(self, mut stage: Stage) -> Stage {
+        Stage::whole(move |audio| self.apply(audio, &mut stage))
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once("..")
+            .ok_or_else(|| format!("expected start..end, got {}", s))?;
+        let start = if start.is_empty() {
+            Time::Frames(0)
+        } else {
+            start.parse()?
+        };
+        let end = if end.is_empty() {
+            None
+        } else {
+            Some(end.parse()?)
+        };
+        let empty = match (start, end) {
+            (Time::Frames(start), Some(Time::Frames(end))) => end <= start,
+            (Time::Seconds(start), Some(Time::Seconds(end))) => end <= start,
+            _ => false,
+        };
+        if empty {
+            return Err(format!("the range {} is empty", s));
+        }
+        Ok(Region {
+            start,
+            end,
+            crossfade: Region::DEFAULT_CROSSFADE,
+        })
+    }
+}
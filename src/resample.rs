@@ -0,0 +1,214 @@
+//! Reading audio at fractional positions, to change its speed or sample rate.
+
+use crate::automation::Automation;
+use crate::effect::{Args, Effect, Param, Stage};
+use crate::types::{AudioBuffer, AudioMetadata};
+
+/// How samples are computed between those of the input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    /// Straight line between the two closest samples, which aliases.
+    Linear,
+    /// Cubic Hermite spline through the four closest samples, smoother but still aliasing.
+    Cubic,
+    /// Band-limited, through a windowed sinc.
+    Sinc,
+}
+
+impl Interpolation {
+    /// Names of the `quality` parameters choosing it.
+    pub const CHOICES: &'static [&'static str] = &["linear", "cubic", "sinc"];
+
+    pub fn from_choice(choice: &str) -> Self {
+        match choice {
+            "linear" => Interpolation::Linear,
+            "cubic" => Interpolation::Cubic,
+            "sinc" => Interpolation::Sinc,
+            _ => panic!("unknown interpolation {}", choice),
+        }
+    }
+}
+
+/// Zero crossings of the sinc on each side of its peak.
+const ZEROS: usize = 16;
+/// Entries of the sinc table between two zero crossings.
+const RESOLUTION: usize = 512;
+
+/// Blackman-windowed sinc, tabulated from its peak to its last zero crossing.
+struct Sinc(Vec<f32>);
+
+impl Sinc {
+    fn new() -> Self {
+        let table = (0..=ZEROS * RESOLUTION)
+            .map(|i| {
+                let x = i as f64 / RESOLUTION as f64;
+                let sinc = if i == 0 {
+                    1.
+                } else {
+                    (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+                };
+                // the window goes from 1 at the peak to 0 at the last zero crossing
+                let phase = std::f64::consts::PI * (1. + x / ZEROS as f64);
+                let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2. * phase).cos();
+                (sinc * window) as f32
+            })
+            .collect();
+        Sinc(table)
+    }
+
+    /// Value of the kernel `x` samples away from its peak.
+    fn at(&self, x: f64) -> f32 {
+        let x = x.abs() * RESOLUTION as f64;
+        let i = x as usize;
+        if i + 1 >= self.0.len() {
+            return 0.;
+        }
+        let ratio = (x - i as f64) as f32;
+        self.0[i] * (1. - ratio) + self.0[i + 1] * ratio
+    }
+}
+
+/// Reads samples at fractional positions with an `Interpolation`.
+pub struct Interpolator {
+    interpolation: Interpolation,
+    /// Only built for `Interpolation::Sinc`.
+    sinc: Option<Sinc>,
+}
+
+impl Interpolator {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Self {
+            interpolation,
+            sinc: (interpolation == Interpolation::Sinc).then(Sinc::new),
+        }
+    }
+
+    /// Value at `position` of the samples that `sample` gives by index, which it has to give for
+    /// any index, like by holding the edges or wrapping around. A sinc also filters out the
+    /// frequencies above `cutoff` times the Nyquist frequency, a cutoff of 1 keeping them all.
+    pub fn at<F: Fn(isize) -> f32>(&self, sample: F, position: f64, cutoff: f64) -> f32 {
+        let first = position.floor();
+        let i = first as isize;
+        let t = (position - first) as f32;
+        match (self.interpolation, &self.sinc) {
+            (Interpolation::Linear, _) => sample(i) * (1. - t) + sample(i + 1) * t,
+            (Interpolation::Cubic, _) => {
+                let (y0, y1, y2, y3) = (sample(i - 1), sample(i), sample(i + 1), sample(i + 2));
+                // Catmull-Rom, whose tangents come from the neighbouring samples
+                let a = -0.5 * y0 + 1.5 * y1 - 1.5 * y2 + 0.5 * y3;
+                let b = y0 - 2.5 * y1 + 2. * y2 - 0.5 * y3;
+                let c = -0.5 * y0 + 0.5 * y2;
+                ((a * t + b) * t + c) * t + y1
+            }
+            (Interpolation::Sinc, Some(sinc)) => {
+                let reach = ZEROS as f64 / cutoff;
+                let first = (position - reach).ceil() as isize;
+                let last = (position + reach).floor() as isize;
+                let mut sum = 0.;
+                for i in first..=last {
+                    sum += sample(i) * sinc.at((position - i as f64) * cutoff);
+                }
+                sum * cutoff as f32
+            }
+            (Interpolation::Sinc, None) => {
+                unreachable!("the sinc is built along with the interpolator")
+            }
+        }
+    }
+}
+
+/// Plays `audio` at `speed`, which follows the input's time, reading between its samples with
+/// `interpolation`. Markers are moved along with the audio.
+pub fn stretch(
+    audio: AudioBuffer,
+    speed: &Automation,
+    interpolation: Interpolation,
+) -> AudioBuffer {
+    let frames = audio.data.len() / usize::from(audio.metadata.channels);
+    let sample_rate = f64::from(audio.metadata.sample_rate);
+    let reads: Vec<(f64, f64)> = match *speed {
+        // the length of the audio is divided by the speed, and positions don't drift
+        Automation::Constant(step) => {
+            let length = (frames as f64 / step).round() as usize;
+            (0..length).map(|i| (i as f64 * step, step)).collect()
+        }
+        _ => {
+            let mut reads = Vec::new();
+            let mut read = 0.;
+            while frames > 0 && read <= (frames - 1) as f64 {
+                let step = speed.at(read / sample_rate);
+                reads.push((read, step));
+                read += step;
+            }
+            reads
+        }
+    };
+
+    let mut audio = audio;
+    let chs = usize::from(audio.metadata.channels);
+    let last = frames as isize - 1;
+    let interpolator = Interpolator::new(interpolation);
+    let mut data = Vec::with_capacity(reads.len() * chs);
+    for &(read, step) in &reads {
+        // speeding up moves frequencies up, those that would pass Nyquist are removed
+        let cutoff = (1. / step).min(1.);
+        for ch in 0..chs {
+            let sample = |i: isize| audio.data[i.clamp(0, last) as usize * chs + ch];
+            data.push(interpolator.at(sample, read, cutoff));
+        }
+    }
+    audio.data = data;
+
+    let chunks = &mut audio.metadata.chunks;
+    match *speed {
+        Automation::Constant(step) => chunks.rescale(1. / step),
+        _ => chunks.remap(|position| {
+            reads.partition_point(|&(read, _)| read < f64::from(position)) as u32
+        }),
+    }
+    audio
+}
+
+/// Converts `audio` to `sample_rate`, keeping its speed and pitch.
+pub fn resample(audio: AudioBuffer, sample_rate: u32) -> AudioBuffer {
+    let from = audio.metadata.sample_rate;
+    if from == sample_rate {
+        return audio;
+    }
+    let step = f64::from(from) / f64::from(sample_rate);
+    let mut audio = stretch(audio, &Automation::Constant(step), Interpolation::Sinc);
+    audio.metadata.sample_rate = sample_rate;
+    if let Some(sampler) = &mut audio.metadata.chunks.sampler {
+        sampler.sample_period = (1e9 / f64::from(sample_rate)).round() as u32;
+    }
+    audio
+}
+
+pub struct ResampleEffect;
+
+impl Effect for ResampleEffect {
+    fn name(&self) -> &'static str {
+        "resample"
+    }
+
+    fn description(&self) -> &'static str {
+        "Converts to another sample rate, keeping speed and pitch"
+    }
+
+    fn params(&self) -> &'static [Param] {
+        static PARAMS: [Param; 1] = [Param::int("rate", 44100)
+            .at_least(1.)
+            .at_most(1536000.)
+            .unit("Hz")];
+        &PARAMS
+    }
+
+    fn build(&self, args: &Args, _: &AudioMetadata) -> Stage {
+        let rate = args.int("rate") as u32;
+        Stage::whole(move |audio| resample(audio, rate))
+    }
+
+    fn sample_rate(&self, args: &Args, _: u32) -> u32 {
+        args.int("rate") as u32
+    }
+}
@@ -1,25 +1,131 @@
+use crate::metadata::ChunkMetadata;
+
 #[derive(Clone)]
 pub struct AudioMetadata {
     pub channels: u16,
     pub sample_rate: u32,
+    /// Number of meaningful bits in each sample, only set for WAVE_FORMAT_EXTENSIBLE files.
+    pub valid_bits_per_sample: Option<u16>,
+    /// Speaker layout of the channels, only set for WAVE_FORMAT_EXTENSIBLE files.
+    pub channel_mask: Option<u32>,
+    /// Encoding of the samples in the file the audio was read from.
+    pub sample_format: SampleFormat,
+    /// Markers, loops and tags read from the file, written back along with the audio.
+    pub chunks: ChunkMetadata,
 }
 
+#[derive(Clone, Copy)]
 pub enum WavFormat {
     PCM = 1,
     FLOAT = 3,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    Pcm8,
+    Pcm16,
+    Pcm24,
+    Pcm32,
+    Float32,
+    Float64,
+}
+
+impl SampleFormat {
+    pub fn from_wav(format: WavFormat, bit_depth: u16) -> Option<Self> {
+        match (format, bit_depth) {
+            (WavFormat::PCM, 8) => Some(Self::Pcm8),
+            (WavFormat::PCM, 16) => Some(Self::Pcm16),
+            (WavFormat::PCM, 24) => Some(Self::Pcm24),
+            (WavFormat::PCM, 32) => Some(Self::Pcm32),
+            (WavFormat::FLOAT, 32) => Some(Self::Float32),
+            (WavFormat::FLOAT, 64) => Some(Self::Float64),
+            _ => None,
+        }
+    }
+
+    pub fn wav_format(self) -> WavFormat {
+        if self.is_float() {
+            WavFormat::FLOAT
+        } else {
+            WavFormat::PCM
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, Self::Float32 | Self::Float64)
+    }
+
+    pub fn bit_depth(self) -> u16 {
+        match self {
+            Self::Pcm8 => 8,
+            Self::Pcm16 => 16,
+            Self::Pcm24 => 24,
+            Self::Pcm32 | Self::Float32 => 32,
+            Self::Float64 => 64,
+        }
+    }
+
+    pub fn bytes_per_sample(self) -> u16 {
+        self.bit_depth() / 8
+    }
+}
+
+impl std::str::FromStr for SampleFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pcm8" => Ok(Self::Pcm8),
+            "pcm16" => Ok(Self::Pcm16),
+            "pcm24" => Ok(Self::Pcm24),
+            "pcm32" => Ok(Self::Pcm32),
+            "float32" => Ok(Self::Float32),
+            "float64" => Ok(Self::Float64),
+            _ => Err(format!("unknown sample format {}", s)),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct AudioBuffer {
     pub metadata: AudioMetadata,
     pub data: Vec<f32>,
 }
 
+impl AudioMetadata {
+    pub fn fmt_chunk_size(&self) -> u32 {
+        if self.channel_mask.is_some() {
+            AudioBuffer::FMT_EXTENSIBLE_CHUNK_SIZE
+        } else {
+            AudioBuffer::FMT_CHUNK_SIZE
+        }
+    }
+
+    /// Size of a RIFF/WAVE file holding `samples` samples, minus the 8 bytes of the RIFF header.
+    pub fn file_size(&self, format: SampleFormat, samples: u64) -> u64 {
+        let data_size = u64::from(format.bytes_per_sample()) * samples;
+        // chunks are padded to an even size
+        let metadata_size: u64 = self
+            .chunks
+            .riff_chunks()
+            .iter()
+            .map(|(_, body)| 8 + body.len() as u64 + body.len() as u64 % 2)
+            .sum();
+        4 + 8 + u64::from(self.fmt_chunk_size()) + metadata_size + 8 + data_size + data_size % 2
+    }
+}
+
 impl AudioBuffer {
     pub const FMT_CHUNK_SIZE: u32 = 16;
+    pub const FMT_EXTENSIBLE_CHUNK_SIZE: u32 = 40;
+    pub const DS64_CHUNK_SIZE: u32 = 28;
+
+    pub fn file_size(&self, format: SampleFormat) -> u64 {
+        self.metadata.file_size(format, self.data.len() as u64)
+    }
 
-    pub fn file_size(&self) -> u32 {
-        4 + 20 + 8 + 4 * self.data.len() as u32
+    pub fn data_size(&self, format: SampleFormat) -> u64 {
+        u64::from(format.bytes_per_sample()) * self.data.len() as u64
     }
 }
 
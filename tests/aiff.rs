@@ -0,0 +1,186 @@
+mod common;
+
+use screech::io::*;
+use screech::metadata::*;
+use screech::types::*;
+
+fn stereo_audio(samples: &[f32]) -> AudioBuffer {
+    common::audio(2, 44100, samples.to_vec())
+}
+
+/// Hand written AIFF-C file with a 16-bit mono sound at 44100Hz.
+fn aifc_file(compression: &[u8; 4], sound: &[u8]) -> Vec<u8> {
+    let mut comm = Vec::new();
+    comm.extend_from_slice(&1_u16.to_be_bytes());
+    comm.extend_from_slice(&(sound.len() as u32 / 2).to_be_bytes());
+    comm.extend_from_slice(&16_u16.to_be_bytes());
+    comm.extend_from_slice(&[0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0]);
+    comm.extend_from_slice(compression);
+    comm.extend_from_slice(&[0, 0]);
+
+    let mut file = b"FORM\0\0\0\0AIFC".to_vec();
+    file.extend_from_slice(b"COMM");
+    file.extend_from_slice(&(comm.len() as u32).to_be_bytes());
+    file.extend_from_slice(&comm);
+    file.extend_from_slice(b"SSND");
+    file.extend_from_slice(&(8 + sound.len() as u32).to_be_bytes());
+    file.extend_from_slice(&[0; 8]);
+    file.extend_from_slice(sound);
+    let form_size = file.len() as u32 - 8;
+    file[4..8].copy_from_slice(&form_size.to_be_bytes());
+    file
+}
+
+#[test]
+fn write_formats() {
+    let samples = [-1., -0.5, 0., 0.25, 0.5, 0.75];
+    for &format in &[
+        SampleFormat::Pcm8,
+        SampleFormat::Pcm16,
+        SampleFormat::Pcm24,
+        SampleFormat::Pcm32,
+        SampleFormat::Float32,
+        SampleFormat::Float64,
+    ] {
+        let mut file = Vec::new();
+        write_aiff(&mut file, &stereo_audio(&samples), format).unwrap();
+        let form_type: &[u8] = if format.is_float() { b"AIFC" } else { b"AIFF" };
+        assert_eq!(&file[8..12], form_type);
+
+        let audio = read_audio(&mut &file[..]).unwrap();
+        assert_eq!(audio.metadata.channels, 2);
+        assert_eq!(audio.metadata.sample_rate, 44100);
+        assert_eq!(audio.metadata.sample_format, format);
+        assert_eq!(audio.data, samples);
+    }
+}
+
+#[test]
+fn byte_orders() {
+    let samples = [-1., 0.5];
+    let big: Vec<u8> = [i16::MIN, 16384]
+        .iter()
+        .flat_map(|s| s.to_be_bytes())
+        .collect();
+    let little: Vec<u8> = [i16::MIN, 16384]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect();
+    for (compression, sound) in &[(b"NONE", &big), (b"twos", &big), (b"sowt", &little)] {
+        let audio = read_aiff(&mut &aifc_file(compression, sound)[..]).unwrap();
+        assert_eq!(audio.metadata.sample_rate, 44100);
+        assert_eq!(audio.data, samples);
+    }
+}
+
+#[test]
+fn unsupported_compression() {
+    assert!(matches!(
+        read_aiff(&mut &aifc_file(b"ulaw", &[0; 4])[..]),
+        Err(WavError::UnsupportedCompression(id)) if &id == b"ulaw"
+    ));
+}
+
+#[test]
+fn no_channels_is_rejected() {
+    let audio = common::audio(0, 44100, Vec::new());
+    let error = write_aiff(&mut Vec::new(), &audio, SampleFormat::Pcm16).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn invalid_comm_is_rejected() {
+    let mut no_channels = aifc_file(b"NONE", &[0; 4]);
+    no_channels[20..22].copy_from_slice(&0_u16.to_be_bytes());
+    assert!(matches!(
+        read_aiff(&mut &no_channels[..]),
+        Err(WavError::InvalidHeader(_))
+    ));
+    for rate in [[0; 10], [0x7F, 0xFF, 0xC0, 0, 0, 0, 0, 0, 0, 0]] {
+        let mut file = aifc_file(b"NONE", &[0; 4]);
+        file[28..38].copy_from_slice(&rate);
+        assert!(matches!(
+            read_aiff(&mut &file[..]),
+            Err(WavError::InvalidHeader(_))
+        ));
+    }
+}
+
+#[test]
+fn loops_and_cues_at_the_limits() {
+    let mut audio = stereo_audio(&[0.; 4]);
+    audio.metadata.chunks = ChunkMetadata {
+        cues: (0..70000)
+            .map(|id| CuePoint {
+                id,
+                position: 0,
+                label: None,
+            })
+            .collect(),
+        sampler: Some(Sampler {
+            loops: vec![SampleLoop {
+                id: 0,
+                kind: 0,
+                start: 1,
+                end: u32::MAX,
+                fraction: 0,
+                play_count: 0,
+            }],
+            ..Sampler::default()
+        }),
+        ..Default::default()
+    };
+    let mut file = Vec::new();
+    write_aiff(&mut file, &audio, SampleFormat::Pcm16).unwrap();
+
+    let chunks = read_aiff(&mut &file[..]).unwrap().metadata.chunks;
+    assert_eq!(chunks.cues.len(), usize::from(u16::MAX) - 4);
+    let sampler = chunks.sampler.unwrap();
+    assert_eq!(sampler.loops[0].start, 1);
+}
+
+#[test]
+fn markers_and_loops() {
+    let mut audio = stereo_audio(&[0.; 200]);
+    audio.metadata.chunks = ChunkMetadata {
+        info: vec![(*b"INAM", String::from("name"))],
+        cues: vec![CuePoint {
+            id: 1,
+            position: 20,
+            label: Some(String::from("hit")),
+        }],
+        sampler: Some(Sampler {
+            loops: vec![SampleLoop {
+                id: 0,
+                kind: 0,
+                start: 10,
+                end: 89,
+                fraction: 0,
+                play_count: 0,
+            }],
+            ..Sampler::default()
+        }),
+        ..Default::default()
+    };
+    let mut file = Vec::new();
+    write_aiff(&mut file, &audio, SampleFormat::Pcm16).unwrap();
+
+    let chunks = read_aiff(&mut &file[..]).unwrap().metadata.chunks;
+    assert_eq!(chunks.info, audio.metadata.chunks.info);
+    assert_eq!(chunks.cues.len(), 1);
+    assert_eq!(chunks.cues[0].position, 20);
+    assert_eq!(chunks.cues[0].label.as_deref(), Some("hit"));
+    let sampler = chunks.sampler.unwrap();
+    assert_eq!((sampler.loops[0].start, sampler.loops[0].end), (10, 89));
+}
+
+#[test]
+fn container_from_path() {
+    assert!(matches!(Container::from_path("a.aif"), Container::Aiff));
+    assert!(matches!(Container::from_path("a.AIFC"), Container::Aiff));
+    assert!(matches!(Container::from_path("a.wav"), Container::Wav));
+    assert!(matches!(
+        Container::from_path("noextension"),
+        Container::Wav
+    ));
+}
@@ -0,0 +1,213 @@
+mod common;
+
+use common::metadata;
+
+use screech::automation::*;
+use screech::effect::*;
+use screech::gain::Gain;
+use screech::metadata::CuePoint;
+use screech::pitch::varispeed;
+use screech::processor::Processor;
+use screech::types::*;
+
+fn automation(s: &str) -> Automation {
+    s.parse().unwrap()
+}
+
+#[test]
+fn parses_and_writes_back() {
+    assert_eq!(automation("0.5"), Automation::Constant(0.5));
+    assert_eq!(
+        automation("0:0.1,2.5s:0.9"),
+        Automation::Breakpoints(vec![(0., 0.1), (2.5, 0.9)])
+    );
+    assert_eq!(
+        automation("lfo:triangle:2:-1:1"),
+        Automation::Lfo(Lfo {
+            shape: Shape::Triangle,
+            frequency: 2.,
+            low: -1.,
+            high: 1.,
+        })
+    );
+    for text in ["0:0.1,2.5:0.9", "lfo:sine:0.5:0.2:0.8", "3"] {
+        assert_eq!(automation(text).to_string(), text);
+    }
+
+    assert!("1:0,0:1".parse::<Automation>().is_err());
+    assert!("0:x".parse::<Automation>().is_err());
+    assert!("0".parse::<Automation>().is_ok());
+    assert!("lfo:wobble:1:0:1".parse::<Automation>().is_err());
+    assert!("lfo:sine:1:0".parse::<Automation>().is_err());
+    assert!("lfo:sine:-1:0:1".parse::<Automation>().is_err());
+    assert!("@/nonexistent.csv".parse::<Automation>().is_err());
+}
+
+#[test]
+fn reads_csv() {
+    let path = std::env::temp_dir().join(format!("screech_env_{}.csv", std::process::id()));
+    std::fs::write(&path, "# envelope\ntime,gain\n0,0\n\n1.5,1\n").unwrap();
+    assert_eq!(
+        automation(&format!("@{}", path.display())),
+        Automation::Breakpoints(vec![(0., 0.), (1.5, 1.)])
+    );
+    // only the first line can be a header
+    std::fs::write(&path, "0,0\ntime,gain\n").unwrap();
+    let err = format!("@{}", path.display())
+        .parse::<Automation>()
+        .unwrap_err();
+    assert!(
+        err.ends_with("line 2: time time: invalid float literal"),
+        "{}",
+        err
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn evaluates_over_time() {
+    let ramp = automation("1:0,3:1,3:2");
+    assert_eq!(ramp.at(0.), 0.);
+    assert_eq!(ramp.at(2.), 0.5);
+    // the last of two breakpoints at the same time wins
+    assert_eq!(ramp.at(3.), 2.);
+    assert_eq!(ramp.at(10.), 2.);
+    assert_eq!(ramp.bounds(), (0., 2.));
+
+    for (shape, quarter, half) in [
+        ("sine", 0.5, 1.),
+        ("triangle", 0.5, 1.),
+        ("saw", 0.25, 0.5),
+        ("square", 0., 1.),
+    ] {
+        let lfo = automation(&format!("lfo:{}:2:0:1", shape));
+        // a period lasts half a second
+        assert_eq!(lfo.at(0.), 0., "{}", shape);
+        assert!((lfo.at(0.125) - quarter).abs() < 1e-9, "{}", shape);
+        assert!((lfo.at(0.25) - half).abs() < 1e-9, "{}", shape);
+        assert!((lfo.at(5.125) - quarter).abs() < 1e-9, "{}", shape);
+    }
+}
+
+#[test]
+fn processors_follow_automations_per_frame() {
+    let metadata = metadata(2, 4);
+    let make =
+        || Automated::new(Gain(1.), &metadata).control(automation("0:0,2:1"), |g, x| g.0 = x);
+
+    let mut whole = vec![1.; 24];
+    make().process(&mut whole);
+    assert_eq!(whole[..6], [0., 0., 0.125, 0.125, 0.25, 0.25]);
+    assert_eq!(whole[16..], [1.; 8]);
+
+    let mut blocks = vec![1.; 24];
+    let mut gain = make();
+    for block in blocks.chunks_mut(6) {
+        gain.process(block);
+    }
+    assert_eq!(blocks, whole);
+
+    // constants are set once
+    let mut constant = vec![1.; 4];
+    Automated::new(Gain(1.), &metadata)
+        .control(Automation::Constant(0.5), |g, x| g.0 = x)
+        .process(&mut constant);
+    assert_eq!(constant, [0.5; 4]);
+}
+
+#[test]
+fn automated_params() {
+    let gain = &find("gain").unwrap().params()[0];
+    assert!(gain.automatable);
+    assert_eq!(gain.parse("0.5").unwrap(), Value::Float(0.5));
+    assert_eq!(
+        gain.parse("0:0,1:2").unwrap(),
+        Value::Automation(automation("0:0,1:2"))
+    );
+    assert!(matches!(gain.parse("x"), Err(ParamError::ParseFloat(_))));
+
+    // every value the automation takes has to be in range
+    let feedback = &find("delayrotate").unwrap().params()[1];
+    assert!(feedback.parse("lfo:sine:1:-0.5:0.9").is_ok());
+    let err = feedback.parse("lfo:sine:1:-0.5:1.5").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "feedback 1.5 is out of range, it goes from -1 to 1"
+    );
+
+    // every float can be automated, other parameters can't
+    for effect in EFFECTS {
+        for param in effect.params() {
+            assert_eq!(param.automatable, param.kind == ParamKind::Float);
+        }
+    }
+    let delay = &find("delayrotate").unwrap().params()[0];
+    assert!(matches!(delay.parse("0:1"), Err(ParamError::ParseInt(_))));
+
+    let params = find("gain").unwrap().params();
+    let args = Args::parse(params, &[String::from("lfo:saw:1:0:1")]).unwrap();
+    assert_eq!(args.automation("gain"), automation("lfo:saw:1:0:1"));
+}
+
+#[test]
+fn flat_automations_match_constants() {
+    let input: Vec<f32> = (0..4000).map(|i| (i as f32 * 0.03).sin() * 0.8).collect();
+    let run = |name: &str, args: &[&str], blocks: usize| {
+        let effect = find(name).unwrap();
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let args = Args::parse(effect.params(), &args).unwrap();
+        let metadata = metadata(2, 1000);
+        let mut stage = effect.build(&args, &metadata);
+        let mut data = input.clone();
+        match &mut stage {
+            Stage::Stream(processor) => data.chunks_mut(blocks).for_each(|b| processor.process(b)),
+            Stage::Whole(_) => data = stage.apply(AudioBuffer { metadata, data }).data,
+        }
+        data
+    };
+    let cases: [(&str, &[&str], &[&str]); 3] = [
+        ("delaypitch", &["1.5", "10"], &["0:1.5,9:1.5", "10"]),
+        (
+            "delayrotate",
+            &["10", "0.5", "3"],
+            &["10", "0.5", "0:3,9:3"],
+        ),
+        ("tensepseudocycles", &["0.3"], &["0:0.3,9:0.3"]),
+    ];
+    for (name, constant, flat) in cases.iter() {
+        let whole = run(name, constant, input.len());
+        assert_eq!(run(name, flat, input.len()), whole, "{}", name);
+        assert_eq!(run(name, flat, 2048), whole, "{}", name);
+    }
+
+    // and changing values are followed
+    let rising = run("delayrotate", &["10", "0.5", "0:0,4:8"], input.len());
+    assert_ne!(rising, run("delayrotate", &["10", "0.5", "0"], input.len()));
+    let rising = run("delaypitch", &["0:0.5,4:1.5", "10"], 100);
+    assert_eq!(
+        rising,
+        run("delaypitch", &["0:0.5,4:1.5", "10"], input.len())
+    );
+    assert_ne!(rising, run("delaypitch", &["0.5", "10"], input.len()));
+}
+
+#[test]
+fn varispeed_follows_the_input() {
+    let mut audio = AudioBuffer {
+        metadata: metadata(1, 10),
+        data: (0..40).map(|i| i as f32).collect(),
+    };
+    audio.metadata.chunks.cues.push(CuePoint {
+        id: 1,
+        position: 20,
+        label: None,
+    });
+    // twice as fast for the first second of the input, then at normal speed
+    let out = varispeed(audio, &automation("0:2,0.999:2,1:1"));
+    assert_eq!(out.data[..6], [0., 2., 4., 6., 8., 10.]);
+    assert_eq!(
+        out.data[5..],
+        (10..40).map(|i| i as f32).collect::<Vec<_>>()
+    );
+    assert_eq!(out.metadata.chunks.cues[0].position, 15);
+}
@@ -0,0 +1,121 @@
+use screech::batch::*;
+use screech::effect::*;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn invocation(name: &str, iterations: u32, args: &[&str]) -> Invocation {
+    let effect = find(name).unwrap();
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    Invocation {
+        effect,
+        iterations,
+        args: Args::parse(effect.params(), &args).unwrap(),
+        mix: None,
+    }
+}
+
+#[test]
+fn globs() {
+    let dir = std::env::temp_dir().join(format!("screech_glob_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    for file in &[
+        "a.wav",
+        "b.wav",
+        "c.aif",
+        ".hidden.wav",
+        "sub/d.wav",
+        "sub/e1.wav",
+    ] {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"").unwrap();
+    }
+    let root = dir.to_string_lossy();
+    let names = |pattern: &str| -> Vec<PathBuf> {
+        glob(&format!("{}/{}", root, pattern))
+            .unwrap()
+            .into_iter()
+            .map(|path| path.strip_prefix(&dir).unwrap().to_path_buf())
+            .collect()
+    };
+
+    assert_eq!(names("*.wav"), [Path::new("a.wav"), Path::new("b.wav")]);
+    assert_eq!(names("?.*"), ["a.wav", "b.wav", "c.aif"].map(PathBuf::from));
+    assert_eq!(names("[!a].wav"), [PathBuf::from("b.wav")]);
+    assert_eq!(names("[a-b].wav"), ["a.wav", "b.wav"].map(PathBuf::from));
+    assert_eq!(names(".*.wav"), [PathBuf::from(".hidden.wav")]);
+    assert_eq!(
+        names("s*/*.wav"),
+        ["sub/d.wav", "sub/e1.wav"].map(PathBuf::from)
+    );
+    assert_eq!(names("*/e?.wav"), [PathBuf::from("sub/e1.wav")]);
+    assert!(names("*.flac").is_empty());
+    // without wildcards the path is kept, even if it doesn't exist
+    assert_eq!(glob("missing.wav").unwrap(), [PathBuf::from("missing.wav")]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn names_outputs_like_fzf_screech() {
+    let chain = [
+        invocation("softclip", 1, &["0.4"]),
+        invocation("fold", 3, &[]),
+        invocation("tense", 1, &["2"]),
+    ];
+    assert_eq!(chain_name(&chain), "softcliped_0.4_3xfolded_tensed_2");
+    assert_eq!(chain_name(&[]), "copy");
+    let mixed = Invocation {
+        mix: Some(Value::Float(0.3)),
+        ..invocation("fold", 1, &[])
+    };
+    assert_eq!(chain_name(&[mixed]), "folded_mixed_0.3");
+    // breakpoints, which could come from a long CSV file, are left out of names
+    let envelope: Vec<String> = (0..1000).map(|i| format!("{}:{}", i, i % 2)).collect();
+    let automated = invocation("gain", 1, &[&envelope.join(",")]);
+    assert_eq!(chain_name(&[automated]), "gained_auto");
+    let lfo = invocation("gain", 1, &["lfo:sine:1:0:1"]);
+    assert_eq!(chain_name(&[lfo]), "gained_lfo:sine:1:0:1");
+
+    let chain = [invocation("gain", 1, &["0.5"])];
+    assert_eq!(
+        output_path(
+            "{dir}/{stem}_{chain}.wav",
+            Path::new("in/a.b.wav"),
+            &chain_name(&chain)
+        )
+        .unwrap(),
+        "in/a.b_gained_0.5.wav"
+    );
+    assert_eq!(
+        output_path("{dir}/{stem}.flac", Path::new("a.wav"), &chain_name(&chain)).unwrap(),
+        "./a.flac"
+    );
+    assert!(output_path("{stem", Path::new("a.wav"), "copy").is_err());
+    assert!(output_path("{name}.wav", Path::new("a.wav"), "copy").is_err());
+}
+
+#[test]
+fn names_sweep_variants() {
+    assert_eq!(
+        variant_path("out/mix.wav", "gain-gain-0.5"),
+        "out/mix_gain-gain-0.5.wav"
+    );
+    assert_eq!(variant_path("mix", "dc-dc-1"), "mix_dc-dc-1");
+    assert_eq!(variant_path("mix.wav", ""), "mix.wav");
+}
+
+#[test]
+fn parallel_results_keep_their_order() {
+    let items: Vec<u64> = (0..100).collect();
+    for jobs in [1, 3, 200] {
+        let results = run_parallel(&items, jobs, |&i| {
+            // uneven work so that threads finish out of order
+            (0..(i % 7) * 1000).fold(i, |x, _| x.wrapping_mul(31) % 1_000_003);
+            i * 2
+        });
+        assert_eq!(results, items.iter().map(|i| i * 2).collect::<Vec<_>>());
+    }
+    assert!(run_parallel(&[] as &[u8], 4, |&x| x).is_empty());
+}
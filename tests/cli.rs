@@ -0,0 +1,146 @@
+mod common;
+
+use screech::io::*;
+use screech::types::*;
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Runs screech in `dir` on `input`, written there as in.wav, returning its exit code.
+fn screech(dir: &Path, input: &[u8], args: &[&str]) -> i32 {
+    std::fs::write(dir.join("in.wav"), input).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_screech"))
+        .current_dir(dir)
+        .arg("in.wav")
+        .args(args)
+        .arg("out.wav")
+        .output()
+        .unwrap()
+        .status
+        .code()
+        .unwrap()
+}
+
+/// Like `screech`, on a second of silence.
+fn exit_code(dir: &Path, args: &[&str]) -> i32 {
+    let audio = common::audio(1, 44100, vec![0.; 44100]);
+    let mut file = Vec::new();
+    write_wav(&mut file, &audio, SampleFormat::Float32).unwrap();
+    screech(dir, &file, args)
+}
+
+/// Like `exit_code`, returning the output if it succeeds.
+fn run(dir: &Path, args: &[&str]) -> Option<AudioBuffer> {
+    if exit_code(dir, args) != 0 {
+        return None;
+    }
+    Some(read_wav(&mut std::fs::File::open(dir.join("out.wav")).unwrap()).unwrap())
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("screech_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A hundred frames of 16 bit mono silence, written out by hand.
+fn pcm16_silence() -> Vec<u8> {
+    let mut wav = b"RIFF".to_vec();
+    wav.extend_from_slice(&(36 + 200_u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16_u32.to_le_bytes());
+    // PCM, mono
+    wav.extend_from_slice(&[1, 0, 1, 0]);
+    wav.extend_from_slice(&44100_u32.to_le_bytes());
+    wav.extend_from_slice(&88200_u32.to_le_bytes());
+    // 2 bytes per frame, 16 bit
+    wav.extend_from_slice(&[2, 0, 16, 0]);
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&200_u32.to_le_bytes());
+    wav.extend_from_slice(&[0; 200]);
+    wav
+}
+
+#[test]
+fn optional_params_can_be_left_out() {
+    let dir = temp_dir("cli_optional");
+    let length = |args: &[&str]| run(&dir, args).map(|audio| audio.data.len());
+    assert_eq!(length(&["speed", "2"]), Some(22050));
+    assert_eq!(length(&["speed", "2", "sinc"]), Some(22050));
+    // what can't be a quality is the next option
+    assert_eq!(length(&["speed", "2", "3", "fold"]), Some(22050));
+    assert_eq!(
+        length(&["speed", "2", "speed", "0.5", "cubic"]),
+        Some(44100)
+    );
+    // required ones can't
+    assert_eq!(length(&["speed"]), None);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn non_finite_values_are_rejected() {
+    let dir = temp_dir("cli_finite");
+    let ok = |args: &[&str]| run(&dir, args).is_some();
+    assert!(ok(&["split", "branch", "0.5", "gain", "1", "sum"]));
+    assert!(ok(&["--with", "side=in.wav", "mix", "side", "0.5"]));
+    assert!(ok(&[
+        "--with",
+        "side=in.wav",
+        "duck",
+        "side",
+        "0.5",
+        "10ms"
+    ]));
+    for value in &["nan", "inf", "-inf"] {
+        assert!(!ok(&["split", "branch", value, "gain", "1", "sum"]));
+        assert!(!ok(&["--with", "side=in.wav", "mix", "side", value]));
+        assert!(!ok(&[
+            "--with",
+            "side=in.wav",
+            "duck",
+            "side",
+            value,
+            "10ms"
+        ]));
+        assert!(!ok(&["gain", value]));
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn errors_have_their_own_exit_codes() {
+    let dir = temp_dir("cli_exit_codes");
+    let wav = pcm16_silence();
+    let gain = ["gain", "1"];
+    assert_eq!(screech(&dir, &wav, &gain), 0);
+    assert_eq!(screech(&dir, &wav, &["nosuchoption"]), 1);
+    assert_eq!(screech(&dir, &wav, &["gain", "x"]), 3);
+    assert_eq!(screech(&dir, b"RIFX\0\0\0\0WAVE", &gain), 4);
+    let mut mp3 = wav.clone();
+    mp3[20] = 0x55;
+    assert_eq!(screech(&dir, &mp3, &gain), 5);
+    let mut twelve_bits = wav.clone();
+    twelve_bits[34] = 12;
+    assert_eq!(screech(&dir, &twelve_bits, &gain), 6);
+    // the RIFF size says there's more than what's left
+    assert_eq!(screech(&dir, &wav[..40], &gain), 7);
+    let mut no_fmt = wav.clone();
+    no_fmt[12..16].copy_from_slice(b"JUNK");
+    assert_eq!(screech(&dir, &no_fmt, &gain), 8);
+    let mut no_data = wav[..36].to_vec();
+    no_data[4..8].copy_from_slice(&28_u32.to_le_bytes());
+    assert_eq!(screech(&dir, &no_data, &gain), 9);
+    let mut no_channels = wav.clone();
+    no_channels[22..24].copy_from_slice(&0_u16.to_le_bytes());
+    assert_eq!(screech(&dir, &no_channels, &gain), 12);
+    assert_eq!(screech(&dir, &no_channels, &[]), 12);
+
+    let missing = Command::new(env!("CARGO_BIN_EXE_screech"))
+        .current_dir(&dir)
+        .args(["missing.wav", "gain", "1", "out.wav"])
+        .output()
+        .unwrap();
+    assert_eq!(missing.status.code(), Some(2));
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::audio;
+
+use screech::combine::*;
+use screech::graph::*;
+use screech::region::Time;
+use screech::types::*;
+
+use std::sync::Arc;
+
+fn combine(combine: Combine, input: AudioBuffer, audio: AudioBuffer) -> AudioBuffer {
+    combine
+        .stage(Arc::new(input), &audio.metadata)
+        .unwrap()
+        .apply(audio)
+}
+
+#[test]
+fn combines_inputs() {
+    let input = audio(1, 100, vec![1., 2.]);
+    let out = combine(Combine::Mix(0.5), input.clone(), audio(1, 100, vec![1.; 3]));
+    assert_eq!(out.data, [1.5, 2., 1.]);
+    let out = combine(Combine::Ring, input.clone(), audio(1, 100, vec![3.; 3]));
+    assert_eq!(out.data, [3., 6., 0.]);
+    let out = combine(Combine::Concat, input, audio(1, 100, vec![3.]));
+    assert_eq!(out.data, [3., 1., 2.]);
+
+    let out = combine(
+        Combine::Crossfade(Time::Frames(2)),
+        audio(2, 100, vec![0.; 6]),
+        audio(2, 100, vec![1.; 6]),
+    );
+    assert_eq!(out.data, [1., 1., 0.75, 0.75, 0.25, 0.25, 0., 0.]);
+}
+
+#[test]
+fn ducks_under_a_sidechain() {
+    // a mono sidechain drives stereo audio, its peak decaying by half every frame
+    let duck = Combine::Duck {
+        depth: 0.5,
+        release: Time::Frames(1),
+    };
+    let sidechain = audio(1, 100, vec![-1., 0., 0.]);
+    let out = combine(duck, sidechain, audio(2, 100, vec![1.; 8]));
+    let decay = (-1f32).exp();
+    assert_eq!(out.data[..2], [0.5; 2]);
+    assert_eq!(out.data[2], 1. - 0.5 * decay);
+    // and keeps coming back up after the end of the sidechain
+    assert!(out.data[2] < out.data[4] && out.data[4] < out.data[6] && out.data[6] < 1.);
+}
+
+#[test]
+fn matches_inputs() {
+    let stereo = audio(2, 100, vec![]);
+    let mono = audio(1, 100, vec![]).metadata;
+    let error = Combine::Mix(1.)
+        .stage(Arc::new(stereo.clone()), &mono)
+        .err()
+        .unwrap();
+    assert_eq!(error, "it has 2 channels instead of 1");
+    let duck = Combine::Duck {
+        depth: 1.,
+        release: Time::Frames(1),
+    };
+    assert!(duck.stage(Arc::new(stereo), &mono).is_ok());
+    // other sample rates are converted
+    let out = combine(
+        Combine::Concat,
+        audio(1, 200, vec![0.; 8]),
+        audio(1, 100, vec![]),
+    );
+    assert_eq!(out.data.len(), 4);
+
+    let graph = [Node::Input(String::from("other"), Combine::Concat)];
+    let inputs = [Input {
+        name: String::from("voice"),
+        audio: Arc::new(audio(1, 100, vec![])),
+    }];
+    assert!(build(&graph, &mono, &inputs).is_err());
+}
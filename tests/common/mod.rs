@@ -0,0 +1,23 @@
+//! Fixtures shared by the integration tests, each of which only uses some of them.
+#![allow(dead_code)]
+
+use screech::types::*;
+
+/// Float audio with no chunks.
+pub fn metadata(channels: u16, sample_rate: u32) -> AudioMetadata {
+    AudioMetadata {
+        channels,
+        sample_rate,
+        valid_bits_per_sample: None,
+        channel_mask: None,
+        sample_format: SampleFormat::Float32,
+        chunks: Default::default(),
+    }
+}
+
+pub fn audio(channels: u16, sample_rate: u32, data: Vec<f32>) -> AudioBuffer {
+    AudioBuffer {
+        metadata: metadata(channels, sample_rate),
+        data,
+    }
+}
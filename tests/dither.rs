@@ -0,0 +1,106 @@
+mod common;
+
+use screech::dither::*;
+use screech::types::*;
+
+fn signal(len: usize) -> Vec<f32> {
+    (0..len).map(|i| (i as f32 * 0.01).sin() * 0.3).collect()
+}
+
+fn dithered(kind: Dither, seed: u64) -> Vec<f32> {
+    dither(
+        common::audio(2, 44100, signal(20000)),
+        SampleFormat::Pcm8,
+        kind,
+        seed,
+    )
+    .data
+}
+
+/// Energy of the quantisation error of `output` once smoothed, which only keeps its low
+/// frequencies.
+fn low_frequency_error(output: &[f32]) -> f32 {
+    let error: Vec<f32> = output
+        .iter()
+        .zip(signal(output.len()))
+        .map(|(o, s)| o - s)
+        .collect();
+    // sums of 64 frames of the left channel, below about 700 Hz
+    error
+        .chunks_exact(128)
+        .map(|chunk| {
+            let left: f32 = chunk.iter().step_by(2).sum();
+            left * left
+        })
+        .sum()
+}
+
+#[test]
+fn defaults() {
+    use SampleFormat::*;
+    assert_eq!(
+        Dither::default_for(Float32, Pcm16, false),
+        Dither::Triangular
+    );
+    assert_eq!(Dither::default_for(Pcm24, Pcm16, false), Dither::Triangular);
+    assert_eq!(Dither::default_for(Pcm16, Pcm24, false), Dither::None);
+    assert_eq!(Dither::default_for(Pcm16, Pcm16, false), Dither::None);
+    // effects produce values in between those of the input format
+    assert_eq!(Dither::default_for(Pcm16, Pcm16, true), Dither::Triangular);
+    assert_eq!(Dither::default_for(Pcm16, Float32, true), Dither::None);
+    // 32 bit floats don't have more precision than 24 bit integers
+    assert_eq!(Dither::default_for(Float32, Pcm24, true), Dither::None);
+    assert_eq!(Dither::default_for(Float64, Pcm32, true), Dither::None);
+    assert_eq!(
+        Dither::default_for(Float32, Pcm8, false),
+        Dither::Triangular
+    );
+}
+
+#[test]
+fn lands_on_the_format() {
+    for kind in [Dither::Rectangular, Dither::Triangular, Dither::NoiseShaped] {
+        let output = dithered(kind, 1);
+        assert!(output.iter().all(|s| (s * 128.).fract() == 0.));
+        let error = output
+            .iter()
+            .zip(signal(output.len()))
+            .map(|(o, s)| (o - s).abs());
+        assert!(error.fold(0., f32::max) < 0.1, "{:?}", kind);
+    }
+    assert_eq!(dithered(Dither::None, 1), signal(20000));
+}
+
+#[test]
+fn seeds() {
+    // the same seed gives the same noise, another seed other noise
+    assert_eq!(
+        dithered(Dither::Triangular, 3),
+        dithered(Dither::Triangular, 3)
+    );
+    assert_ne!(
+        dithered(Dither::Triangular, 3),
+        dithered(Dither::Triangular, 4)
+    );
+    assert_ne!(
+        dithered(Dither::Triangular, 0),
+        dithered(Dither::Triangular, 1)
+    );
+
+    // and processing in pieces gives the same result as in one go
+    for kind in [Dither::Triangular, Dither::NoiseShaped] {
+        let mut data = signal(20000);
+        let mut ditherer = Ditherer::new(kind, SampleFormat::Pcm8, 2, 3);
+        for block in data.chunks_mut(998) {
+            ditherer.process(block);
+        }
+        assert_eq!(data, dithered(kind, 3), "{:?}", kind);
+    }
+}
+
+#[test]
+fn noise_shaping_moves_the_error_up() {
+    let flat = low_frequency_error(&dithered(Dither::Triangular, 5));
+    let shaped = low_frequency_error(&dithered(Dither::NoiseShaped, 5));
+    assert!(shaped < flat / 4., "{} against {}", shaped, flat);
+}
@@ -0,0 +1,298 @@
+mod common;
+
+use screech::effect::*;
+use screech::types::*;
+
+fn audio(data: Vec<f32>) -> AudioBuffer {
+    common::audio(2, 44100, data)
+}
+
+fn strings(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn names_are_unique() {
+    for (i, effect) in EFFECTS.iter().enumerate() {
+        assert!(EFFECTS[..i].iter().all(|e| e.name() != effect.name()));
+        // an abbreviation never hides a later effect's full name
+        assert_eq!(find(effect.name()).unwrap().name(), effect.name());
+    }
+}
+
+#[test]
+fn abbreviations_follow_the_registry_order() {
+    assert_eq!(find("t").unwrap().name(), "tense");
+    assert_eq!(find("d").unwrap().name(), "decimate");
+    assert_eq!(find("de").unwrap().name(), "decimate");
+    assert_eq!(find("del").unwrap().name(), "delaypitch");
+    assert_eq!(find("delayr").unwrap().name(), "delayrotate");
+    assert!(find("x").is_none());
+}
+
+#[test]
+fn defaults_are_in_range() {
+    for effect in EFFECTS {
+        for param in effect.params() {
+            assert!(param.check(param.default.clone()).is_ok(), "{}", param.name);
+        }
+    }
+}
+
+#[test]
+fn parses_and_validates_args() {
+    let effect = find("delayrotate").unwrap();
+    assert_eq!(
+        effect.synopsis(),
+        "delayrotate <delay> <feedback> <frequency>"
+    );
+    assert_eq!(
+        effect.expected_args(),
+        "delayrotate takes an integer delay, a decimal feedback and a decimal frequency"
+    );
+
+    let args = Args::parse(effect.params(), &strings(&["10", "0.5", "3"])).unwrap();
+    assert_eq!(args.int("delay"), 10);
+    assert_eq!(args.float("feedback"), 0.5);
+    assert_eq!(
+        args.values(),
+        [Value::Int(10), Value::Float(0.5), Value::Float(3.)]
+    );
+
+    assert!(matches!(
+        Args::parse(effect.params(), &strings(&["10.5", "0.5", "3"])),
+        Err(ParamError::ParseInt(_))
+    ));
+    assert!(matches!(
+        Args::parse(effect.params(), &strings(&["10", "x", "3"])),
+        Err(ParamError::ParseFloat(_))
+    ));
+    assert!(matches!(
+        Args::parse(effect.params(), &strings(&["0", "0.5", "3"])),
+        Err(ParamError::OutOfRange(param, _)) if param.name == "delay"
+    ));
+    // NaN is in no range
+    assert!(matches!(
+        Args::parse(effect.params(), &strings(&["10", "nan", "3"])),
+        Err(ParamError::OutOfRange(param, _)) if param.name == "feedback"
+    ));
+    assert!(find("hardclip").unwrap().params()[0].parse("nan").is_err());
+    let err = Args::parse(effect.params(), &strings(&["10", "0.5"])).unwrap_err();
+    assert_eq!(err.to_string(), "missing a decimal frequency");
+}
+
+#[test]
+fn iterations() {
+    let gain = Invocation {
+        effect: find("gain").unwrap(),
+        iterations: 3,
+        args: Args::parse(find("gain").unwrap().params(), &strings(&["2"])).unwrap(),
+        mix: None,
+    };
+    let mut stage = gain.build(&audio(Vec::new()).metadata);
+    assert!(matches!(stage, Stage::Stream(_)));
+    assert_eq!(stage.apply(audio(vec![0.5, -0.25])).data, [4., -2.]);
+
+    let none = Invocation {
+        iterations: 0,
+        ..gain.clone()
+    };
+    assert_eq!(
+        none.build(&audio(Vec::new()).metadata)
+            .apply(audio(vec![0.5, -0.25]))
+            .data,
+        [0.5, -0.25]
+    );
+
+    // a chain only streams if all of its stages do
+    let normalize = Invocation {
+        effect: find("normalize").unwrap(),
+        iterations: 1,
+        args: Args::parse(&[], &[]).unwrap(),
+        mix: None,
+    };
+    let metadata = audio(Vec::new()).metadata;
+    let mut stage = Stage::chain(vec![gain.build(&metadata), normalize.build(&metadata)]);
+    assert!(matches!(stage, Stage::Whole(_)));
+    assert_eq!(stage.apply(audio(vec![0.5, -0.25])).data, [1., -0.5]);
+}
+
+#[test]
+fn some_effects_are_applied_once() {
+    let once: Vec<&str> = EFFECTS
+        .iter()
+        .filter(|effect| !effect.repeats())
+        .map(|effect| effect.name())
+        .collect();
+    assert_eq!(
+        once,
+        ["expand", "hardclip", "decimate", "removedc", "normalize"]
+    );
+
+    // whatever the number of iterations, like before there were any for them
+    let hardclip = find("hardclip").unwrap();
+    for iterations in [0, 1, 3] {
+        let clip = Invocation {
+            effect: hardclip,
+            iterations,
+            args: Args::parse(hardclip.params(), &strings(&["0.5"])).unwrap(),
+            mix: None,
+        };
+        let out = clip
+            .build(&audio(Vec::new()).metadata)
+            .apply(audio(vec![0.75, -0.25]));
+        assert_eq!(out.data, [0.5, -0.25]);
+    }
+}
+
+#[test]
+fn every_effect_runs_with_its_defaults() {
+    let data: Vec<f32> = (0..2000).map(|i| (i as f32 * 0.05).sin() * 0.7).collect();
+    for effect in EFFECTS {
+        let defaults: Vec<String> = effect
+            .params()
+            .iter()
+            .map(|param| param.default.to_string())
+            .collect();
+        let args = Args::parse(effect.params(), &defaults).unwrap();
+        let input = audio(data.clone());
+        let output = effect.build(&args, &input.metadata).apply(input);
+        assert_eq!(output.metadata.channels, 2, "{}", effect.name());
+        assert!(
+            output.data.iter().all(|s| s.is_finite()),
+            "{}",
+            effect.name()
+        );
+    }
+}
+
+#[test]
+fn enum_params() {
+    static QUALITY: Param = Param::choice("quality", &["low", "high"], "high");
+    assert_eq!(QUALITY.parse("low").unwrap(), Value::Enum("low"));
+    let err = QUALITY.parse("medium").unwrap_err();
+    assert!(matches!(err, ParamError::UnknownChoice(_, _)));
+    assert_eq!(
+        err.to_string(),
+        "unknown quality medium, it is one of low, high"
+    );
+
+    // an optional one takes its default when it is left out
+    let speed = find("speed").unwrap();
+    assert_eq!(speed.synopsis(), "speed <speed> [<quality>]");
+    assert_eq!(
+        speed.expected_args(),
+        "speed takes a decimal speed and optionally a quality among linear/cubic/sinc"
+    );
+    let args = Args::parse(speed.params(), &strings(&["2"])).unwrap();
+    assert_eq!(args.choice("quality"), "linear");
+}
+
+#[test]
+fn json_catalogue() {
+    let json = catalogue_json();
+    assert!(json.starts_with("[\n") && json.ends_with("\n]"));
+    // one line per effect
+    assert_eq!(json.lines().count(), EFFECTS.len() + 2);
+    assert!(json.contains(
+        "{\"name\": \"frequency\", \"type\": \"float\", \"default\": 1, \"min\": 0, \"max\": null, \
+         \"unit\": \"Hz\", \"automatable\": true}"
+    ));
+    assert!(json.contains(
+        "{\"name\": \"fold\", \"description\": \"Folds samples back with a sine\", \"params\": []}"
+    ));
+    for effect in EFFECTS {
+        assert!(!effect.description().is_empty());
+        assert!(json.contains(&format!("\"name\": \"{}\"", effect.name())));
+    }
+}
+
+#[test]
+fn sweeps() {
+    let amount = &find("softclip").unwrap().params()[0];
+    let floats = |values: Vec<Value>| -> Vec<f64> {
+        values
+            .into_iter()
+            .map(|value| match value {
+                Value::Float(x) => x,
+                _ => panic!("{:?} isn't a float", value),
+            })
+            .collect()
+    };
+    assert_eq!(
+        floats(amount.parse_sweep("0.1..0.9:5").unwrap()),
+        [0.1, 0.3, 0.5, 0.7, 0.9]
+    );
+    assert_eq!(floats(amount.parse_sweep("[1, -2]").unwrap()), [1., -2.]);
+    assert_eq!(floats(amount.parse_sweep("0.5").unwrap()), [0.5]);
+    assert_eq!(floats(amount.parse_sweep("2..4:1").unwrap()), [2.]);
+    assert!(amount.parse_sweep("0..1:0").is_err());
+    assert!(amount.parse_sweep("0..1:x").is_err());
+    assert!(amount.parse_sweep("[0.1,x]").is_err());
+
+    // the commas of breakpoints aren't taken for those of a list
+    let gain = &find("gain").unwrap().params()[0];
+    assert!(matches!(
+        gain.parse_sweep("[0:1,1:2]"),
+        Err(ParamError::BadSweep(_))
+    ));
+    assert_eq!(
+        gain.parse_sweep("0:1,1:2").unwrap(),
+        [gain.parse("0:1,1:2").unwrap()]
+    );
+
+    let depth = &find("fractalize").unwrap().params()[0];
+    assert_eq!(
+        depth.parse_sweep("1..10:4").unwrap(),
+        [1, 4, 7, 10].map(Value::Int)
+    );
+    // every value is checked against the range
+    assert!(depth.parse_sweep("0..4:3").is_err());
+
+    let chain = [
+        Sweep {
+            effect: find("softclip").unwrap(),
+            iterations: 2,
+            values: vec![vec![Value::Float(0.1), Value::Float(0.2)]],
+            mix: None,
+        },
+        Sweep {
+            effect: find("delaypitch").unwrap(),
+            iterations: 1,
+            values: vec![
+                vec![Value::Float(0.5), Value::Float(2.), Value::Float(3.)],
+                vec![Value::Int(12)],
+            ],
+            mix: None,
+        },
+        Sweep {
+            effect: find("softclip").unwrap(),
+            iterations: 1,
+            values: vec![vec![Value::Float(1.), Value::Float(2.)]],
+            mix: None,
+        },
+    ];
+    let variants = expand_sweeps(&chain);
+    assert_eq!(variants.len(), 12);
+    assert_eq!(
+        variants[0].0,
+        "softclip-amount-0.1_delaypitch-factor-0.5_softclip3-amount-1"
+    );
+    assert_eq!(
+        variants[11].0,
+        "softclip-amount-0.2_delaypitch-factor-3_softclip3-amount-2"
+    );
+    let last = &variants[11].1;
+    assert_eq!(last[0].iterations, 2);
+    assert_eq!(last[1].args.values(), [Value::Float(3.), Value::Int(12)]);
+
+    // without sweeps there's a single unnamed variant
+    let variants = expand_sweeps(&[Sweep {
+        effect: find("gain").unwrap(),
+        iterations: 1,
+        values: vec![vec![Value::Float(0.5)]],
+        mix: None,
+    }]);
+    assert_eq!(variants.len(), 1);
+    assert_eq!(variants[0].0, "");
+}
@@ -0,0 +1,274 @@
+mod common;
+
+use screech::io::*;
+use screech::types::*;
+
+fn audio(channels: u16, data: Vec<f32>) -> AudioBuffer {
+    common::audio(channels, 44100, data)
+}
+
+fn round_trip(audio: &AudioBuffer, format: SampleFormat) -> (AudioBuffer, usize) {
+    let mut file = Vec::new();
+    write_flac(&mut file, audio, format).unwrap();
+    (read_audio(&mut &file[..]).unwrap(), file.len())
+}
+
+/// Deterministic noise in [-1, 1), already on the grid of `bit_depth`.
+fn noise(len: usize, bit_depth: u16, seed: u64) -> Vec<f32> {
+    let mut state = seed | 1;
+    let scale = (1_u64 << (bit_depth - 1)) as f64;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let value = (state >> (64 - bit_depth)) as i64 - scale as i64;
+            (value as f64 / scale) as f32
+        })
+        .collect()
+}
+
+const INTEGER_FORMATS: [SampleFormat; 4] = [
+    SampleFormat::Pcm8,
+    SampleFormat::Pcm16,
+    SampleFormat::Pcm24,
+    SampleFormat::Pcm32,
+];
+
+#[test]
+fn bit_exact_noise() {
+    for &format in &INTEGER_FORMATS {
+        for &channels in &[1, 2, 3] {
+            // not a multiple of the block size, so that the last frame is shorter
+            let data = noise(10001 * channels, format.bit_depth(), 42);
+            let original = audio(channels as u16, data);
+            let (decoded, _) = round_trip(&original, format);
+            assert_eq!(decoded.metadata.channels, channels as u16);
+            assert_eq!(decoded.metadata.sample_rate, 44100);
+            assert_eq!(decoded.metadata.sample_format, format);
+            assert_eq!(decoded.data, original.data);
+        }
+    }
+}
+
+#[test]
+fn bit_exact_extremes() {
+    for &format in &INTEGER_FORMATS {
+        let max = 1. - 1. / (1_u64 << (format.bit_depth() - 1)) as f32;
+        let data = [-1., max, max, -1., 0., 0., -1., -1.].repeat(100);
+        let (decoded, _) = round_trip(&audio(2, data.clone()), format);
+        assert_eq!(decoded.data, data);
+    }
+}
+
+#[test]
+fn compresses_correlated_audio() {
+    // the same sine on both channels, with 8 wasted bits when stored as 24 bit
+    let data: Vec<f32> = (0..20000)
+        .map(|i| ((i / 2) as f32 * 0.01).sin() * 0.5)
+        .map(|s| (s * 32768.).round() / 32768.)
+        .collect();
+    for &format in &[SampleFormat::Pcm16, SampleFormat::Pcm24] {
+        let (decoded, size) = round_trip(&audio(2, data.clone()), format);
+        assert_eq!(decoded.data, data);
+        assert!(size < data.len() * 2 / 3, "{} bytes", size);
+    }
+
+    let silence = vec![0.; 20000];
+    let (decoded, size) = round_trip(&audio(2, silence.clone()), SampleFormat::Pcm16);
+    assert_eq!(decoded.data, silence);
+    assert!(size < 200, "{} bytes", size);
+}
+
+#[test]
+fn empty() {
+    let (decoded, _) = round_trip(&audio(2, Vec::new()), SampleFormat::Pcm16);
+    assert!(decoded.data.is_empty());
+}
+
+#[test]
+fn tags() {
+    let mut original = audio(1, vec![0.; 10]);
+    original.metadata.chunks.info = vec![
+        (*b"INAM", String::from("name")),
+        (*b"IART", String::from("artist")),
+    ];
+    let (decoded, _) = round_trip(&original, SampleFormat::Pcm16);
+    assert_eq!(decoded.metadata.chunks.info, original.metadata.chunks.info);
+}
+
+#[test]
+fn float_is_rejected() {
+    let mut file = Vec::new();
+    assert!(write_flac(&mut file, &audio(1, vec![0.; 10]), SampleFormat::Float32).is_err());
+    assert_eq!(
+        Container::from_path("out.flac").default_format(SampleFormat::Float32),
+        SampleFormat::Pcm24
+    );
+}
+
+#[test]
+fn detects_corruption() {
+    let original = audio(1, noise(1000, 16, 7));
+    let mut file = Vec::new();
+    write_flac(&mut file, &original, SampleFormat::Pcm16).unwrap();
+
+    let mut corrupt = file.clone();
+    // last byte of the final frame's CRC
+    let last = corrupt.len() - 1;
+    corrupt[last] ^= 0x10;
+    assert!(matches!(
+        read_flac(&mut &corrupt[..]),
+        Err(WavError::Corrupt(_))
+    ));
+
+    let truncated = &file[..file.len() - 10];
+    assert!(matches!(
+        read_flac(&mut &truncated[..]),
+        Err(WavError::Truncated)
+    ));
+}
+
+/// Big endian bit fields, to write streams by hand.
+#[derive(Default)]
+struct Bits(Vec<bool>);
+
+impl Bits {
+    fn put(&mut self, value: i64, n: u32) -> &mut Self {
+        for i in (0..n).rev() {
+            self.0.push(value >> i & 1 == 1);
+        }
+        self
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.0
+            .chunks(8)
+            .map(|bits| {
+                let byte = bits.iter().fold(0, |byte, &bit| byte << 1 | bit as u8);
+                byte << (8 - bits.len())
+            })
+            .collect()
+    }
+}
+
+fn crc(bytes: &[u8], polynomial: u16, width: u32) -> u16 {
+    let top = 1 << (width - 1);
+    let mask = ((1_u32 << width) - 1) as u16;
+    bytes.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ u16::from(byte) << (width - 8), |crc, _| {
+            (if crc & top != 0 {
+                crc << 1 ^ polynomial
+            } else {
+                crc << 1
+            }) & mask
+        })
+    })
+}
+
+#[test]
+fn decodes_lpc_escapes_and_mid_side() {
+    let mut file = b"fLaC".to_vec();
+    let mut info = Bits::default();
+    info.put(8, 16).put(8, 16).put(0, 24).put(0, 24);
+    info.put(44100, 20).put(1, 3).put(15, 5).put(8, 36);
+    file.extend_from_slice(&[0x80, 0, 0, 34]);
+    file.extend_from_slice(&info.bytes());
+    file.extend_from_slice(&[0; 16]);
+
+    let mut frame = Bits::default();
+    // sync, fixed blocking, 8 bit block size, sample rate from STREAMINFO, mid/side, 16 bit
+    frame.put(0b11_1111_1111_1110, 14).put(0, 2);
+    frame.put(6, 4).put(0, 4).put(10, 4).put(4, 3).put(0, 1);
+    frame.put(0, 8).put(7, 8);
+    let crc8 = crc(&frame.bytes(), 0x07, 8);
+    frame.put(i64::from(crc8), 8);
+
+    // mid: LPC of order 2 with coefficients 2 and -1, precision 3 and no shift
+    frame.put(0, 1).put(33, 6).put(0, 1);
+    frame.put(10, 16).put(20, 16);
+    frame.put(2, 4).put(0, 5).put(2, 3).put(-1, 3);
+    // two partitions, the first one Rice coded with parameter 1, the second one escaped
+    frame.put(0, 2).put(1, 4);
+    frame.put(1, 4);
+    // 1 and -1 fold to 2 and 1
+    frame.put(0b010, 3).put(0b11, 2);
+    frame.put(15, 4).put(4, 5);
+    for &r in &[3, -2, 0, 7] {
+        frame.put(r, 4);
+    }
+    // side: constant 3 on 17 bits
+    frame.put(0, 1).put(0, 6).put(0, 1).put(3, 17);
+    while frame.0.len() % 8 != 0 {
+        frame.0.push(false);
+    }
+    let crc16 = crc(&frame.bytes(), 0x8005, 16);
+    frame.put(i64::from(crc16), 16);
+    file.extend_from_slice(&frame.bytes());
+
+    let decoded = read_flac(&mut &file[..]).unwrap();
+    let mid = [10, 20, 31, 41, 54, 65, 76, 94];
+    let expected: Vec<f32> = mid
+        .iter()
+        .flat_map(|m| vec![m + 2, m - 1])
+        .map(|s| s as f32 / 32768.)
+        .collect();
+    assert_eq!(decoded.data, expected);
+}
+
+/// STREAMINFO for mono 16 bit audio of `frames` frames.
+fn stream_info(frames: i64) -> Vec<u8> {
+    let mut info = Bits::default();
+    info.put(4096, 16).put(4096, 16).put(0, 24).put(0, 24);
+    info.put(44100, 20).put(0, 3).put(15, 5).put(frames, 36);
+    let mut file = b"fLaC".to_vec();
+    file.extend_from_slice(&[0x80, 0, 0, 34]);
+    file.extend_from_slice(&info.bytes());
+    file.extend_from_slice(&[0; 16]);
+    file
+}
+
+#[test]
+fn crafted_files_are_errors() {
+    // more frames than could ever be allocated
+    let file = stream_info((1 << 36) - 1);
+    assert!(matches!(
+        read_flac(&mut &file[..]),
+        Err(WavError::Truncated)
+    ));
+
+    // the 20 bits of the sample rate come after the block and frame sizes
+    let mut file = stream_info(0);
+    file[18..20].fill(0);
+    file[20] &= 0x0F;
+    assert!(matches!(
+        read_flac(&mut &file[..]),
+        Err(WavError::InvalidHeader(_))
+    ));
+
+    // a fourth order predictor fed the largest residuals overflows before the checksum
+    let mut file = stream_info(4096);
+    let mut frame = Bits::default();
+    frame.put(0b11_1111_1111_1110, 14).put(0, 2);
+    frame.put(12, 4).put(0, 4).put(0, 4).put(0, 3).put(0, 1);
+    frame.put(0, 8);
+    let crc8 = crc(&frame.bytes(), 0x07, 8);
+    frame.put(i64::from(crc8), 8);
+    frame.put(0, 1).put(12, 6).put(0, 1);
+    for _ in 0..4 {
+        frame.put(i64::from(i16::MAX), 16);
+    }
+    frame.put(0, 2).put(0, 4).put(15, 4).put(31, 5);
+    for _ in 4..4096 {
+        frame.put((1 << 30) - 1, 31);
+    }
+    while frame.0.len() % 8 != 0 {
+        frame.0.push(false);
+    }
+    frame.put(0, 16);
+    file.extend_from_slice(&frame.bytes());
+    assert!(matches!(
+        read_flac(&mut &file[..]),
+        Err(WavError::Corrupt(_))
+    ));
+}
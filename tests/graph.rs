@@ -0,0 +1,103 @@
+mod common;
+
+use screech::batch::graph_name;
+use screech::effect::*;
+use screech::graph::*;
+use screech::types::*;
+
+fn audio(channels: u16, data: Vec<f32>) -> AudioBuffer {
+    common::audio(channels, 100, data)
+}
+
+fn effect(name: &str, args: &[&str]) -> Node<Invocation> {
+    let effect = find(name).unwrap();
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    Node::Effect(Invocation {
+        effect,
+        iterations: 1,
+        args: Args::parse(effect.params(), &args).unwrap(),
+        mix: None,
+    })
+}
+
+#[test]
+fn sums_branches() {
+    // the input doubled at half its level, plus the input untouched
+    let graph = [Node::Split(vec![
+        (0.5, vec![effect("gain", &["2"])]),
+        (1., vec![]),
+    ])];
+    let input = audio(1, vec![1., -0.5, 0.25]);
+    let mut stage = build(&graph, &input.metadata, &[]).unwrap();
+    assert!(matches!(stage, Stage::Stream(_)));
+    assert_eq!(stage.apply(input).data, [2., -1., 0.5]);
+
+    // a branch that halves the length is padded with silence
+    let graph = [Node::Split(vec![
+        (1., vec![effect("speed", &["2", "linear"])]),
+        (1., vec![effect("gain", &["0.5"])]),
+    ])];
+    let input = audio(1, vec![1.; 8]);
+    let mut stage = build(&graph, &input.metadata, &[]).unwrap();
+    let out = stage.apply(input);
+    assert_eq!(out.data.len(), 8);
+    assert_eq!(out.data[4..], [0.5; 4]);
+}
+
+#[test]
+fn processes_channels_separately() {
+    let graph = [Node::Channels(vec![(1, vec![effect("gain", &["0"])])])];
+    let input = audio(2, vec![1., 1., 0.5, 0.5]);
+    let mut stage = build(&graph, &input.metadata, &[]).unwrap();
+    assert!(matches!(stage, Stage::Stream(_)));
+    assert_eq!(stage.apply(input).data, [1., 0., 0.5, 0.]);
+
+    // channels of different lengths are padded with silence
+    let graph = [Node::Channels(vec![
+        (0, vec![effect("speed", &["2", "linear"])]),
+        (1, vec![effect("gain", &["2"])]),
+    ])];
+    let input = audio(2, vec![1.; 8]);
+    let out = build(&graph, &input.metadata, &[]).unwrap().apply(input);
+    assert_eq!(
+        out.data[1..].iter().step_by(2).collect::<Vec<_>>(),
+        [&2.; 4]
+    );
+    assert_eq!(out.data.len(), 8);
+
+    let missing = [Node::Channels(vec![(2, vec![])])];
+    assert!(build(&missing, &audio(2, vec![]).metadata, &[]).is_err());
+    let twice = [Node::Channels(vec![(0, vec![]), (0, vec![])])];
+    assert!(build(&twice, &audio(2, vec![]).metadata, &[]).is_err());
+}
+
+#[test]
+fn sweeps_and_names_branches() {
+    let gain = find("gain").unwrap();
+    let sweep = Sweep {
+        effect: gain,
+        iterations: 1,
+        values: vec![gain.params()[0].parse_sweep("[1,2]").unwrap()],
+        mix: None,
+    };
+    let graph = [
+        Node::Effect(Sweep {
+            effect: find("fold").unwrap(),
+            iterations: 1,
+            values: Vec::new(),
+            mix: None,
+        }),
+        Node::Split(vec![(0.5, vec![Node::Effect(sweep)]), (1., vec![])]),
+    ];
+    let variants = expand_graph(&graph);
+    assert_eq!(variants.len(), 2);
+    assert_eq!(variants[1].0, "gain-gain-2");
+    assert_eq!(
+        graph_name(&variants[1].1),
+        "folded_split_branch_0.5_gained_2_branch_1_copy_sum"
+    );
+    assert!(linear(&variants[1].1).is_none());
+
+    let channels = [Node::Channels(vec![(1, vec![effect("dc", &["0.1"])])])];
+    assert_eq!(graph_name(&channels), "channels_channel_1_dced_0.1_merge");
+}
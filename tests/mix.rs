@@ -0,0 +1,160 @@
+mod common;
+
+use screech::automation::Automation;
+use screech::effect::*;
+use screech::gain::Gain;
+use screech::graph::{build, Node};
+use screech::mix::*;
+use screech::processor::Processor;
+use screech::types::*;
+
+use std::collections::VecDeque;
+
+fn metadata(channels: u16) -> AudioMetadata {
+    common::metadata(channels, 4)
+}
+
+fn audio(data: Vec<f32>) -> AudioBuffer {
+    AudioBuffer {
+        metadata: metadata(1),
+        data,
+    }
+}
+
+/// Delays its input by a number of samples, which it reports as its latency.
+struct Delay(VecDeque<f32>);
+
+impl Processor for Delay {
+    fn process(&mut self, block: &mut [f32]) {
+        for s in block {
+            self.0.push_back(*s);
+            *s = self.0.pop_front().unwrap();
+        }
+    }
+
+    fn latency(&self) -> usize {
+        self.0.len()
+    }
+}
+
+#[test]
+fn mixes_streams() {
+    let mut half = Mix::new(Gain(0.), Automation::Constant(0.25), &metadata(2));
+    let mut data = vec![1., -1., 0.5, 0.];
+    half.process(&mut data);
+    assert_eq!(data, [0.75, -0.75, 0.375, 0.]);
+
+    // from dry to wet over the first second
+    let ramp = Automation::Breakpoints(vec![(0., 0.), (1., 1.)]);
+    let mut data = vec![1.; 6];
+    Mix::new(Gain(0.), ramp, &metadata(1)).process(&mut data);
+    assert_eq!(data, [1., 0.75, 0.5, 0.25, 0., 0.]);
+}
+
+#[test]
+fn compensates_latency() {
+    let delay = Delay(VecDeque::from(vec![0.; 3]));
+    let mut mixed = Mix::new(delay, Automation::Constant(0.5), &metadata(1));
+    assert_eq!(mixed.latency(), 3);
+    let input: Vec<f32> = (1..=8).map(|i| i as f32).collect();
+    let mut output = Vec::new();
+    for block in input.chunks(3) {
+        let mut block = block.to_vec();
+        mixed.process(&mut block);
+        output.extend(block);
+    }
+    // the dry input is delayed along with the effect, so that mixing doesn't comb filter
+    assert_eq!(output, [0., 0., 0., 1., 2., 3., 4., 5.]);
+}
+
+#[test]
+fn mixes_whole_stages() {
+    // halves the length, so the end of the input is mixed with silence
+    let halve = Stage::whole(|mut audio: AudioBuffer| {
+        audio.data = audio.data.iter().copied().step_by(2).collect();
+        audio
+    });
+    let mut mixed = mix(halve, Automation::Constant(0.5), &metadata(1));
+    let out = mixed.apply(audio(vec![2., 4., 6., 8.]));
+    assert_eq!(out.data, [2., 5., 3., 4.]);
+
+    let gain = mix(
+        Stage::stream(Gain(3.)),
+        Automation::Constant(0.5),
+        &metadata(1),
+    );
+    assert!(matches!(gain, Stage::Stream(_)));
+}
+
+#[test]
+fn mixes_invocations() {
+    let effect = find("gain").unwrap();
+    let mut invocation = Invocation {
+        effect,
+        iterations: 2,
+        args: Args::parse(effect.params(), &[String::from("3")]).unwrap(),
+        mix: Some(MIX.parse("0.25").unwrap()),
+    };
+    // the mix is applied once, around every iteration
+    let out = invocation.build(&metadata(1)).apply(audio(vec![1.]));
+    assert_eq!(out.data, [3.]);
+
+    invocation.mix = Some(MIX.parse("0:0,1:1").unwrap());
+    let out = invocation
+        .build(&metadata(1))
+        .apply(audio(vec![1., 1., 1., 1., 1.]));
+    assert_eq!(out.data, [1., 3., 5., 7., 9.]);
+    assert!(MIX.parse("1.5").is_err());
+
+    let sweep = Sweep {
+        effect: find("fold").unwrap(),
+        iterations: 1,
+        values: Vec::new(),
+        mix: Some(MIX.parse_sweep("[0.2,0.8]").unwrap()),
+    };
+    assert!(sweep.is_swept());
+    let variants = expand_sweeps(&[sweep]);
+    assert_eq!(variants[1].0, "fold-mix-0.8");
+    assert_eq!(variants[1].1[0].mix, Some(Value::Float(0.8)));
+}
+
+#[test]
+fn mixing_nothing_in_gives_back_the_input() {
+    let input: Vec<f32> = (0..4096).map(|i| (i as f32 * 0.05).sin() * 0.8).collect();
+    for effect in EFFECTS {
+        let args: Vec<String> = effect
+            .params()
+            .iter()
+            .map(|param| param.default.to_string())
+            .collect();
+        let invocation = Invocation {
+            effect: *effect,
+            iterations: 1,
+            args: Args::parse(effect.params(), &args).unwrap(),
+            mix: Some(MIX.parse("0").unwrap()),
+        };
+        // effects that change the length or the rate can't be mixed at all
+        if let Ok(mut stage) = build(&[Node::Effect(invocation)], &metadata(1), &[]) {
+            let out = stage.apply(audio(input.clone()));
+            assert_eq!(out.data, input, "{}", effect.name());
+        }
+    }
+}
+
+#[test]
+fn rejects_rate_and_length_changes() {
+    let mixed = |name: &str, args: &[&str]| {
+        let effect = find(name).unwrap();
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let invocation = Invocation {
+            effect,
+            iterations: 1,
+            args: Args::parse(effect.params(), &args).unwrap(),
+            mix: Some(MIX.parse("0.5").unwrap()),
+        };
+        build(&[Node::Effect(invocation)], &metadata(1), &[])
+    };
+    assert!(mixed("gain", &["2"]).is_ok());
+    assert!(mixed("resample", &["8"]).is_err());
+    assert!(mixed("speed", &["2"]).is_err());
+}
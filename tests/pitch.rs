@@ -0,0 +1,151 @@
+mod common;
+
+use screech::automation::Automation;
+use screech::pitch::*;
+use screech::processor::Processor;
+use screech::resample::*;
+use screech::types::*;
+
+const KERNELS: [Interpolation; 3] = [
+    Interpolation::Linear,
+    Interpolation::Cubic,
+    Interpolation::Sinc,
+];
+
+fn audio(data: Vec<f32>) -> AudioBuffer {
+    common::audio(1, 44100, data)
+}
+
+fn sine(frequency: f64, frames: usize) -> Vec<f32> {
+    (0..frames)
+        .map(|i| (2. * std::f64::consts::PI * frequency * i as f64 / 44100.).sin() as f32)
+        .collect()
+}
+
+/// Frequency of a sine at 44.1 kHz, from the time between its first and last rising zero
+/// crossings.
+fn frequency(data: &[f32]) -> f64 {
+    let crossings: Vec<f64> = data
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| pair[0] < 0. && pair[1] >= 0.)
+        .map(|(i, pair)| i as f64 + f64::from(pair[0] / (pair[0] - pair[1])))
+        .collect();
+    let periods = (crossings.len() - 1) as f64;
+    periods * 44100. / (crossings[crossings.len() - 1] - crossings[0])
+}
+
+#[test]
+fn speed_changes_the_frequency() {
+    let input = audio(sine(440., 44100));
+    for interpolation in KERNELS {
+        for (speed, expected) in [(1.5, 660.), (0.5, 220.), (0.37, 162.8)] {
+            let out = stretch(input.clone(), &Automation::Constant(speed), interpolation);
+            assert_eq!(out.data.len(), (44100. / speed).round() as usize);
+            let found = frequency(&out.data);
+            assert!(
+                (found - expected).abs() < 0.1,
+                "{:?} at {}: {} Hz",
+                interpolation,
+                speed,
+                found
+            );
+            // nothing is read past the end or left at its initial value
+            assert!(out.data.iter().all(|s| s.abs() <= 1.01));
+        }
+    }
+}
+
+#[test]
+fn interpolates_between_the_right_samples() {
+    let ramp: Vec<f32> = (0..8).map(|i| i as f32).collect();
+    // reading a quarter of the way from a sample gives three quarters of it
+    let out = speed(audio(ramp.clone()), 0.25);
+    assert_eq!(out.data[..6], [0., 0.25, 0.5, 0.75, 1., 1.25]);
+    assert_eq!(out.data.len(), 32);
+    // a cubic goes through straight lines too
+    let out = stretch(
+        audio(ramp.clone()),
+        &Automation::Constant(0.25),
+        Interpolation::Cubic,
+    );
+    assert_eq!(out.data[4..10], [1., 1.25, 1.5, 1.75, 2., 2.25]);
+
+    let mut data = ramp;
+    DelayPitch::new(0.25, 4, 1).process(&mut data);
+    assert_eq!(data, [0., 0.25, 0.5, 0.75, 1., 1.25, 1.5, 1.75]);
+}
+
+#[test]
+fn delaypitch_changes_the_frequency() {
+    for interpolation in KERNELS {
+        for (factor, expected) in [(0.5, 220.), (1.5, 660.)] {
+            let mut data = sine(440., 8192);
+            DelayPitch::new(factor, 14, 1)
+                .interpolation(interpolation)
+                .process(&mut data);
+            // reading up goes through the primed buffer, which ends at 8192 / 1.5 frames
+            let found = frequency(&data[1000..5000]);
+            assert!(
+                (found - expected).abs() < 0.1,
+                "{:?} by {}: {} Hz",
+                interpolation,
+                factor,
+                found
+            );
+        }
+    }
+}
+
+#[test]
+fn delaypitch_shares_its_buffer_between_any_channels() {
+    // the share of every channel is rounded down to a power of two
+    for channels in [3, 6] {
+        let mono = sine(440., 8192);
+        let mut data: Vec<f32> = mono.iter().flat_map(|&s| vec![s; channels]).collect();
+        DelayPitch::new(0.5, 14, channels as u16).process(&mut data);
+        for ch in 0..channels {
+            let channel: Vec<f32> = data.iter().skip(ch).step_by(channels).copied().collect();
+            // reading at half the speed jumps back when it has gone through twice the share
+            let found = frequency(&channel[500..4000]);
+            assert!(
+                (found - 220.).abs() < 0.1,
+                "{} channels: {} Hz",
+                channels,
+                found
+            );
+        }
+    }
+    // with less than a sample each
+    let mut data = vec![0.5; 30];
+    DelayPitch::new(2., 1, 3).process(&mut data);
+    assert!(data.iter().all(|s| s.is_finite()));
+}
+
+#[test]
+fn delaypitch_buffer_fits_short_inputs() {
+    let effect = screech::effect::find("delaypitch").unwrap();
+    let args: Vec<String> = ["2", "12"].iter().map(|arg| arg.to_string()).collect();
+    let args = screech::effect::Args::parse(effect.params(), &args).unwrap();
+    let input = audio(sine(440., 1000));
+    let out = effect.build(&args, &input.metadata).apply(input);
+    // the end isn't read from a part of the buffer that was never written
+    let silent = out.data[500..].iter().filter(|&&s| s == 0.).count();
+    assert!(silent < 50, "{} silent samples", silent);
+}
+
+#[test]
+fn delaypitch_of_nothing_is_nothing() {
+    let out = screech::pitch::delay_pitch(audio(Vec::new()), 2., 12);
+    assert!(out.data.is_empty());
+
+    let effect = screech::effect::find("delaypitch").unwrap();
+    let args: Vec<String> = ["2", "12"].iter().map(|arg| arg.to_string()).collect();
+    let args = screech::effect::Args::parse(effect.params(), &args).unwrap();
+    let input = audio(Vec::new());
+    assert!(effect
+        .build(&args, &input.metadata)
+        .apply(input)
+        .data
+        .is_empty());
+}
@@ -0,0 +1,80 @@
+use screech::effect::*;
+use screech::preset::*;
+
+fn error_line(text: &str) -> usize {
+    read_preset(text).unwrap_err().line
+}
+
+#[test]
+fn reads_chains() {
+    let chain = read_preset(
+        "# recipe\n\
+         [[effect]]\n\
+         name = \"softclip\"  # the cubic one\n\
+         iterations = 3\n\
+         amount = 0.4\n\
+         \n\
+         [[effect]]\n\
+         name = \"delayrotate\"\n\
+         delay = 20\n",
+    )
+    .unwrap();
+    assert_eq!(chain.len(), 2);
+    assert_eq!(chain[0].effect.name(), "softclip");
+    assert_eq!(chain[0].iterations, 3);
+    assert_eq!(chain[0].args.values(), [Value::Float(0.4)]);
+    // left out parameters take their defaults
+    assert_eq!(chain[1].iterations, 1);
+    assert_eq!(
+        chain[1].args.values(),
+        [Value::Int(20), Value::Float(0.5), Value::Float(1.)]
+    );
+
+    assert!(read_preset("").unwrap().is_empty());
+}
+
+#[test]
+fn round_trips() {
+    let text = "[[effect]]\nname = \"fractalize\"\niterations = 2\ndepth = 3\n\n\
+                [[effect]]\nname = \"gain\"\ngain = 1.0\n\n\
+                [[effect]]\nname = \"normalize\"\n";
+    let chain = read_preset(text).unwrap();
+    assert_eq!(write_preset(&chain), text);
+
+    let text = "[[effect]]\nname = \"softclip\"\nmix = 0.3\namount = \"lfo:saw:0.5:0.2:0.8\"\n";
+    let chain = read_preset(text).unwrap();
+    assert!(matches!(chain[0].args.values()[0], Value::Automation(_)));
+    assert_eq!(chain[0].mix, Some(Value::Float(0.3)));
+    assert_eq!(write_preset(&chain), text);
+}
+
+#[test]
+fn reports_errors() {
+    assert_eq!(error_line("name = \"fold\""), 1);
+    assert_eq!(error_line("[[effect]]\nname = fold"), 2);
+    assert_eq!(error_line("[effect]"), 1);
+    assert_eq!(error_line("[[effect]]\n\n[[effect]]\nname = \"fold\""), 1);
+    assert_eq!(error_line("[[effect]]\nname = \"nothing\""), 1);
+    assert_eq!(
+        error_line("[[effect]]\nname = \"fold\"\nname = \"fold\""),
+        3
+    );
+    assert_eq!(error_line("[[effect]]\nname = \"fold\"\namount = 1"), 3);
+    assert_eq!(error_line("[[effect]]\nname = \"gain\"\ngain = \"1\""), 3);
+    assert_eq!(
+        error_line("[[effect]]\nname = \"fractalize\"\ndepth = 1.5"),
+        3
+    );
+    assert_eq!(
+        error_line("[[effect]]\nname = \"fold\"\niterations = -1"),
+        3
+    );
+    assert_eq!(error_line("[[effect]]\nname = \"fold\"\nwhat"), 3);
+    assert_eq!(error_line("[[effect]]\nname = \"fold\"\nmix = 2.0"), 3);
+
+    let err = read_preset("[[effect]]\nname = \"delayrotate\"\nfeedback = 2").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "line 3: feedback 2 is out of range, it goes from -1 to 1"
+    );
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use screech::distort::*;
+use screech::gain::*;
+use screech::io::*;
+use screech::metadata::*;
+use screech::phase::*;
+use screech::pitch::*;
+use screech::processor::Processor;
+use screech::types::*;
+
+use std::io::Cursor;
+
+/// Makes a processor for the given channel count.
+type Maker = fn(u16) -> Box<dyn Processor>;
+
+fn metadata(channels: u16) -> AudioMetadata {
+    common::metadata(channels, 44100)
+}
+
+fn signal(frames: usize, channels: usize) -> Vec<f32> {
+    (0..frames * channels)
+        .map(|i| ((i / channels) as f32 * 0.01 * (1 + i % channels) as f32).sin() * 0.8)
+        .collect()
+}
+
+/// Processes `data` in blocks of varying sizes, the first one covering the lookahead.
+fn process_in_blocks<P: Processor>(mut processor: P, data: &mut [f32], channels: usize) {
+    let mut start = 0;
+    let mut frames = processor.lookahead().max(1);
+    while start < data.len() {
+        let end = data.len().min(start + frames * channels);
+        processor.process(&mut data[start..end]);
+        start = end;
+        frames = frames % 7 + 13;
+    }
+}
+
+#[test]
+fn blocks_match_whole_buffer() {
+    let makers: Vec<(&str, Maker)> = vec![
+        ("gain", |_| Box::new(Gain(0.5))),
+        ("dc", |_| Box::new(AddDc(0.1))),
+        ("hardclip", |_| Box::new(HardClip(0.3))),
+        ("softclip", |_| Box::new(SoftClip(0.4))),
+        ("tense", |_| Box::new(Tense(0.3))),
+        ("fold", |_| Box::new(Vec::from([Fold, Fold]))),
+        ("decimate", |_| Box::new(Decimate(4.))),
+        ("delayrotate", |channels| {
+            Box::new(DelayRotate::new(37, 0.6, 3., channels, 44100))
+        }),
+        ("delaypitch down", |channels| {
+            Box::new(DelayPitch::new(0.7, 8, channels))
+        }),
+        ("delaypitch up", |channels| {
+            Box::new(DelayPitch::new(1.5, 8, channels))
+        }),
+    ];
+    for channels in 1..=2 {
+        let data = signal(1000, channels);
+        for (name, make) in &makers {
+            let mut whole = data.clone();
+            make(channels as u16).process(&mut whole);
+            let mut blocks = data.clone();
+            process_in_blocks(make(channels as u16), &mut blocks, channels);
+            assert_eq!(whole, blocks, "{} on {} channels", name, channels);
+        }
+    }
+}
+
+#[test]
+fn delays_keep_channels_apart() {
+    // a click on the left channel only must not leak into the right one
+    let mut data = vec![0.; 400];
+    data[0] = 1.;
+    DelayRotate::new(10, 0.9, 1., 2, 44100).process(&mut data);
+    assert!(data.iter().skip(1).step_by(2).all(|&s| s == 0.));
+    assert!(data.iter().step_by(2).filter(|&&s| s != 0.).count() > 1);
+}
+
+#[test]
+fn writer_fills_in_sizes() {
+    let mut chunks = ChunkMetadata {
+        info: vec![(*b"INAM", String::from("name"))],
+        ..Default::default()
+    };
+    let audio_metadata = AudioMetadata {
+        chunks: chunks.clone(),
+        ..metadata(2)
+    };
+    // known once the input has been read to the end
+    chunks.info.push((*b"ICMT", String::from("comment")));
+    chunks.cues.push(CuePoint {
+        id: 1,
+        position: 20,
+        label: None,
+    });
+
+    let data = signal(301, 2);
+    let mut wav = WavWriter::new(
+        Cursor::new(Vec::new()),
+        &audio_metadata,
+        SampleFormat::Pcm24,
+    )
+    .unwrap();
+    for block in data.chunks(50) {
+        wav.write(block).unwrap();
+    }
+    let file = wav.finish_with_sizes(&chunks).unwrap().into_inner();
+
+    let riff_size = u32::from_le_bytes([file[4], file[5], file[6], file[7]]);
+    assert_eq!(riff_size as usize, file.len() - 8);
+    let audio = read_audio(&mut &file[..]).unwrap();
+    assert_eq!(audio.metadata.sample_format, SampleFormat::Pcm24);
+    assert_eq!(audio.data.len(), data.len());
+    assert!(audio
+        .data
+        .iter()
+        .zip(&data)
+        .all(|(a, b)| (a - b).abs() < 1e-6));
+    // the entries already in the header aren't written again
+    assert_eq!(audio.metadata.chunks.info, chunks.info);
+    assert_eq!(file.windows(4).filter(|w| w == b"INAM").count(), 1);
+    assert_eq!(audio.metadata.chunks.cues.len(), 1);
+    assert_eq!(audio.metadata.chunks.cues[0].position, 20);
+}
+
+#[test]
+fn reader_reads_blocks() {
+    let mut audio = AudioBuffer {
+        metadata: metadata(2),
+        data: signal(1000, 2),
+    };
+    audio.metadata.chunks.cues.push(CuePoint {
+        id: 1,
+        position: 5,
+        label: None,
+    });
+    let mut file = Vec::new();
+    write_wav(&mut file, &audio, SampleFormat::Float32).unwrap();
+
+    let mut wav = WavReader::new(&file[..]).unwrap();
+    assert_eq!(wav.metadata().channels, 2);
+    let mut data = Vec::new();
+    let mut reads = Vec::new();
+    loop {
+        let read = wav.read(&mut data, 300).unwrap();
+        if read == 0 {
+            break;
+        }
+        reads.push(read);
+    }
+    assert_eq!(reads, [300, 300, 300, 100]);
+    assert_eq!(data, audio.data);
+    assert_eq!(wav.finish().chunks.cues.len(), 1);
+
+    let truncated = &file[..file.len() - 100];
+    let mut wav = WavReader::new(truncated).unwrap();
+    let mut data = Vec::new();
+    assert!(matches!(
+        wav.read(&mut data, 2000),
+        Err(WavError::Truncated)
+    ));
+}
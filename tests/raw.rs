@@ -0,0 +1,85 @@
+mod common;
+
+use screech::io::*;
+use screech::types::*;
+
+#[test]
+fn parses_specs() {
+    let spec: RawSpec = "f32le:2:48000".parse().unwrap();
+    assert_eq!(spec.format.format, SampleFormat::Float32);
+    assert_eq!(spec.format.endianness, Endianness::Little);
+    assert_eq!((spec.channels, spec.sample_rate), (2, 48000));
+
+    let format: RawFormat = "s16".parse().unwrap();
+    assert_eq!(format.format, SampleFormat::Pcm16);
+    assert_eq!(format.endianness, Endianness::Little);
+    let format: RawFormat = "s24be".parse().unwrap();
+    assert_eq!(format.format, SampleFormat::Pcm24);
+    assert_eq!(format.endianness, Endianness::Big);
+
+    for bad in &[
+        "f32le",
+        "f32le:2",
+        "f32le:2:48000:1",
+        "f16:2:48000",
+        "s16:0:48000",
+        "s16:1:0",
+        "s16:x:1",
+    ] {
+        assert!(bad.parse::<RawSpec>().is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn reads_interleaved_samples() {
+    let spec: RawSpec = "s16be:2:44100".parse().unwrap();
+    // two frames and an incomplete third one
+    let bytes = [0x80, 0x00, 0x40, 0x00, 0x00, 0x00, 0xC0, 0x00, 0x12];
+    let audio = read_raw(&mut &bytes[..], &spec).unwrap();
+    assert_eq!(audio.metadata.channels, 2);
+    assert_eq!(audio.metadata.sample_rate, 44100);
+    assert_eq!(audio.metadata.sample_format, SampleFormat::Pcm16);
+    assert_eq!(audio.data, [-1., 0.5, 0., -0.5]);
+}
+
+#[test]
+fn specs_without_channels_or_rate_are_rejected() {
+    let format: RawFormat = "s16".parse().unwrap();
+    for (channels, sample_rate) in [(0, 44100), (1, 0)] {
+        let spec = RawSpec {
+            format,
+            channels,
+            sample_rate,
+        };
+        assert!(matches!(
+            read_raw(&mut &[0_u8; 4][..], &spec),
+            Err(WavError::InvalidHeader(_))
+        ));
+        let audio = common::audio(channels, sample_rate, vec![0.; 4]);
+        let error = write_raw(&mut Vec::new(), &audio, format).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+fn round_trips() {
+    let samples = [-1., -0.5, 0., 0.25, 0.5, 0.75];
+    for name in &[
+        "u8", "s16le", "s16be", "s24le", "s24be", "s32be", "f32le", "f32be", "f64be",
+    ] {
+        let spec: RawSpec = format!("{}:3:8000", name).parse().unwrap();
+        let audio = common::audio(3, 8000, samples.to_vec());
+        let mut bytes = Vec::new();
+        write_raw(&mut bytes, &audio, spec.format).unwrap();
+        assert_eq!(
+            bytes.len(),
+            samples.len() * usize::from(spec.format.format.bytes_per_sample())
+        );
+        assert_eq!(
+            read_raw(&mut &bytes[..], &spec).unwrap().data,
+            samples,
+            "{}",
+            name
+        );
+    }
+}
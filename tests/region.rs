@@ -0,0 +1,95 @@
+mod common;
+
+use screech::effect::*;
+use screech::gain::Gain;
+use screech::metadata::CuePoint;
+use screech::region::*;
+use screech::types::*;
+
+fn audio(data: Vec<f32>) -> AudioBuffer {
+    common::audio(1, 100, data)
+}
+
+#[test]
+fn parses_ranges() {
+    assert_eq!("1.5s".parse(), Ok(Time::Seconds(1.5)));
+    assert_eq!("20ms".parse(), Ok(Time::Seconds(0.02)));
+    assert_eq!("441".parse(), Ok(Time::Frames(441)));
+    assert_eq!(Time::Seconds(1.5).frames(44100), 66150);
+    assert!("-1s".parse::<Time>().is_err());
+    assert!("1.5".parse::<Time>().is_err());
+
+    let region: Region = "1.5s..4.2s".parse().unwrap();
+    assert_eq!(region.start, Time::Seconds(1.5));
+    assert_eq!(region.end, Some(Time::Seconds(4.2)));
+    assert_eq!(region.crossfade, Region::DEFAULT_CROSSFADE);
+    let region: Region = "..100".parse().unwrap();
+    assert_eq!(
+        (region.start, region.end),
+        (Time::Frames(0), Some(Time::Frames(100)))
+    );
+    assert_eq!("1s..".parse::<Region>().unwrap().end, None);
+    // units can only be compared once the sample rate is known
+    assert!("100..1s".parse::<Region>().is_ok());
+    assert!("2s..1s".parse::<Region>().is_err());
+    assert!("1s".parse::<Region>().is_err());
+}
+
+#[test]
+fn crossfades_at_the_edges() {
+    let region = Region {
+        start: Time::Frames(10),
+        end: Some(Time::Seconds(0.3)),
+        crossfade: Time::Frames(4),
+    };
+    let out = region.apply(audio(vec![1.; 40]), &mut Stage::stream(Gain(0.)));
+    assert_eq!(out.data.len(), 40);
+    assert_eq!(out.data[..10], [1.; 10]);
+    assert_eq!(out.data[10..14], [0.875, 0.625, 0.375, 0.125]);
+    assert_eq!(out.data[14..26], [0.; 12]);
+    assert_eq!(out.data[26..30], [0.125, 0.375, 0.625, 0.875]);
+    assert_eq!(out.data[30..], [1.; 10]);
+
+    // a region past the end of the file is left alone
+    let region: Region = "1s..".parse().unwrap();
+    let out = region.apply(audio(vec![1.; 40]), &mut Stage::stream(Gain(0.)));
+    assert_eq!(out.data, [1.; 40]);
+}
+
+#[test]
+fn splices_length_changes_back() {
+    let mut input = audio((0..40).map(|i| i as f32).collect());
+    for (id, position) in [(1, 5), (2, 20), (3, 35)] {
+        input.metadata.chunks.cues.push(CuePoint {
+            id,
+            position,
+            label: None,
+        });
+    }
+    let region = Region {
+        start: Time::Frames(10),
+        end: Some(Time::Frames(30)),
+        crossfade: Time::Frames(0),
+    };
+    // keeps every other frame
+    let mut halve = Stage::whole(|mut audio: AudioBuffer| {
+        audio.data = audio.data.iter().copied().step_by(2).collect();
+        audio
+    });
+    let out = region.apply(input, &mut halve);
+
+    let expected: Vec<f32> = (0..10)
+        .chain((10..30).step_by(2))
+        .chain(30..40)
+        .map(|i| i as f32)
+        .collect();
+    assert_eq!(out.data, expected);
+    let cues: Vec<u32> = out
+        .metadata
+        .chunks
+        .cues
+        .iter()
+        .map(|cue| cue.position)
+        .collect();
+    assert_eq!(cues, [5, 15, 25]);
+}
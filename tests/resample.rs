@@ -0,0 +1,105 @@
+mod common;
+
+use screech::automation::Automation;
+use screech::effect::*;
+use screech::graph::*;
+use screech::metadata::CuePoint;
+use screech::resample::*;
+use screech::types::*;
+
+fn sine(frequency: f64, sample_rate: u32, frames: usize) -> AudioBuffer {
+    let data = (0..frames)
+        .map(|i| {
+            let t = i as f64 / f64::from(sample_rate);
+            (2. * std::f64::consts::PI * frequency * t).sin() as f32
+        })
+        .collect();
+    common::audio(1, sample_rate, data)
+}
+
+/// Root mean square of the middle half of `data`, away from the edges.
+fn rms(data: &[f32]) -> f32 {
+    let middle = &data[data.len() / 4..data.len() * 3 / 4];
+    (middle.iter().map(|s| s * s).sum::<f32>() / middle.len() as f32).sqrt()
+}
+
+#[test]
+fn converts_sample_rates() {
+    let mut input = sine(1000., 44100, 4410);
+    input.metadata.chunks.cues.push(CuePoint {
+        id: 1,
+        position: 2205,
+        label: None,
+    });
+    let out = resample(input, 48000);
+    assert_eq!(out.metadata.sample_rate, 48000);
+    assert_eq!(out.data.len(), 4800);
+    assert_eq!(out.metadata.chunks.cues[0].position, 2400);
+    // the same tone, sampled at the new rate
+    let expected = sine(1000., 48000, 4800);
+    let error: Vec<f32> = out
+        .data
+        .iter()
+        .zip(&expected.data)
+        .map(|(s, e)| s - e)
+        .collect();
+    assert!(rms(&error) < 1e-3, "{}", rms(&error));
+}
+
+#[test]
+fn filters_what_would_alias() {
+    // at twice the speed, 15 kHz would fold back to 14.1 kHz
+    let tone = sine(15000., 44100, 8192);
+    let speed = Automation::Constant(2.);
+    let linear = stretch(tone.clone(), &speed, Interpolation::Linear);
+    let sinc = stretch(tone.clone(), &speed, Interpolation::Sinc);
+    assert!(rms(&linear.data) > 0.3);
+    assert!(rms(&sinc.data) < 0.01, "{}", rms(&sinc.data));
+
+    // while tones that stay under Nyquist go through
+    let tone = sine(5000., 44100, 8192);
+    let sinc = stretch(tone, &speed, Interpolation::Sinc);
+    assert!((rms(&sinc.data) - 0.5f32.sqrt()).abs() < 0.01);
+}
+
+#[test]
+fn constant_speeds_rescale_cues() {
+    let mut input = sine(1000., 44100, 300);
+    input.metadata.chunks.cues.push(CuePoint {
+        id: 1,
+        position: 31,
+        label: None,
+    });
+    let out = stretch(input, &Automation::Constant(1.5), Interpolation::Linear);
+    assert_eq!(out.data.len(), 200);
+    assert_eq!(out.metadata.chunks.cues[0].position, 21);
+}
+
+#[test]
+fn chains_at_the_new_rate() {
+    let invocation = |name: &str, args: &[&str]| {
+        let effect = find(name).unwrap();
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Invocation {
+            effect,
+            iterations: 1,
+            args: Args::parse(effect.params(), &args).unwrap(),
+            mix: None,
+        }
+    };
+    let chain = vec![Node::Effect(invocation("resample", &["22050"]))];
+    let metadata = sine(1000., 44100, 0).metadata;
+    assert_eq!(sample_rate(&chain, 44100), 22050);
+
+    let mut stage = build(&chain, &metadata, &[]).unwrap();
+    let out = stage.apply(sine(1000., 44100, 100));
+    assert_eq!((out.metadata.sample_rate, out.data.len()), (22050, 50));
+
+    let mixed = Invocation {
+        mix: Some(Value::Float(0.5)),
+        ..invocation("resample", &["22050"])
+    };
+    assert!(build(&[Node::Effect(mixed)], &metadata, &[]).is_err());
+    let branch = Node::Split(vec![(1., chain)]);
+    assert!(build(&[branch], &metadata, &[]).is_err());
+}
@@ -0,0 +1,112 @@
+use screech::io::riff::*;
+use screech::io::*;
+
+use std::io::Read;
+
+fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+    let body = chunks.concat();
+    [
+        &b"RIFF"[..],
+        &(body.len() as u32 + 4).to_le_bytes(),
+        b"WAVE",
+        &body,
+    ]
+    .concat()
+}
+
+fn fmt_chunk() -> Vec<u8> {
+    let fmt: Vec<u8> = [
+        &1_u16.to_le_bytes()[..],
+        &1_u16.to_le_bytes(),
+        &8000_u32.to_le_bytes(),
+        &8000_u32.to_le_bytes(),
+        &1_u16.to_le_bytes(),
+        &8_u16.to_le_bytes(),
+    ]
+    .concat();
+    chunk(b"fmt ", &fmt)
+}
+
+#[test]
+fn walks_chunks_with_padding() {
+    let file = riff(&[
+        chunk(b"odd ", &[1, 2, 3]),
+        chunk(b"LIST", b"INFOINAM\x01\x00\x00\x00x"),
+        chunk(b"even", &[4, 5]),
+    ]);
+    let mut chunks = Chunks::new(&file[..]).unwrap();
+    assert_eq!(chunks.form_type(), *b"WAVE");
+
+    let mut found = Vec::new();
+    while let Some(header) = chunks.next() {
+        let header = header.unwrap();
+        let mut body = Vec::new();
+        chunks.read_to_end(&mut body).unwrap();
+        assert_eq!(body.len() as u64, header.size);
+        found.push((header.id, body));
+    }
+    assert_eq!(
+        found,
+        [
+            (*b"odd ", vec![1, 2, 3]),
+            (*b"LIST", b"INFOINAM\x01\x00\x00\x00x".to_vec()),
+            (*b"even", vec![4, 5]),
+        ]
+    );
+}
+
+#[test]
+fn skips_unread_bodies() {
+    let file = riff(&[chunk(b"junk", &[0; 33]), chunk(b"last", &[])]);
+    let ids: Vec<_> = Chunks::new(&file[..])
+        .unwrap()
+        .map(|header| header.unwrap().id)
+        .collect();
+    assert_eq!(ids, [*b"junk", *b"last"]);
+}
+
+#[test]
+fn detects_truncation() {
+    let file = riff(&[chunk(b"junk", &[0; 32]), chunk(b"last", &[0; 8])]);
+    let truncated = &file[..file.len() - 4];
+    let result: Result<Vec<_>, _> = Chunks::new(truncated).unwrap().collect();
+    assert!(matches!(result, Err(WavError::Truncated)));
+
+    // cut right after a chunk, but before the end announced by the RIFF header
+    let truncated = &file[..file.len() - 16];
+    let result: Result<Vec<_>, _> = Chunks::new(truncated).unwrap().collect();
+    assert!(matches!(result, Err(WavError::Truncated)));
+}
+
+#[test]
+fn chunks_stay_inside_the_riff_body() {
+    let mut file = riff(&[chunk(b"junk", &[0; 4]), chunk(b"last", &[0; 8])]);
+    // the last chunk claims bytes that follow the RIFF chunk
+    let last = file.len() - 12;
+    file[last..last + 4].copy_from_slice(&16_u32.to_le_bytes());
+    file.extend_from_slice(&[0; 8]);
+    let result: Result<Vec<_>, _> = Chunks::new(&file[..]).unwrap().collect();
+    assert!(matches!(result, Err(WavError::Truncated)));
+}
+
+#[test]
+fn many_chunks() {
+    let mut chunks = vec![fmt_chunk()];
+    // JUNK, as unknown chunks would each be reported on stderr
+    chunks.extend((0..100_000).map(|_| chunk(b"JUNK", &[0])));
+    chunks.push(chunk(b"data", &[0, 128, 255]));
+    let file = riff(&chunks);
+
+    let audio = read_wav(&mut &file[..]).unwrap();
+    assert_eq!(audio.data, [-1., 0., 127. / 128.]);
+}
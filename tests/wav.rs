@@ -0,0 +1,448 @@
+mod common;
+
+use screech::io::*;
+use screech::types::*;
+
+fn wav_file(format: u16, channels: u16, bit_depth: u16, data: &[u8]) -> Vec<u8> {
+    let block_align = channels * bit_depth / 8;
+    let mut file = Vec::new();
+    file.extend_from_slice(b"RIFF");
+    file.extend_from_slice(&(4 + 24 + 8 + data.len() as u32).to_le_bytes());
+    file.extend_from_slice(b"WAVE");
+    file.extend_from_slice(b"fmt ");
+    file.extend_from_slice(&16_u32.to_le_bytes());
+    file.extend_from_slice(&format.to_le_bytes());
+    file.extend_from_slice(&channels.to_le_bytes());
+    file.extend_from_slice(&48000_u32.to_le_bytes());
+    file.extend_from_slice(&(48000 * u32::from(block_align)).to_le_bytes());
+    file.extend_from_slice(&block_align.to_le_bytes());
+    file.extend_from_slice(&bit_depth.to_le_bytes());
+    file.extend_from_slice(b"data");
+    file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    file.extend_from_slice(data);
+    file
+}
+
+fn read(file: &[u8]) -> Vec<f32> {
+    read_wav(&mut &file[..]).unwrap().data
+}
+
+#[test]
+fn pcm_8() {
+    let file = wav_file(1, 1, 8, &[0, 64, 128, 255]);
+    assert_eq!(read(&file), [-1., -0.5, 0., 127. / 128.]);
+}
+
+#[test]
+fn pcm_16() {
+    let data: Vec<u8> = [i16::MIN, -16384, 0, i16::MAX]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect();
+    let file = wav_file(1, 2, 16, &data);
+    assert_eq!(read(&file), [-1., -0.5, 0., 32767. / 32768.]);
+}
+
+#[test]
+fn pcm_24() {
+    let data: Vec<u8> = [-8_388_608_i32, -4_194_304, 0, 8_388_607]
+        .iter()
+        .flat_map(|s| s.to_le_bytes()[..3].to_vec())
+        .collect();
+    let file = wav_file(1, 1, 24, &data);
+    assert_eq!(read(&file), [-1., -0.5, 0., 8_388_607. / 8_388_608.]);
+}
+
+#[test]
+fn pcm_32() {
+    let data: Vec<u8> = [i32::MIN, -1_073_741_824, 0, 1 << 30]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect();
+    let file = wav_file(1, 1, 32, &data);
+    assert_eq!(read(&file), [-1., -0.5, 0., 0.5]);
+}
+
+#[test]
+fn float_32() {
+    let samples = [-1_f32, -0.25, 0., 0.75];
+    let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    let file = wav_file(3, 2, 32, &data);
+    assert_eq!(read(&file), samples);
+}
+
+#[test]
+fn float_64() {
+    let data: Vec<u8> = [-1_f64, -0.25, 0., 0.75]
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect();
+    let file = wav_file(3, 1, 64, &data);
+    assert_eq!(read(&file), [-1., -0.25, 0., 0.75]);
+}
+
+#[test]
+fn metadata() {
+    let file = wav_file(1, 2, 16, &[0; 8]);
+    let audio = read_wav(&mut &file[..]).unwrap();
+    assert_eq!(audio.metadata.channels, 2);
+    assert_eq!(audio.metadata.sample_rate, 48000);
+    assert_eq!(audio.data.len(), 4);
+}
+
+#[test]
+fn unsupported_bit_depth() {
+    let file = wav_file(1, 1, 12, &[0; 4]);
+    assert!(matches!(
+        read_wav(&mut &file[..]),
+        Err(WavError::UnsupportedBitDepth(12))
+    ));
+}
+
+#[test]
+fn malformed_headers() {
+    let error = |file: &[u8]| read_wav(&mut &file[..]).err().unwrap();
+    let file = wav_file(1, 1, 16, &[0; 4]);
+
+    let mut rifx = file.clone();
+    rifx[..4].copy_from_slice(b"RIFX");
+    assert!(matches!(error(&rifx), WavError::BadMagic(magic) if &magic == b"RIFX"));
+    let mut avi = file.clone();
+    avi[8..12].copy_from_slice(b"AVI ");
+    assert!(matches!(error(&avi), WavError::BadMagic(magic) if &magic == b"AVI "));
+
+    let mut no_fmt = file.clone();
+    no_fmt[12..16].copy_from_slice(b"JUNK");
+    assert!(matches!(error(&no_fmt), WavError::MissingFmt));
+
+    let mut no_data = file[..36].to_vec();
+    no_data[4..8].copy_from_slice(&28_u32.to_le_bytes());
+    assert!(matches!(error(&no_data), WavError::MissingData));
+
+    // MPEG layer 3
+    let mp3 = wav_file(0x55, 1, 16, &[0; 4]);
+    assert!(matches!(error(&mp3), WavError::UnsupportedCodec(0x55)));
+
+    let no_channels = wav_file(1, 0, 16, &[0; 4]);
+    assert!(matches!(error(&no_channels), WavError::InvalidHeader(_)));
+    let mut no_rate = file.clone();
+    no_rate[24..28].copy_from_slice(&0_u32.to_le_bytes());
+    assert!(matches!(error(&no_rate), WavError::InvalidHeader(_)));
+}
+
+#[test]
+fn extensible() {
+    let data: Vec<u8> = [i32::MIN, 0, 0, 1 << 30, 0, 0]
+        .iter()
+        .flat_map(|s| s.to_le_bytes()[1..].to_vec())
+        .collect();
+    let mut file = wav_file(0xFFFE, 3, 24, &data);
+    // grow the fmt chunk to hold the extension
+    let extension = [
+        &22_u16.to_le_bytes()[..],
+        &20_u16.to_le_bytes(),
+        &0b1011_u32.to_le_bytes(),
+        &[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00],
+        &[0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71],
+    ]
+    .concat();
+    file.splice(36..36, extension);
+    file[16..20].copy_from_slice(&40_u32.to_le_bytes());
+    let riff_size = file.len() as u32 - 8;
+    file[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+    let audio = read_wav(&mut &file[..]).unwrap();
+    assert_eq!(audio.metadata.channels, 3);
+    assert_eq!(audio.metadata.valid_bits_per_sample, Some(20));
+    assert_eq!(audio.metadata.channel_mask, Some(0b1011));
+    assert_eq!(audio.data, [-1., 0., 0., 0.5, 0., 0.]);
+}
+
+#[test]
+fn valid_bits_only_kept_for_the_same_format() {
+    let valid_bits = |input: SampleFormat, output: SampleFormat| {
+        let mut audio = common::audio(2, 44100, vec![0.; 4]);
+        audio.metadata.sample_format = input;
+        audio.metadata.valid_bits_per_sample = Some(24);
+        audio.metadata.channel_mask = Some(0b11);
+        let mut file = Vec::new();
+        write_wav(&mut file, &audio, output).unwrap();
+        read_wav(&mut &file[..])
+            .unwrap()
+            .metadata
+            .valid_bits_per_sample
+    };
+    assert_eq!(
+        valid_bits(SampleFormat::Pcm32, SampleFormat::Pcm32),
+        Some(24)
+    );
+    assert_eq!(
+        valid_bits(SampleFormat::Pcm24, SampleFormat::Pcm32),
+        Some(32)
+    );
+    assert_eq!(
+        valid_bits(SampleFormat::Pcm32, SampleFormat::Float32),
+        Some(32)
+    );
+    assert_eq!(
+        valid_bits(SampleFormat::Float32, SampleFormat::Float32),
+        Some(32)
+    );
+}
+
+fn stereo_metadata() -> AudioMetadata {
+    common::metadata(2, 44100)
+}
+
+fn write_read(samples: &[f32], format: SampleFormat) -> AudioBuffer {
+    let audio = AudioBuffer {
+        metadata: stereo_metadata(),
+        data: samples.to_vec(),
+    };
+    let mut file = Vec::new();
+    write_wav(&mut file, &audio, format).unwrap();
+    read_wav(&mut &file[..]).unwrap()
+}
+
+#[test]
+fn write_formats() {
+    let samples = [-1., -0.5, 0., 0.25, 0.5, 0.75];
+    for &format in &[
+        SampleFormat::Pcm8,
+        SampleFormat::Pcm16,
+        SampleFormat::Pcm24,
+        SampleFormat::Pcm32,
+        SampleFormat::Float32,
+        SampleFormat::Float64,
+    ] {
+        let audio = write_read(&samples, format);
+        assert_eq!(audio.metadata.sample_format, format);
+        assert_eq!(audio.data, samples);
+    }
+}
+
+#[test]
+fn round_trips_the_wider_depths() {
+    // every 8 bit value
+    let samples: Vec<f32> = (-128..128).map(|i| i as f32 / 128.).collect();
+    assert_eq!(write_read(&samples, SampleFormat::Pcm8).data, samples);
+
+    // 32 bit values down to the least significant bit, and as close to 1 as a float gets
+    let lsb = 1. / 2_147_483_648.;
+    let samples = [
+        -1.,
+        lsb,
+        -lsb,
+        3. * lsb,
+        1. - f32::EPSILON / 2.,
+        -0.123_456_79,
+    ];
+    assert_eq!(write_read(&samples, SampleFormat::Pcm32).data, samples);
+
+    // floats are kept as they are, beyond full scale included
+    let samples = [1e-40, -0., 2.5, -7.25, 0.1, f32::MIN_POSITIVE];
+    let audio = write_read(&samples, SampleFormat::Float64);
+    assert_eq!(audio.data, samples);
+    assert!(audio.data[1].is_sign_negative());
+}
+
+#[test]
+fn too_wide_frames_are_rejected() {
+    let write = |channels, sample_rate, format| {
+        let audio = common::audio(channels, sample_rate, Vec::new());
+        write_wav(&mut Vec::new(), &audio, format).map_err(|e| e.kind())
+    };
+    assert_eq!(write(8, 192000, SampleFormat::Float64), Ok(()));
+    // a byte rate over 4GB/s and a block align over 64KiB
+    assert_eq!(
+        write(8, 100_000_000, SampleFormat::Float64),
+        Err(std::io::ErrorKind::InvalidInput)
+    );
+    assert_eq!(
+        write(9000, 44100, SampleFormat::Float64),
+        Err(std::io::ErrorKind::InvalidInput)
+    );
+}
+
+#[test]
+fn write_clips_and_rounds() {
+    let lsb = 1. / 32768.;
+    let audio = write_read(
+        &[1.5, -1.5, 1., 0.4 * lsb, 0.6 * lsb, -0.6 * lsb],
+        SampleFormat::Pcm16,
+    );
+    assert_eq!(audio.data, [1. - lsb, -1., 1. - lsb, 0., lsb, -lsb]);
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    let mut arr = [0; 8];
+    arr.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(arr)
+}
+
+#[test]
+fn rf64_header() {
+    // a little over 4GiB of data, only the header is actually written
+    let samples = (1 << 30) + 2;
+    let mut header = Vec::new();
+    write_wav_header(
+        &mut header,
+        &stereo_metadata(),
+        SampleFormat::Float32,
+        samples,
+    )
+    .unwrap();
+
+    assert_eq!(&header[0..4], b"RF64");
+    assert_eq!(&header[4..8], &u32::MAX.to_le_bytes());
+    assert_eq!(&header[12..16], b"ds64");
+    let data_size = 4 * samples;
+    assert_eq!(u64_at(&header, 20), header.len() as u64 - 8 + data_size);
+    assert_eq!(u64_at(&header, 28), data_size);
+    assert_eq!(u64_at(&header, 36), samples / 2);
+    assert_eq!(&header[header.len() - 8..header.len() - 4], b"data");
+    assert_eq!(&header[header.len() - 4..], &u32::MAX.to_le_bytes());
+
+    assert!(matches!(
+        read_wav(&mut &header[..]),
+        Err(WavError::Truncated)
+    ));
+}
+
+#[test]
+fn rf64_read() {
+    let samples = [-1_f32, -0.25, 0., 0.75];
+    let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    for &magic in &[b"RF64", b"BW64"] {
+        let mut file = wav_file(3, 2, 32, &data);
+        file[0..4].copy_from_slice(magic);
+        file[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        file[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+        let ds64 = [
+            &b"ds64"[..],
+            &28_u32.to_le_bytes(),
+            &(file.len() as u64 + 28).to_le_bytes(),
+            &(data.len() as u64).to_le_bytes(),
+            &2_u64.to_le_bytes(),
+            &0_u32.to_le_bytes(),
+        ]
+        .concat();
+        file.splice(12..12, ds64);
+        assert_eq!(read(&file), samples);
+    }
+}
+
+fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+fn u32s(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+#[test]
+fn metadata_chunks() {
+    let mut file = wav_file(1, 1, 16, &[0; 200]);
+    let cue = [u32s(&[1, 1, 20]), b"data".to_vec(), u32s(&[0, 0, 20])].concat();
+    let labl = [u32s(&[1]), b"hit\0".to_vec()].concat();
+    let adtl = [b"adtl".to_vec(), chunk(b"labl", &labl)].concat();
+    let info = [b"INFO".to_vec(), chunk(b"INAM", b"name\0")].concat();
+    let smpl = u32s(&[0, 0, 22675, 60, 0, 0, 0, 1, 0, 7, 0, 10, 90, 0, 0]);
+    let file_chunks = [
+        chunk(b"LIST", &adtl),
+        chunk(b"cue ", &cue),
+        chunk(b"LIST", &info),
+        chunk(b"smpl", &smpl),
+        chunk(b"inst", &[60, 0, 0, 0, 127, 1, 127]),
+    ];
+    for c in &file_chunks {
+        file.extend_from_slice(c);
+    }
+    let riff_size = file.len() as u32 - 8;
+    file[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+    let audio = read_wav(&mut &file[..]).unwrap();
+    let chunks = &audio.metadata.chunks;
+    assert_eq!(chunks.cues.len(), 1);
+    assert_eq!(chunks.cues[0].position, 20);
+    assert_eq!(chunks.cues[0].label.as_deref(), Some("hit"));
+    assert_eq!(chunks.info, [(*b"INAM", String::from("name"))]);
+    let sampler = chunks.sampler.as_ref().unwrap();
+    assert_eq!((sampler.loops[0].start, sampler.loops[0].end), (10, 90));
+    assert_eq!(chunks.instrument.as_ref().unwrap().high_note, 127);
+
+    let mut written = Vec::new();
+    write_wav(&mut written, &audio, SampleFormat::Pcm16).unwrap();
+    let reread = read_wav(&mut &written[..]).unwrap();
+    assert_eq!(&reread.metadata.chunks, chunks);
+    assert_eq!(reread.data, audio.data);
+
+    let faster = screech::pitch::speed(reread, 2.);
+    let chunks = &faster.metadata.chunks;
+    assert_eq!(chunks.cues[0].position, 10);
+    let sampler = chunks.sampler.as_ref().unwrap();
+    assert_eq!((sampler.loops[0].start, sampler.loops[0].end), (5, 45));
+}
+
+#[test]
+fn sampler_loop_count_is_bounded_by_the_chunk() {
+    let mut file = wav_file(1, 1, 16, &[0; 200]);
+    let smpl = u32s(&[0, 0, 22675, 60, 0, 0, 0, u32::MAX, 0, 7, 0, 10, 90, 0, 0]);
+    file.extend_from_slice(&chunk(b"smpl", &smpl));
+    let riff_size = file.len() as u32 - 8;
+    file[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+    let audio = read_wav(&mut &file[..]).unwrap();
+    let sampler = audio.metadata.chunks.sampler.unwrap();
+    assert_eq!(sampler.loops.len(), 1);
+}
+
+/// Hands out one byte per read, like a slow pipe.
+struct Trickle<'a>(&'a [u8]);
+
+impl std::io::Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.0.is_empty() || buf.is_empty() {
+            return Ok(0);
+        }
+        buf[0] = self.0[0];
+        self.0 = &self.0[1..];
+        Ok(1)
+    }
+}
+
+#[test]
+fn streaming_header() {
+    let samples = [-1_f32, -0.25, 0., 0.75, 0.5];
+    let mut file = Vec::new();
+    write_streaming_wav_header(&mut file, &stereo_metadata(), SampleFormat::Float32).unwrap();
+    assert_eq!(&file[4..8], &u32::MAX.to_le_bytes());
+    assert_eq!(&file[file.len() - 4..], &u32::MAX.to_le_bytes());
+    // a trailing incomplete frame is dropped
+    for s in &samples {
+        file.extend_from_slice(&s.to_le_bytes());
+    }
+
+    let audio = read_audio(&mut Trickle(&file)).unwrap();
+    assert_eq!(audio.data, samples[..4]);
+}
+
+#[test]
+fn unknown_sizes() {
+    let samples = [-1_f32, -0.25, 0., 0.75];
+    let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    let mut file = wav_file(3, 2, 32, &data);
+    file[4..8].copy_from_slice(&0_u32.to_le_bytes());
+    file[40..44].copy_from_slice(&0_u32.to_le_bytes());
+    assert_eq!(read(&file), samples);
+
+    // a known RIFF size means an empty data chunk really is empty
+    let file = wav_file(3, 2, 32, &[]);
+    assert!(read(&file).is_empty());
+}